@@ -0,0 +1,158 @@
+use std::{
+    alloc::{self, Layout},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    slice,
+};
+
+use super::IoBuf;
+
+/// The alignment most devices require of `O_DIRECT` buffers, offsets and lengths.
+pub const DIRECT_ALIGN: usize = 4096;
+
+/// A fixed-capacity buffer whose start and capacity are aligned, as required of buffers read into
+/// and written from files opened with [`OpenOptions::direct`](super::OpenOptions::direct).
+///
+/// The offsets and lengths of direct IO must also be aligned, usually to the device's logical
+/// block size, so direct reads should be into buffers with no bytes filled.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+// SAFETY: the buffer owns its allocation, like a `Vec<u8>`
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    /// Creates an empty buffer aligned to `align`, with room for at least `capacity` bytes,
+    /// rounded up to a multiple of `align`.
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two, or the capacity overflows.
+    pub fn new(capacity: usize, align: usize) -> Self {
+        let capacity = capacity.max(1).next_multiple_of(align);
+        let layout = Layout::from_size_align(capacity, align).expect("invalid buffer layout");
+
+        // SAFETY: the layout's size is non-zero
+        let ptr = unsafe { alloc::alloc(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+
+        Self {
+            ptr,
+            len: 0,
+            layout,
+        }
+    }
+
+    /// Creates an empty buffer aligned to [`DIRECT_ALIGN`].
+    pub fn direct(capacity: usize) -> Self {
+        Self::new(capacity, DIRECT_ALIGN)
+    }
+
+    /// The alignment of the buffer's start and capacity.
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    /// The number of bytes the buffer has room for.
+    pub fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// Appends as much of `data` as there is room for, returning the number of bytes appended.
+    pub fn extend_from_slice(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.capacity() - self.len);
+        // SAFETY: `n` bytes fit in the spare capacity, which does not overlap `data`
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.as_ptr().add(self.len), n) };
+        self.len += n;
+
+        n
+    }
+
+    /// Shortens the buffer to `len` bytes, if it is longer.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// Empties the buffer, keeping its capacity.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the first `len` bytes are initialized
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the first `len` bytes are initialized
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity())
+            .field("align", &self.align())
+            .finish()
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        // SAFETY: allocated with the same layout
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) };
+    }
+}
+
+// SAFETY: the allocation is on the heap, and does not move with the buffer
+unsafe impl IoBuf for AlignedBuf {
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        self.layout.size()
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        self.len = len;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AlignedBuf, DIRECT_ALIGN};
+
+    #[test]
+    fn aligns_start_and_capacity() {
+        let mut buf = AlignedBuf::direct(5000);
+        assert_eq!(buf.as_ptr() as usize % DIRECT_ALIGN, 0);
+        assert_eq!(buf.capacity(), 2 * DIRECT_ALIGN);
+
+        assert_eq!(buf.extend_from_slice(&[1; 10_000]), 2 * DIRECT_ALIGN);
+        buf.truncate(3);
+        assert_eq!(&*buf, &[1, 1, 1]);
+    }
+}
@@ -0,0 +1,642 @@
+//! Files whose IO is submitted to the current runtime's ring, so reading and persisting them does
+//! not block the runtime's thread.
+//!
+//! Like [`runtime::net`](crate::runtime::net), buffers are passed to operations by value, and
+//! handed back alongside the result. Reads and writes are positional, so a file may be shared by
+//! several tasks without a cursor to coordinate.
+//!
+//! ```no_run
+//! # use rask_core::{fs::File, runtime::Runtime};
+//! let rt = Runtime::new().unwrap();
+//! rt.block_on(async {
+//!     let file = File::create("/tmp/greeting").await.unwrap();
+//!     let (res, _) = file.write_all_at(b"hello".to_vec(), 0).await;
+//!     res.unwrap();
+//!     file.sync_all().await.unwrap();
+//!
+//!     let (res, contents) = file.read_to_end(Vec::new()).await;
+//!     res.unwrap();
+//!     assert_eq!(contents, b"hello");
+//! });
+//! ```
+
+mod aligned;
+pub use aligned::*;
+
+use std::{
+    ffi::{CStr, CString},
+    fmt, io,
+    os::{
+        fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    slice,
+    time::{Duration, SystemTime},
+};
+
+use crate::runtime::{
+    net::{close_orphaned, result},
+    Op,
+};
+
+/// How much a buffer grows by when reading a file of unknown size.
+const READ_CHUNK: usize = 32 * 1024;
+
+/// A buffer which can be handed to the kernel, i.e. a `Vec<u8>` or an [`AlignedBuf`].
+///
+/// Reads fill the spare capacity after the buffer's length, and writes send its first `len`
+/// bytes.
+///
+/// # Safety
+/// The memory the buffer refers to must not move when the buffer is moved, and must remain valid
+/// until it is dropped. `capacity` bytes must be writable from `as_mut_ptr`, of which the first
+/// `len` are initialized.
+pub unsafe trait IoBuf: 'static {
+    /// A pointer to the start of the buffer.
+    fn as_ptr(&self) -> *const u8;
+
+    /// A mutable pointer to the start of the buffer.
+    fn as_mut_ptr(&mut self) -> *mut u8;
+
+    /// The number of initialized bytes.
+    fn len(&self) -> usize;
+
+    /// Whether no bytes are initialized.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of bytes the buffer has room for.
+    fn capacity(&self) -> usize;
+
+    /// Sets the number of initialized bytes.
+    ///
+    /// # Safety
+    /// `len` must not exceed the capacity, and that many bytes must be initialized.
+    unsafe fn set_len(&mut self, len: usize);
+}
+
+// SAFETY: a vector's elements are on the heap, and only move when it reallocates, which requires
+// a mutable borrow
+unsafe impl IoBuf for Vec<u8> {
+    fn as_ptr(&self) -> *const u8 {
+        Vec::as_ptr(self)
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        Vec::as_mut_ptr(self)
+    }
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    unsafe fn set_len(&mut self, len: usize) {
+        Vec::set_len(self, len)
+    }
+}
+
+/// Options for opening a file, mirroring [`std::fs::OpenOptions`].
+///
+/// ```no_run
+/// # use rask_core::{fs::OpenOptions, runtime::Runtime};
+/// # let rt = Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// let file = OpenOptions::new()
+///     .read(true)
+///     .write(true)
+///     .create(true)
+///     .direct(true)
+///     .open("/var/lib/rask/cache")
+///     .await
+///     .unwrap();
+/// # });
+/// ```
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+    direct: bool,
+    mode: libc::mode_t,
+    custom_flags: i32,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+            direct: false,
+            mode: 0o666,
+            custom_flags: 0,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Creates a set of options, with no access requested.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the file for reading.
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    /// Open the file for writing.
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    /// Open the file for appending, which implies writing.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    /// Truncate the file to be empty once opened.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Create the file if it does not exist.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing if it already exists.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Open the file with `O_DIRECT`, bypassing the page cache. Buffers, offsets and lengths must
+    /// then be aligned, i.e. by using an [`AlignedBuf`].
+    ///
+    /// See [open(2)](https://man.archlinux.org/man/open.2)
+    pub fn direct(&mut self, direct: bool) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    /// Sets the permissions a created file has, before the umask is applied. Defaults to `0o666`.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = mode as libc::mode_t;
+        self
+    }
+
+    /// Sets additional flags to open the file with.
+    pub fn custom_flags(&mut self, flags: i32) -> &mut Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    /// Opens the file at `path` with these options.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](crate::runtime::Runtime).
+    pub async fn open(&self, path: impl AsRef<Path>) -> io::Result<File> {
+        let flags = self.flags()?;
+        let mode = self.mode;
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte"))?;
+
+        // SAFETY: the path is on the heap, owned by the operation
+        let (cqe, _) = unsafe {
+            Op::submit(path, |path, sqe| {
+                sqe.prep_openat(libc::AT_FDCWD, path, flags, mode);
+            })
+        }?
+        .on_orphaned(close_orphaned(false))
+        .await;
+
+        let fd = result(cqe)?;
+        // SAFETY: the descriptor was just opened, and is owned by nothing else
+        Ok(File {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    fn flags(&self) -> io::Result<i32> {
+        let mut flags = match (self.read, self.write || self.append) {
+            (true, false) => libc::O_RDONLY,
+            (false, true) => libc::O_WRONLY,
+            (true, true) => libc::O_RDWR,
+            (false, false) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "file must be opened for reading or writing",
+                ))
+            }
+        };
+
+        if self.create_new {
+            flags |= libc::O_CREAT | libc::O_EXCL;
+        } else if self.create {
+            flags |= libc::O_CREAT;
+        }
+        if self.truncate {
+            flags |= libc::O_TRUNC;
+        }
+        if self.append {
+            flags |= libc::O_APPEND;
+        }
+        if self.direct {
+            flags |= libc::O_DIRECT;
+        }
+
+        Ok(flags | libc::O_CLOEXEC | self.custom_flags)
+    }
+}
+
+/// An open file.
+///
+/// Dropping the file closes it. Use [`File::close`] to wait for it to be closed, and observe any
+/// error.
+#[derive(Debug)]
+pub struct File {
+    fd: OwnedFd,
+}
+
+impl File {
+    /// Opens the file at `path` for reading.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new().read(true).open(path).await
+    }
+
+    /// Opens the file at `path` for reading and writing, creating it if it does not exist, and
+    /// truncating it if it does.
+    pub async fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await
+    }
+
+    /// Creates a set of options to open a file with.
+    pub fn options() -> OpenOptions {
+        OpenOptions::new()
+    }
+
+    /// Wraps a file opened elsewhere.
+    pub fn from_std(file: std::fs::File) -> Self {
+        Self { fd: file.into() }
+    }
+
+    /// Reads from `offset` into the spare capacity of `buf`, returning the number of bytes read,
+    /// which are appended to `buf`. Returns 0 at the end of the file, or if `buf` has no spare
+    /// capacity.
+    ///
+    /// See [pread(2)](https://man.archlinux.org/man/pread.2)
+    pub async fn read_at<B: IoBuf>(&self, buf: B, offset: u64) -> (io::Result<usize>, B) {
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: the buffer's memory does not move with it, and it is owned by the operation
+        let op = unsafe {
+            Op::try_submit(buf, |buf, sqe| {
+                let spare = slice::from_raw_parts_mut(
+                    buf.as_mut_ptr().add(buf.len()),
+                    buf.capacity() - buf.len(),
+                );
+                sqe.prep_read(fd, spare, offset);
+            })
+        };
+        let (cqe, mut buf) = match op {
+            Ok(op) => op.await,
+            Err((e, buf)) => return (Err(e), buf),
+        };
+
+        match result(cqe) {
+            Ok(n) => {
+                // SAFETY: the kernel initialized `n` bytes of the spare capacity
+                unsafe { buf.set_len(buf.len() + n as usize) };
+                (Ok(n as usize), buf)
+            }
+            Err(e) => (Err(e), buf),
+        }
+    }
+
+    /// Writes `buf` at `offset`, returning the number of bytes written.
+    ///
+    /// See [pwrite(2)](https://man.archlinux.org/man/pwrite.2)
+    pub async fn write_at<B: IoBuf>(&self, buf: B, offset: u64) -> (io::Result<usize>, B) {
+        self.write_from(buf, 0, offset).await
+    }
+
+    /// Writes all of `buf` at `offset`, submitting further writes until it has been written in
+    /// full.
+    pub async fn write_all_at<B: IoBuf>(&self, mut buf: B, offset: u64) -> (io::Result<()>, B) {
+        let mut written = 0;
+
+        while written < buf.len() {
+            let res;
+            (res, buf) = self.write_from(buf, written, offset + written as u64).await;
+            match res {
+                Ok(0) => return (Err(io::ErrorKind::WriteZero.into()), buf),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return (Err(e), buf),
+            }
+        }
+
+        (Ok(()), buf)
+    }
+
+    /// Writes `buf` from its byte `start` at `offset`.
+    async fn write_from<B: IoBuf>(
+        &self,
+        buf: B,
+        start: usize,
+        offset: u64,
+    ) -> (io::Result<usize>, B) {
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: the buffer's memory does not move with it, and it is owned by the operation
+        let op = unsafe {
+            Op::try_submit(buf, |buf, sqe| {
+                let data = slice::from_raw_parts(buf.as_ptr().add(start), buf.len() - start);
+                sqe.prep_write(fd, data, offset);
+            })
+        };
+
+        match op {
+            Ok(op) => {
+                let (cqe, buf) = op.await;
+                (result(cqe).map(|n| n as usize), buf)
+            }
+            Err((e, buf)) => (Err(e), buf),
+        }
+    }
+
+    /// Reads the whole file, from its start, appending it to `buf` and returning the number of
+    /// bytes read.
+    pub async fn read_to_end(&self, mut buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        // The size is only a hint, as the file may change while it is read
+        if let Ok(metadata) = self.metadata().await {
+            buf.reserve(metadata.len() as usize);
+        }
+
+        let mut read = 0;
+        loop {
+            if buf.len() == buf.capacity() {
+                buf.reserve(READ_CHUNK);
+            }
+
+            let res;
+            (res, buf) = self.read_at(buf, read as u64).await;
+            match res {
+                Ok(0) => return (Ok(read), buf),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return (Err(e), buf),
+            }
+        }
+    }
+
+    /// Queries the file's metadata.
+    ///
+    /// See [statx(2)](https://man.archlinux.org/man/statx.2)
+    pub async fn metadata(&self) -> io::Result<Metadata> {
+        const EMPTY: &CStr = c"";
+
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: statx is plain data, for which zero is a valid value
+        let buf: Box<libc::statx> = Box::new(unsafe { std::mem::zeroed() });
+
+        // SAFETY: the path is static, and the buffer is on the heap, owned by the operation
+        let (cqe, buf) = unsafe {
+            Op::submit(buf, |buf, sqe| {
+                sqe.prep_statx(
+                    fd,
+                    EMPTY,
+                    libc::AT_EMPTY_PATH,
+                    libc::STATX_BASIC_STATS | libc::STATX_BTIME,
+                    buf,
+                );
+            })
+        }?
+        .await;
+
+        result(cqe)?;
+        Ok(Metadata { statx: *buf })
+    }
+
+    /// Flushes the file's data and metadata to the device.
+    ///
+    /// See [fsync(2)](https://man.archlinux.org/man/fsync.2)
+    pub async fn sync_all(&self) -> io::Result<()> {
+        self.fsync(false).await
+    }
+
+    /// Flushes the file's data to the device, along with only the metadata needed to read it.
+    ///
+    /// See [fdatasync(2)](https://man.archlinux.org/man/fdatasync.2)
+    pub async fn sync_data(&self) -> io::Result<()> {
+        self.fsync(true).await
+    }
+
+    async fn fsync(&self, data_only: bool) -> io::Result<()> {
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: the request references no memory
+        let (cqe, ()) = unsafe {
+            Op::submit((), |_, sqe| {
+                sqe.prep_fsync(fd, data_only);
+            })
+        }?
+        .await;
+
+        result(cqe).map(|_| ())
+    }
+
+    /// Manipulates the space allocated to the file in the range of `len` bytes from `offset`.
+    /// With a `mode` of 0, the range is allocated, extending the file if need be, so later writes
+    /// to it cannot fail for lack of space.
+    ///
+    /// See [fallocate(2)](https://man.archlinux.org/man/fallocate.2)
+    pub async fn fallocate(&self, mode: i32, offset: u64, len: u64) -> io::Result<()> {
+        let fd = self.fd.as_raw_fd();
+        // SAFETY: the request references no memory
+        let (cqe, ()) = unsafe {
+            Op::submit((), |_, sqe| {
+                sqe.prep_fallocate(fd, mode, offset, len);
+            })
+        }?
+        .await;
+
+        result(cqe).map(|_| ())
+    }
+
+    /// Closes the file, waiting for the close to complete.
+    ///
+    /// See [close(2)](https://man.archlinux.org/man/close.2)
+    pub async fn close(self) -> io::Result<()> {
+        let fd = self.fd.as_raw_fd();
+
+        // SAFETY: the request references no memory
+        let op = unsafe {
+            Op::submit((), |_, sqe| {
+                sqe.prep_close(fd);
+            })
+        };
+        // Only once the close is queued does it own the descriptor, which is otherwise closed
+        // when the file is dropped
+        let op = op?;
+        let _ = self.fd.into_raw_fd();
+
+        let (cqe, ()) = op.await;
+        result(cqe).map(|_| ())
+    }
+}
+
+impl AsRawFd for File {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Reads the whole file at `path`.
+pub async fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    let file = File::open(path).await?;
+    let (res, buf) = file.read_to_end(Vec::new()).await;
+    res?;
+
+    Ok(buf)
+}
+
+/// Metadata about a file, returned by [`File::metadata`].
+#[derive(Clone, Copy)]
+pub struct Metadata {
+    statx: libc::statx,
+}
+
+impl Metadata {
+    /// The size of the file, in bytes.
+    pub fn len(&self) -> u64 {
+        self.statx.stx_size
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the file is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type() == libc::S_IFREG
+    }
+
+    /// Whether the file is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == libc::S_IFDIR
+    }
+
+    /// The file's permission bits.
+    pub fn mode(&self) -> u32 {
+        u32::from(self.statx.stx_mode) & 0o7777
+    }
+
+    /// The preferred size of IO on the file, in bytes.
+    pub fn block_size(&self) -> u32 {
+        self.statx.stx_blksize
+    }
+
+    /// When the file was last modified.
+    pub fn modified(&self) -> SystemTime {
+        to_system_time(self.statx.stx_mtime)
+    }
+
+    /// When the file was last accessed.
+    pub fn accessed(&self) -> SystemTime {
+        to_system_time(self.statx.stx_atime)
+    }
+
+    /// When the file was created, if the filesystem records it.
+    pub fn created(&self) -> Option<SystemTime> {
+        (self.statx.stx_mask & libc::STATX_BTIME != 0).then(|| to_system_time(self.statx.stx_btime))
+    }
+
+    fn file_type(&self) -> libc::mode_t {
+        libc::mode_t::from(self.statx.stx_mode) & libc::S_IFMT
+    }
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metadata")
+            .field("len", &self.len())
+            .field("mode", &format_args!("{:o}", self.mode()))
+            .field("modified", &self.modified())
+            .finish_non_exhaustive()
+    }
+}
+
+fn to_system_time(ts: libc::statx_timestamp) -> SystemTime {
+    let since = Duration::new(ts.tv_sec.unsigned_abs(), ts.tv_nsec);
+    if ts.tv_sec >= 0 {
+        SystemTime::UNIX_EPOCH + since
+    } else {
+        SystemTime::UNIX_EPOCH - since
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, process};
+
+    use super::{read, File, OpenOptions};
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn writes_reads_and_persists() {
+        let path = env::temp_dir().join(format!("rask-{}-fs", process::id()));
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let file = File::create(&path).await.unwrap();
+            file.fallocate(0, 0, 8192).await.unwrap();
+            assert_eq!(file.metadata().await.unwrap().len(), 8192);
+
+            let (res, _) = file.write_all_at(b"hello world".to_vec(), 0).await;
+            res.unwrap();
+            file.sync_all().await.unwrap();
+
+            let (res, buf) = file.read_at(Vec::with_capacity(5), 6).await;
+            assert_eq!(res.unwrap(), 5);
+            assert_eq!(buf, b"world");
+            file.close().await.unwrap();
+
+            let contents = read(&path).await.unwrap();
+            assert_eq!(contents.len(), 8192);
+            assert_eq!(&contents[..11], b"hello world");
+
+            let err = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        });
+
+        fs::remove_file(path).unwrap();
+    }
+}
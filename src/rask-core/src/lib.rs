@@ -1 +1,5 @@
+#[cfg(target_os = "linux")]
+pub mod fs;
+#[cfg(target_os = "linux")]
+pub mod runtime;
 pub mod sys;
@@ -0,0 +1,307 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+/// The number of threads the global pool runs blocking work on at once.
+const MAX_THREADS: usize = 64;
+
+/// How long a thread waits for more work before exiting.
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs `f` on the global [`BlockingPool`], for work which would otherwise block the runtime's
+/// thread, i.e. resolving names with `getaddrinfo`, compression, or calling into libraries which
+/// perform blocking IO.
+///
+/// The returned [`Blocking`] resolves to the output of `f` once it has run. Its task is woken
+/// through the runtime's ring, so resumes on the runtime's thread.
+///
+/// ```no_run
+/// # use rask_core::runtime::{self, Runtime};
+/// # use std::net::ToSocketAddrs;
+/// let rt = Runtime::new().unwrap();
+/// let addrs = rt.block_on(runtime::spawn_blocking(|| {
+///     ("example.com", 443).to_socket_addrs().map(Vec::from_iter)
+/// }));
+/// ```
+pub fn spawn_blocking<F, T>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    BlockingPool::global().spawn(f)
+}
+
+/// A bounded pool of threads running blocking work, shared by every runtime.
+///
+/// Threads are started as work is spawned, up to the pool's limit, beyond which work is queued
+/// until a thread is free. Threads exit once they have been idle for a while.
+#[derive(Debug, Clone)]
+pub struct BlockingPool {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    max_threads: usize,
+}
+
+struct State {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    /// The number of threads waiting for work.
+    idle: usize,
+    /// The number of idle threads which have been notified of work, and not yet woken.
+    notified: usize,
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("State")
+            .field("jobs", &self.jobs.len())
+            .field("threads", &self.threads)
+            .field("idle", &self.idle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BlockingPool {
+    /// Creates a pool running at most `max_threads` at once.
+    ///
+    /// # Panics
+    /// Panics if `max_threads` is zero.
+    pub fn new(max_threads: usize) -> Self {
+        assert!(
+            max_threads > 0,
+            "blocking pool must have at least one thread"
+        );
+
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State {
+                    jobs: VecDeque::new(),
+                    threads: 0,
+                    idle: 0,
+                    notified: 0,
+                }),
+                condvar: Condvar::new(),
+                max_threads,
+            }),
+        }
+    }
+
+    /// The pool used by [`spawn_blocking`], running at most 64 threads at once.
+    pub fn global() -> &'static BlockingPool {
+        static GLOBAL: OnceLock<BlockingPool> = OnceLock::new();
+        GLOBAL.get_or_init(|| BlockingPool::new(MAX_THREADS))
+    }
+
+    /// Runs `f` on the pool, returning a future which resolves to its output.
+    ///
+    /// # Panics
+    /// Panics if no thread is running and one cannot be started.
+    pub fn spawn<F, T>(&self, f: F) -> Blocking<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            output: None,
+            waker: None,
+        }));
+
+        let job_slot = slot.clone();
+        let job = Box::new(move || {
+            let output = panic::catch_unwind(AssertUnwindSafe(f));
+
+            let mut slot = job_slot.lock().unwrap();
+            slot.output = Some(output);
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        });
+
+        let mut state = self.shared.state.lock().unwrap();
+        state.jobs.push_back(job);
+        if state.idle > 0 {
+            state.idle -= 1;
+            state.notified += 1;
+            self.shared.condvar.notify_one();
+        } else if state.threads < self.shared.max_threads {
+            let shared = self.shared.clone();
+            let spawned = thread::Builder::new()
+                .name("rask-blocking".into())
+                .spawn(move || shared.run());
+
+            match spawned {
+                Ok(_) => state.threads += 1,
+                // Running threads pick the work up once they are free
+                Err(e) if state.threads == 0 => panic!("failed to start a blocking thread: {e}"),
+                Err(_) => {}
+            }
+        }
+
+        Blocking { slot }
+    }
+}
+
+impl Shared {
+    /// Runs queued work until none arrives for [`KEEP_ALIVE`].
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock().unwrap();
+                continue;
+            }
+
+            state.idle += 1;
+            loop {
+                let (guard, res) = self.condvar.wait_timeout(state, KEEP_ALIVE).unwrap();
+                state = guard;
+
+                // The thread notifying it has already counted it as no longer idle
+                if state.notified > 0 {
+                    state.notified -= 1;
+                    break;
+                }
+                if res.timed_out() {
+                    state.idle -= 1;
+                    state.threads -= 1;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+struct Slot<T> {
+    output: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// The output of work run on a [`BlockingPool`], returned by [`spawn_blocking`].
+///
+/// If the work panicked, awaiting it resumes the panic. Dropping it does not stop the work, whose
+/// output is discarded.
+pub struct Blocking<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> fmt::Debug for Blocking<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blocking").finish_non_exhaustive()
+    }
+}
+
+impl<T> Blocking<T> {
+    /// Whether the work has finished running.
+    pub fn is_finished(&self) -> bool {
+        self.slot.lock().unwrap().output.is_some()
+    }
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.slot.lock().unwrap();
+
+        match slot.output.take() {
+            Some(Ok(output)) => Poll::Ready(output),
+            Some(Err(panic)) => {
+                drop(slot);
+                panic::resume_unwind(panic)
+            }
+            None => {
+                match &mut slot.waker {
+                    Some(waker) => waker.clone_from(cx.waker()),
+                    waker => *waker = Some(cx.waker().clone()),
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use super::{spawn_blocking, BlockingPool};
+    use crate::runtime::{nop, spawn, Runtime};
+
+    #[test]
+    fn resumes_on_the_runtime_thread() {
+        let rt = Runtime::new().unwrap();
+        let runtime_thread = thread::current().id();
+
+        rt.block_on(async {
+            // Keeps a request in flight, so the runtime waits on the ring rather than idling
+            let busy = spawn(async {
+                for _ in 0..10 {
+                    nop().await.unwrap();
+                }
+            });
+
+            let worker = spawn_blocking(|| {
+                thread::sleep(Duration::from_millis(10));
+                thread::current().id()
+            })
+            .await;
+
+            assert_ne!(worker, runtime_thread);
+            assert_eq!(thread::current().id(), runtime_thread);
+            busy.await;
+        });
+    }
+
+    #[test]
+    fn queues_work_beyond_the_thread_limit() {
+        let rt = Runtime::new().unwrap();
+        let pool = BlockingPool::new(2);
+        let barrier = Arc::new(Barrier::new(2));
+
+        let sum = rt.block_on(async {
+            let tasks: Vec<_> = (0..8)
+                .map(|i| {
+                    let barrier = barrier.clone();
+                    pool.spawn(move || {
+                        // The first two run at once, so neither finishes before the other starts
+                        if i < 2 {
+                            barrier.wait();
+                        }
+                        i
+                    })
+                })
+                .collect();
+
+            let mut sum = 0;
+            for task in tasks {
+                sum += task.await;
+            }
+            sum
+        });
+
+        assert_eq!(sum, (0..8).sum());
+        assert!(pool.shared.state.lock().unwrap().threads <= 2);
+    }
+}
@@ -0,0 +1,509 @@
+//! A single-threaded async runtime, driven by completions on an io_uring.
+//!
+//! Ring operations are [`Op`] futures. Each is keyed by the user_data of its SQE, and the task
+//! awaiting it is woken once the matching CQE is reaped. Tasks are only polled on the thread the
+//! [`Runtime`] was created on, and so need not be `Send`.
+//!
+//! ```no_run
+//! # use rask_core::runtime::{self, Runtime};
+//! let rt = Runtime::new().unwrap();
+//! let sum = rt.block_on(async {
+//!     let task = runtime::spawn(async {
+//!         runtime::nop().await.unwrap();
+//!         2
+//!     });
+//!
+//!     task.await + 2
+//! });
+//! assert_eq!(sum, 4);
+//! ```
+
+pub mod net;
+pub mod signal;
+pub mod time;
+
+mod op;
+pub use op::*;
+
+mod blocking;
+pub use blocking::*;
+
+mod task;
+pub use task::JoinHandle;
+
+mod thread_per_core;
+pub use thread_per_core::*;
+
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    io, mem,
+    pin::pin,
+    rc::Rc,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use rask_liburing::{BufRing, IoUring, NapiSettings, SubmissionEntry};
+use slab::Slab;
+
+use crate::sys::unix::io::next_sqe;
+use op::{Lifecycle, UNTRACKED};
+use task::{Queue, Task, TaskWaker};
+use time::{RingTimeout, Wheel, TIMEOUT};
+
+const RING_ENTRIES: u32 = 256;
+
+/// Prepares an untracked request, deferred until the runtime next parks.
+type Deferred = Box<dyn FnOnce(&mut SubmissionEntry<'_>)>;
+
+/// The user_data of the read armed on the task queue's eventfd, which completes when a task is
+/// woken while the runtime is parked.
+const WAKE: u64 = u64::MAX - 2;
+
+/// The user_data of the request cancelling every other one when the runtime is dropped.
+const CANCEL: u64 = u64::MAX - 3;
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Rc<Inner>>> = const { RefCell::new(None) };
+}
+
+struct Inner {
+    ring: RefCell<IoUring>,
+    /// Buffer rings registered with the ring, dropped after it.
+    buf_rings: RefCell<Vec<Rc<BufRing>>>,
+    ops: RefCell<Slab<Lifecycle>>,
+    /// Untracked requests submitted while completions were being reaped, prepared on the next
+    /// park.
+    deferred: RefCell<Vec<Deferred>>,
+    timers: RefCell<Wheel>,
+    timeout: RefCell<RingTimeout>,
+    /// Whether the read on the task queue's eventfd is in flight.
+    wake_armed: Cell<bool>,
+    /// Written to by the kernel when the read completes.
+    wake_buf: RefCell<Box<[u8]>>,
+    tasks: RefCell<Slab<Option<Task>>>,
+    queue: Arc<Queue>,
+}
+
+impl Inner {
+    fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+    {
+        let (task, handle) = task::task(future);
+        let id = self.tasks.borrow_mut().insert(Some(task));
+        self.queue.push(id);
+
+        handle
+    }
+
+    /// Polls every task which has been woken.
+    fn run_tasks(&self) {
+        for id in self.queue.take() {
+            // The task may have already completed, or be woken by a stale waker
+            let Some(mut task) = self.tasks.borrow_mut().get_mut(id).and_then(Option::take) else {
+                continue;
+            };
+
+            let waker = TaskWaker::task(id, self.queue.clone());
+            match task.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(()) => {
+                    self.tasks.borrow_mut().remove(id);
+                }
+                Poll::Pending => self.tasks.borrow_mut()[id] = Some(task),
+            }
+        }
+    }
+
+    /// Submits pending operations and waits for at least one to complete, or for a task to be
+    /// woken, including from another thread. Timers whose deadline has passed are fired.
+    fn park(&self) -> io::Result<()> {
+        let mut ring = self.ring.borrow_mut();
+        for prep in self.deferred.take() {
+            prep(&mut next_sqe(&mut ring)?);
+        }
+
+        let mut timeout = self.timeout.borrow_mut();
+        if let Some(deadline) = self.timers.borrow().next_deadline() {
+            timeout.arm(&mut ring, deadline)?;
+        }
+
+        if !self.wake_armed.get() {
+            let mut buf = self.wake_buf.borrow_mut();
+            next_sqe(&mut ring)?
+                .prep_read(self.queue.eventfd(), &mut buf, u64::MAX)
+                .set_user_data(WAKE);
+            self.wake_armed.set(true);
+        }
+
+        if !self.queue.park() {
+            ring.enter()?;
+            return Ok(());
+        }
+        let res = ring.enter_and_wait(1);
+        self.queue.unpark();
+        res?;
+
+        reap(
+            &mut ring,
+            &mut self.ops.borrow_mut(),
+            &mut timeout,
+            &self.wake_armed,
+        );
+        drop(timeout);
+        drop(ring);
+
+        self.timers.borrow_mut().advance(Instant::now());
+        Ok(())
+    }
+
+    /// Whether any request is in flight, whose resources the kernel may still be using.
+    fn is_busy(&self) -> bool {
+        self.wake_armed.get()
+            || self.timeout.borrow().is_armed()
+            || self.ops.borrow().iter().any(|(_, op)| op.is_in_flight())
+    }
+
+    /// Cancels each request in flight by its user_data.
+    fn cancel_each(&mut self) -> io::Result<()> {
+        let ring = self.ring.get_mut();
+        for (key, op) in self.ops.get_mut().iter() {
+            if op.is_in_flight() {
+                next_sqe(ring)?
+                    .prep_cancel(key as u64)
+                    .set_user_data(UNTRACKED);
+            }
+        }
+        if self.wake_armed.get() {
+            next_sqe(ring)?.prep_cancel(WAKE).set_user_data(UNTRACKED);
+        }
+        if self.timeout.get_mut().is_armed() {
+            next_sqe(ring)?
+                .prep_timeout_remove(TIMEOUT)
+                .set_user_data(UNTRACKED);
+        }
+
+        Ok(())
+    }
+
+    /// Leaks everything the kernel may still write to, when the requests in flight can't be
+    /// reaped. Tearing down the ring cancels them, but not before they may have completed.
+    fn abandon(&mut self) {
+        mem::forget(mem::take(self.ops.get_mut()));
+        mem::forget(mem::take(self.buf_rings.get_mut()));
+        mem::forget(mem::take(self.wake_buf.get_mut()));
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if !self.is_busy() {
+            return;
+        }
+
+        // Every request is cancelled and reaped before the ring is torn down, as the kernel may
+        // otherwise write to their buffers once they are freed
+        let ring = self.ring.get_mut();
+        let Ok(mut sqe) = next_sqe(ring) else {
+            return self.abandon();
+        };
+        sqe.prep_cancel_any().set_user_data(CANCEL);
+
+        while self.is_busy() {
+            let ring = self.ring.get_mut();
+            match ring.enter_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return self.abandon(),
+            }
+            let cancelled = reap(
+                ring,
+                self.ops.get_mut(),
+                self.timeout.get_mut(),
+                &self.wake_armed,
+            );
+
+            // IORING_ASYNC_CANCEL_ANY is only supported since Linux 5.19, before which each
+            // request has to be cancelled by its user_data
+            if cancelled == Some(-libc::EINVAL) && self.cancel_each().is_err() {
+                return self.abandon();
+            }
+        }
+    }
+}
+
+/// Hands each reaped completion to the request it belongs to. Returns the result of the request
+/// cancelling every other one, if it completed.
+fn reap(
+    ring: &mut IoUring,
+    ops: &mut Slab<Lifecycle>,
+    timeout: &mut RingTimeout,
+    wake_armed: &Cell<bool>,
+) -> Option<i32> {
+    let mut cancelled = None;
+    for cqe in ring.get_cqes() {
+        match cqe.get_user_data() {
+            CANCEL => {
+                cancelled = Some(cqe.result());
+                continue;
+            }
+            TIMEOUT => {
+                timeout.complete();
+                continue;
+            }
+            WAKE => {
+                wake_armed.set(false);
+                continue;
+            }
+            _ => {}
+        }
+
+        let key = cqe.get_user_data() as usize;
+        if ops.get_mut(key).is_some_and(|op| op.complete(cqe)) {
+            ops.remove(key);
+        }
+    }
+
+    cancelled
+}
+
+/// Sets the runtime as current for the thread, until dropped.
+struct Enter;
+
+impl Enter {
+    fn new(inner: Rc<Inner>) -> Self {
+        CONTEXT.with_borrow_mut(|cx| {
+            assert!(cx.is_none(), "cannot start a runtime from within a runtime");
+            *cx = Some(inner);
+        });
+
+        Self
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CONTEXT.set(None);
+    }
+}
+
+/// A single-threaded executor for futures, which drives their ring operations to completion.
+///
+/// A `Runtime` is bound to the thread that created it.
+pub struct Runtime {
+    inner: Rc<Inner>,
+}
+
+impl Runtime {
+    /// Creates a runtime backed by a new ring with default parameters.
+    pub fn new() -> io::Result<Self> {
+        Self::with_ring(IoUring::new(RING_ENTRIES)?)
+    }
+
+    /// Creates a runtime backed by `ring`, i.e. one configured with
+    /// [`IoUringBuilder`](rask_liburing::IoUringBuilder).
+    pub fn with_ring(ring: IoUring) -> io::Result<Self> {
+        Ok(Self {
+            inner: Rc::new(Inner {
+                ring: RefCell::new(ring),
+                buf_rings: RefCell::new(Vec::new()),
+                ops: RefCell::new(Slab::new()),
+                deferred: RefCell::new(Vec::new()),
+                timers: RefCell::new(Wheel::new(Instant::now())),
+                timeout: RefCell::new(RingTimeout::new()),
+                wake_armed: Cell::new(false),
+                wake_buf: RefCell::new(vec![0; 8].into_boxed_slice()),
+                tasks: RefCell::new(Slab::new()),
+                queue: Arc::new(Queue::new()?),
+            }),
+        })
+    }
+
+    /// Spawns `future` onto the runtime. It is not polled until the runtime is running, in
+    /// [`Runtime::block_on`].
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + 'static,
+    {
+        self.inner.spawn(future)
+    }
+
+    /// Runs `future` to completion, driving spawned tasks and ring operations alongside it.
+    ///
+    /// Tasks which have not completed by the time `future` does are kept, and continue to be
+    /// driven by the next call to `block_on`.
+    ///
+    /// # Panics
+    /// Panics if called from within a runtime, or if waiting on the ring fails.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let _enter = Enter::new(self.inner.clone());
+        let mut future = pin!(future);
+        let waker = TaskWaker::main(self.inner.queue.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            if self.inner.queue.take_main() {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+
+            self.inner.run_tasks();
+
+            if self.inner.queue.is_empty() {
+                self.inner.park().expect("failed to wait on the ring");
+            }
+        }
+    }
+}
+
+/// Spawns `future` onto the current runtime.
+///
+/// # Panics
+/// Panics if called outside of a [`Runtime`].
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+{
+    CONTEXT.with_borrow(|cx| {
+        cx.as_ref()
+            .expect("no runtime is running on this thread")
+            .spawn(future)
+    })
+}
+
+/// Registers `buf_ring` with the current runtime's ring, providing its buffers to requests
+/// selecting from its group, i.e. [`UdpSocket::recv_multishot`](net::UdpSocket::recv_multishot).
+/// The runtime keeps the buffer ring alive for as long as the ring.
+///
+/// # Panics
+/// Panics if called outside of a [`Runtime`].
+pub fn register_buf_ring(buf_ring: Rc<BufRing>) -> io::Result<()> {
+    CONTEXT.with_borrow(|cx| {
+        let cx = cx.as_ref().expect("no runtime is running on this thread");
+        // SAFETY: the buffer ring is kept alive until the ring is dropped
+        unsafe { cx.ring.borrow_mut().register_buf_ring(&buf_ring) }?;
+        cx.buf_rings.borrow_mut().push(buf_ring);
+
+        Ok(())
+    })
+}
+
+/// Registers NAPI busy polling `settings` with the current runtime's ring, replacing and returning
+/// those previously registered. Requires Linux 6.9 or later.
+///
+/// # Panics
+/// Panics if called outside of a [`Runtime`].
+pub fn register_napi(settings: NapiSettings) -> io::Result<NapiSettings> {
+    CONTEXT.with_borrow(|cx| {
+        let cx = cx.as_ref().expect("no runtime is running on this thread");
+        cx.ring.borrow_mut().register_napi(settings)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        net,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    use rask_liburing::IoUring;
+
+    use super::{net::TcpStream, nop, spawn, time::sleep, Op, Runtime};
+
+    #[test]
+    fn spawned_tasks_are_joined() {
+        let rt = Runtime::new().unwrap();
+        let sum = rt.block_on(async {
+            let tasks: Vec<_> = (0..16)
+                .map(|i| {
+                    spawn(async move {
+                        nop().await.unwrap();
+                        i
+                    })
+                })
+                .collect();
+
+            let mut sum = 0;
+            for task in tasks {
+                sum += task.await;
+            }
+            sum
+        });
+
+        assert_eq!(sum, (0..16).sum());
+    }
+
+    #[test]
+    fn spawned_before_block_on_is_driven() {
+        let rt = Runtime::new().unwrap();
+        let done = Rc::new(Cell::new(false));
+
+        let flag = done.clone();
+        let task = rt.spawn(async move {
+            nop().await.unwrap();
+            flag.set(true);
+        });
+
+        rt.block_on(task);
+        assert!(done.get());
+    }
+
+    #[test]
+    fn waits_for_the_poller_when_the_sq_is_full() {
+        let ring = IoUring::builder()
+            .sqpoll(Duration::from_millis(10))
+            .build(4)
+            .unwrap();
+        let rt = Runtime::with_ring(ring).unwrap();
+
+        rt.block_on(async {
+            let tasks: Vec<_> = (0..64).map(|_| spawn(nop())).collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn dropped_op_is_reaped() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let op = unsafe {
+                Op::submit(vec![0u8; 16], |_, sqe| {
+                    sqe.prep_nop();
+                })
+            }
+            .unwrap();
+            drop(op);
+
+            nop().await.unwrap();
+        });
+
+        assert!(rt.inner.ops.borrow().is_empty());
+    }
+
+    #[test]
+    fn reaps_requests_in_flight_when_dropped() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let rt = Runtime::new().unwrap();
+        let stream = TcpStream::from_std(server);
+        rt.spawn(async move { stream.read(Vec::with_capacity(16)).await });
+        rt.spawn(sleep(Duration::from_secs(60)));
+        // Parks once, leaving the read, the ring timeout and the wake read in flight
+        rt.block_on(nop()).unwrap();
+        assert!(rt.inner.is_busy());
+
+        let start = Instant::now();
+        drop(rt);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
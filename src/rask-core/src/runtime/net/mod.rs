@@ -0,0 +1,156 @@
+//! Networking types whose IO is submitted to the current runtime's ring.
+//!
+//! Buffers are passed to operations by value, and handed back alongside the result, as the kernel
+//! may still be using them after the future awaiting the operation is dropped.
+
+mod tcp;
+pub use tcp::*;
+
+mod udp;
+pub use udp::*;
+
+mod splice;
+
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    os::fd::{FromRawFd, OwnedFd, RawFd},
+};
+
+use rask_liburing::{CompletionEntry, SqeFlags};
+
+use super::op::submit_untracked;
+
+/// A file descriptor used by ring operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fd {
+    /// A regular file descriptor.
+    Raw(RawFd),
+    /// A direct descriptor, at an index of the ring's registered file table.
+    Fixed(u32),
+}
+
+impl Fd {
+    /// The value of the SQE's fd field.
+    pub(crate) fn raw(self) -> RawFd {
+        match self {
+            Fd::Raw(fd) => fd,
+            Fd::Fixed(index) => index as RawFd,
+        }
+    }
+
+    /// The flags an SQE using this descriptor must set.
+    pub(crate) fn flags(self) -> SqeFlags {
+        match self {
+            Fd::Raw(_) => SqeFlags::empty(),
+            Fd::Fixed(_) => SqeFlags::FixedFile,
+        }
+    }
+
+    /// Closes the descriptor, right away if it is a regular one. Direct descriptors can only be
+    /// closed by the ring, with an untracked request.
+    ///
+    /// # Safety
+    /// The descriptor must be owned by the caller, and not used once closed.
+    pub(crate) unsafe fn close(self) {
+        match self {
+            Fd::Raw(fd) => drop(OwnedFd::from_raw_fd(fd)),
+            Fd::Fixed(index) => submit_untracked(move |sqe| {
+                sqe.prep_close_direct(index);
+            }),
+        }
+    }
+}
+
+/// Handles the completion of a dropped request which creates a descriptor, i.e. an accept, by
+/// closing the descriptor it created, as nothing else will.
+pub(crate) fn close_orphaned(direct: bool) -> impl FnMut(&CompletionEntry) + 'static {
+    move |cqe| {
+        let Ok(res) = result(*cqe) else {
+            return;
+        };
+
+        let fd = if direct {
+            Fd::Fixed(res as u32)
+        } else {
+            Fd::Raw(res)
+        };
+        // SAFETY: the descriptor was created by the request, and is owned by nothing else
+        unsafe { fd.close() };
+    }
+}
+
+/// Converts the result of `cqe` into an `io::Result`.
+pub(crate) fn result(cqe: CompletionEntry) -> io::Result<i32> {
+    match cqe.result() {
+        res if res < 0 => Err(io::Error::from_raw_os_error(-res)),
+        res => Ok(res),
+    }
+}
+
+/// Converts an address written by the kernel into a `SocketAddr`.
+pub(crate) fn to_socket_addr(
+    storage: &libc::sockaddr_storage,
+    len: libc::socklen_t,
+) -> io::Result<SocketAddr> {
+    match storage.ss_family as libc::c_int {
+        libc::AF_INET if len as usize >= std::mem::size_of::<libc::sockaddr_in>() => {
+            // SAFETY: the family and length match a `sockaddr_in`
+            let addr =
+                unsafe { &*(storage as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>() };
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                u16::from_be(addr.sin_port),
+            )))
+        }
+        libc::AF_INET6 if len as usize >= std::mem::size_of::<libc::sockaddr_in6>() => {
+            // SAFETY: the family and length match a `sockaddr_in6`
+            let addr = unsafe {
+                &*(storage as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                u16::from_be(addr.sin6_port),
+                addr.sin6_flowinfo,
+                addr.sin6_scope_id,
+            )))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "address is not an IPv4 or IPv6 address",
+        )),
+    }
+}
+
+/// Converts `addr` into an address to pass to the kernel, along with its length.
+pub(crate) fn from_socket_addr(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: sockaddr_storage is plain data, for which zero is a valid value
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            // SAFETY: sockaddr_storage is large enough and aligned for any address
+            let sin = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in>()
+            };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            // SAFETY: sockaddr_storage is large enough and aligned for any address
+            let sin6 = unsafe {
+                &mut *(&mut storage as *mut libc::sockaddr_storage).cast::<libc::sockaddr_in6>()
+            };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_scope_id = addr.scope_id();
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as libc::socklen_t)
+}
@@ -0,0 +1,236 @@
+use std::{cell::RefCell, io, rc::Rc};
+
+use rask_liburing::SPLICE_F_FD_IN_FIXED;
+
+use super::{result, Fd};
+use crate::runtime::Op;
+use crate::sys::unix::pipe::Pipe;
+
+/// The capacity asked of the pipes data is spliced through.
+const PIPE_SIZE: usize = 256 * 1024;
+
+/// The number of empty pipes each thread keeps for reuse.
+const MAX_POOLED_PIPES: usize = 16;
+
+/// A splice through a pipe, which holds on to the pipe until it completes.
+type Splice = Op<Rc<Pipe>>;
+
+thread_local! {
+    /// Empty pipes, reused by the splices of the runtime on this thread.
+    static PIPES: RefCell<Vec<Pipe>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Takes an empty pipe from the current thread's pool, or creates one.
+fn take_pipe() -> io::Result<Pipe> {
+    match PIPES.with_borrow_mut(Vec::pop) {
+        Some(pipe) => Ok(pipe),
+        None => Pipe::new(PIPE_SIZE),
+    }
+}
+
+/// Returns a pipe to the current thread's pool. It must be empty, and no longer referenced by
+/// any operation.
+fn release_pipe(pipe: Rc<Pipe>) {
+    let Ok(pipe) = Rc::try_unwrap(pipe) else {
+        return;
+    };
+
+    PIPES.with_borrow_mut(|pipes| {
+        if pipes.len() < MAX_POOLED_PIPES {
+            pipes.push(pipe);
+        }
+    });
+}
+
+/// How far a splice has got.
+#[derive(Debug, Default)]
+struct Progress {
+    /// The bytes moved from the source into the pipe.
+    filled: u64,
+    /// The bytes moved from the pipe to the destination.
+    sent: u64,
+    /// Whether the source has ended.
+    eof: bool,
+}
+
+/// Moves up to `len` bytes from `src`, starting at `offset` if it is a file, to `dst`, through a
+/// pipe, without copying them into userspace. Returns the number of bytes moved, which is less
+/// than `len` only if `src` ends first.
+///
+/// Each round splices from `src` into the pipe, linked to a splice from the pipe to `dst`. A short
+/// splice into the pipe severs the link, as does a short splice out of it, after which the rest
+/// of the pipe is drained on its own. Sources and destinations which are non-blocking complete
+/// with `EAGAIN` when not ready, and are polled before retrying.
+pub(crate) async fn splice(src: Fd, offset: Option<u64>, dst: Fd, len: u64) -> io::Result<u64> {
+    let pipe = Rc::new(take_pipe()?);
+    let mut progress = Progress::default();
+
+    let res = transfer(&pipe, src, offset, dst, len, &mut progress).await;
+
+    // A pipe still holding data would leak it into the next splice
+    if progress.filled == progress.sent {
+        release_pipe(pipe);
+    }
+
+    res.map(|()| progress.sent)
+}
+
+async fn transfer(
+    pipe: &Rc<Pipe>,
+    src: Fd,
+    offset: Option<u64>,
+    dst: Fd,
+    len: u64,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let capacity = pipe.capacity() as u64;
+
+    loop {
+        let buffered = (progress.filled - progress.sent) as u32;
+        if buffered > 0 {
+            match result(drain(pipe, dst, buffered)?.await.0) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => progress.sent += n as u64,
+                Err(e) => retry(e, dst, libc::POLLOUT).await?,
+            }
+            continue;
+        }
+        if progress.eof || progress.filled == len {
+            return Ok(());
+        }
+
+        let chunk = (len - progress.filled).min(capacity) as u32;
+        let at = offset.map(|offset| offset + progress.filled);
+        let (fill, drain) = fill_and_drain(pipe, src, at, dst, chunk)?;
+        let (fill, _) = fill.await;
+        let (drain, _) = drain.await;
+
+        match result(fill) {
+            Ok(0) => progress.eof = true,
+            Ok(n) => progress.filled += n as u64,
+            Err(e) => retry(e, src, libc::POLLIN).await?,
+        }
+        match result(drain) {
+            Ok(n) => progress.sent += n as u64,
+            // The fill was short or failed, severing the link
+            Err(e) if e.raw_os_error() == Some(libc::ECANCELED) => {}
+            Err(e) => retry(e, dst, libc::POLLOUT).await?,
+        }
+    }
+}
+
+/// Submits a splice of `len` bytes from `src` into the pipe, linked to a splice of the same
+/// length from the pipe to `dst`.
+fn fill_and_drain(
+    pipe: &Rc<Pipe>,
+    src: Fd,
+    offset: Option<u64>,
+    dst: Fd,
+    len: u32,
+) -> io::Result<(Splice, Splice)> {
+    let flags = match src {
+        Fd::Raw(_) => 0,
+        Fd::Fixed(_) => SPLICE_F_FD_IN_FIXED,
+    };
+
+    // SAFETY: the operations reference no memory, and hold on to the pipe until they complete
+    unsafe {
+        Op::submit_linked(
+            pipe.clone(),
+            |pipe, sqe| {
+                sqe.prep_splice(src.raw(), offset, pipe.writer(), None, len, flags);
+            },
+            pipe.clone(),
+            |pipe, sqe| {
+                sqe.prep_splice(pipe.reader(), None, dst.raw(), None, len, 0)
+                    .set_flags(dst.flags());
+            },
+        )
+    }
+}
+
+/// Submits a splice of `len` bytes from the pipe to `dst`.
+fn drain(pipe: &Rc<Pipe>, dst: Fd, len: u32) -> io::Result<Splice> {
+    // SAFETY: the operation references no memory, and holds on to the pipe until it completes
+    unsafe {
+        Op::submit(pipe.clone(), |pipe, sqe| {
+            sqe.prep_splice(pipe.reader(), None, dst.raw(), None, len, 0)
+                .set_flags(dst.flags());
+        })
+    }
+}
+
+/// Returns `e`, unless the splice should be retried: if it was interrupted, or once `fd` is ready
+/// for `events` if it would have blocked.
+async fn retry(e: io::Error, fd: Fd, events: libc::c_short) -> io::Result<()> {
+    match e.kind() {
+        io::ErrorKind::Interrupted => Ok(()),
+        io::ErrorKind::WouldBlock => {
+            // SAFETY: the request references no memory
+            let (cqe, ()) = unsafe {
+                Op::submit((), |_, sqe| {
+                    sqe.prep_poll_add(fd.raw(), events as u32)
+                        .set_flags(fd.flags());
+                })
+            }?
+            .await;
+
+            result(cqe).map(|_| ())
+        }
+        _ => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net,
+        rc::Rc,
+        thread,
+    };
+
+    use super::{release_pipe, splice, take_pipe, PIPES};
+    use crate::runtime::{net::Fd, Runtime};
+
+    #[test]
+    fn moves_data_between_sockets_and_reuses_the_pipe() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let body: Vec<u8> = (0..1_000_000u32).map(|i| i as u8).collect();
+
+        let expected = body.clone();
+        let peer = thread::spawn(move || {
+            let mut src = net::TcpStream::connect(addr).unwrap();
+            let mut dst = net::TcpStream::connect(addr).unwrap();
+            src.write_all(&body).unwrap();
+            drop(src);
+
+            let mut received = Vec::new();
+            dst.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let (src, _) = listener.accept().unwrap();
+        let (dst, _) = listener.accept().unwrap();
+        // Non-blocking sockets exercise the retries on `EAGAIN`
+        src.set_nonblocking(true).unwrap();
+        dst.set_nonblocking(true).unwrap();
+
+        let rt = Runtime::new().unwrap();
+        let sent = rt.block_on(async {
+            let fd = |stream: &net::TcpStream| Fd::Raw(std::os::fd::AsRawFd::as_raw_fd(stream));
+            // More than was sent, so the splice ends with the source
+            splice(fd(&src), None, fd(&dst), 2_000_000).await.unwrap()
+        });
+        drop(dst);
+
+        assert_eq!(sent, expected.len() as u64);
+        assert_eq!(peer.join().unwrap(), expected);
+
+        let pooled = PIPES.with_borrow(Vec::len);
+        assert!(pooled >= 1);
+        release_pipe(Rc::new(take_pipe().unwrap()));
+        assert_eq!(PIPES.with_borrow(Vec::len), pooled);
+    }
+}
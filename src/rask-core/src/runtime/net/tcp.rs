@@ -0,0 +1,945 @@
+use std::{
+    future::{self, Future},
+    io::{self, IoSlice},
+    mem::{self, ManuallyDrop},
+    net::{self, Shutdown, SocketAddr, ToSocketAddrs},
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+    pin::Pin,
+    slice,
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use rask_liburing::{NapiSettings, SubmissionEntry};
+
+use super::{close_orphaned, from_socket_addr, result, splice::splice, to_socket_addr, Fd};
+use crate::fs::File;
+use crate::runtime::{
+    register_napi,
+    time::{self, Sleep},
+    Multishot, Op,
+};
+use crate::sys::unix::tcp::ListenerBuilder;
+
+/// A TCP listener, accepting connections with requests submitted to the current runtime's ring.
+///
+/// Connections are accepted either as regular file descriptors, or as direct descriptors, which
+/// avoid the cost of the process file table but may only be used with the ring that accepted
+/// them. Accepting direct descriptors requires the runtime's ring to have a file table, registered
+/// with [`IoUring::register_files_sparse`](rask_liburing::IoUring::register_files_sparse).
+#[derive(Debug)]
+pub struct TcpListener {
+    inner: net::TcpListener,
+}
+
+impl TcpListener {
+    /// Binds a listener to `addr`, with the default [`ListenerBuilder`] options.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let mut listeners = ListenerBuilder::new().cloexec(true).bind(addr)?;
+        Ok(Self::from_std(listeners.remove(0)))
+    }
+
+    /// Wraps a listener bound elsewhere, i.e. by [`ThreadPerCore`](crate::runtime::ThreadPerCore).
+    pub fn from_std(listener: net::TcpListener) -> Self {
+        Self { inner: listener }
+    }
+
+    /// Gets the address the listener is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Accepts a connection, returning it along with the peer's address.
+    ///
+    /// See [accept(2)](https://man.archlinux.org/man/accept.2)
+    pub async fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.accept_with(false).await
+    }
+
+    /// Accepts a connection as a direct descriptor, returning it along with the peer's address.
+    pub async fn accept_direct(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.accept_with(true).await
+    }
+
+    async fn accept_with(&self, direct: bool) -> io::Result<(TcpStream, SocketAddr)> {
+        let fd = self.inner.as_raw_fd();
+        let addr = Box::new((
+            // SAFETY: sockaddr_storage is plain data, for which zero is a valid value
+            unsafe { mem::zeroed::<libc::sockaddr_storage>() },
+            mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t,
+        ));
+
+        // SAFETY: the address is on the heap, owned by the operation
+        let (cqe, addr) = unsafe {
+            Op::submit(addr, |addr, sqe| {
+                let (addr, len) = &mut **addr;
+                if direct {
+                    sqe.prep_accept_direct(fd, addr, len);
+                } else {
+                    sqe.prep_accept_addr(fd, addr, len, libc::SOCK_CLOEXEC);
+                }
+            })
+        }?
+        .on_orphaned(close_orphaned(direct))
+        .await;
+
+        let res = result(cqe)?;
+        let mut stream = if direct {
+            TcpStream::new(Fd::Fixed(res as u32))
+        } else {
+            TcpStream::new(Fd::Raw(res))
+        };
+        let peer = to_socket_addr(&addr.0, addr.1)?;
+        stream.peer = Some(peer);
+
+        Ok((stream, peer))
+    }
+
+    /// Opts connections accepted from the listener into NAPI busy polling with `settings`. Requests
+    /// on them then busy poll their network device's receive queue, rather than sleeping until an
+    /// interrupt, trading CPU time for lower latency.
+    ///
+    /// NAPI settings are registered with the current runtime's ring, so they also apply to the
+    /// connections of other listeners served by it, and replace any registered before. Requires
+    /// Linux 6.9 or later.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](crate::runtime::Runtime).
+    pub fn busy_poll(&self, settings: NapiSettings) -> io::Result<()> {
+        register_napi(settings).map(|_| ())
+    }
+
+    /// Accepts connections with a single multishot request, which the kernel keeps armed to
+    /// complete once for each connection.
+    ///
+    /// The peer's address of connections accepted this way is looked up when requested.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+            direct: false,
+            op: None,
+        }
+    }
+
+    /// Accepts connections as direct descriptors with a single multishot request.
+    ///
+    /// As the peer's address of a direct descriptor cannot be looked up, it is not available for
+    /// connections accepted this way.
+    pub fn incoming_direct(&self) -> Incoming<'_> {
+        Incoming {
+            listener: self,
+            direct: true,
+            op: None,
+        }
+    }
+}
+
+impl From<net::TcpListener> for TcpListener {
+    fn from(listener: net::TcpListener) -> Self {
+        Self::from_std(listener)
+    }
+}
+
+impl AsRawFd for TcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// Connections accepted by a multishot accept request, from [`TcpListener::incoming`].
+///
+/// The request is re-armed if the kernel terminates it, i.e. after an error, and cancelled once
+/// this is dropped.
+#[derive(Debug)]
+pub struct Incoming<'a> {
+    listener: &'a TcpListener,
+    direct: bool,
+    op: Option<Multishot<()>>,
+}
+
+impl Incoming<'_> {
+    /// Waits for the next accepted connection.
+    pub async fn next(&mut self) -> io::Result<TcpStream> {
+        loop {
+            let op = match &mut self.op {
+                Some(op) if !op.is_terminated() => op,
+                op => {
+                    let fd = self.listener.as_raw_fd();
+                    let direct = self.direct;
+                    // SAFETY: the request references no memory
+                    let accept = unsafe {
+                        Multishot::submit((), |_, sqe| {
+                            if direct {
+                                sqe.prep_accept_multi_direct(&fd);
+                            } else {
+                                sqe.prep_accept_multi_flags(&fd, libc::SOCK_CLOEXEC);
+                            }
+                        })
+                    }?;
+                    op.insert(accept.on_orphaned(close_orphaned(direct)))
+                }
+            };
+
+            let Some(cqe) = op.next().await else {
+                continue;
+            };
+
+            let res = result(cqe)?;
+            return Ok(if self.direct {
+                TcpStream::new(Fd::Fixed(res as u32))
+            } else {
+                TcpStream::new(Fd::Raw(res))
+            });
+        }
+    }
+}
+
+/// The delay before racing a connection attempt to the next address, recommended by
+/// [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305#section-5).
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Configures outbound TCP connections.
+///
+/// Connecting to a host with several addresses races attempts to each, following Happy Eyeballs
+/// ([RFC 8305](https://www.rfc-editor.org/rfc/rfc8305)). Addresses are tried alternating between
+/// IPv6 and IPv4, starting with IPv6. Each attempt is given a head start before the next one
+/// begins, and a failed attempt starts the next immediately. The first connection established is
+/// returned, and the remaining attempts cancelled.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rask_core::runtime::net::TcpConnector;
+/// # async fn connect() -> std::io::Result<()> {
+/// let stream = TcpConnector::new()
+///     .timeout(Duration::from_secs(5))
+///     .local_addr("10.0.0.2:0".parse().unwrap())
+///     .connect("10.0.0.1:8080")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TcpConnector {
+    timeout: Option<Duration>,
+    attempt_delay: Duration,
+    local_v4: Option<SocketAddr>,
+    local_v6: Option<SocketAddr>,
+}
+
+impl Default for TcpConnector {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            attempt_delay: ATTEMPT_DELAY,
+            local_v4: None,
+            local_v6: None,
+        }
+    }
+}
+
+impl TcpConnector {
+    /// Creates a connector with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long to wait for a connection to be established, across all attempts. Each
+    /// attempt is submitted with a timeout linked to it for the time remaining.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long an attempt is given before racing an attempt to the next address. Defaults to
+    /// 250ms.
+    pub fn attempt_delay(&mut self, delay: Duration) -> &mut Self {
+        self.attempt_delay = delay;
+        self
+    }
+
+    /// Binds connections to the local source address `addr` before connecting. A port of 0 is
+    /// chosen when connecting, so the same source address may be used for connections to many
+    /// destinations.
+    ///
+    /// Once a local address is set, only addresses in the family of a local address are connected
+    /// to. Set both an IPv4 and IPv6 address to connect to either.
+    pub fn local_addr(&mut self, addr: SocketAddr) -> &mut Self {
+        match addr {
+            SocketAddr::V4(_) => self.local_v4 = Some(addr),
+            SocketAddr::V6(_) => self.local_v6 = Some(addr),
+        }
+        self
+    }
+
+    /// Connects to one of the addresses `addr` resolves to. Resolving a host name blocks the
+    /// thread, so prefer passing resolved addresses.
+    pub async fn connect(&self, addr: impl ToSocketAddrs) -> io::Result<TcpStream> {
+        let mut pending = self.sort(addr.to_socket_addrs()?).into_iter();
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut attempts: Vec<Pin<Box<dyn Future<Output = io::Result<TcpStream>> + '_>>> =
+            Vec::new();
+        let mut delay: Option<Sleep> = None;
+        let mut start_next = true;
+        let mut last_err = None;
+
+        future::poll_fn(|cx| loop {
+            if delay
+                .as_mut()
+                .is_some_and(|delay| Pin::new(delay).poll(cx).is_ready())
+            {
+                start_next = true;
+            }
+            if start_next {
+                start_next = false;
+                delay = None;
+                if let Some(addr) = pending.next() {
+                    attempts.push(Box::pin(self.attempt(addr, deadline)));
+                }
+            }
+
+            if attempts.is_empty() {
+                return Poll::Ready(Err(last_err.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+                })));
+            }
+
+            let mut i = 0;
+            while i < attempts.len() {
+                match attempts[i].as_mut().poll(cx) {
+                    Poll::Ready(Ok(stream)) => return Poll::Ready(Ok(stream)),
+                    Poll::Ready(Err(e)) => {
+                        drop(attempts.swap_remove(i));
+                        last_err = Some(e);
+                        start_next = true;
+                    }
+                    Poll::Pending => i += 1,
+                }
+            }
+
+            if start_next {
+                continue;
+            }
+            if delay.is_none() && pending.len() > 0 {
+                delay = Some(time::sleep(self.attempt_delay));
+                continue;
+            }
+            return Poll::Pending;
+        })
+        .await
+    }
+
+    /// Orders `addrs` alternating between IPv6 and IPv4, dropping those without a local address
+    /// when one is set.
+    fn sort(&self, addrs: impl Iterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+        let bound = self.local_v4.is_some() || self.local_v6.is_some();
+        let (v6, v4): (Vec<_>, Vec<_>) = addrs
+            .filter(|addr| !bound || self.local_for(addr).is_some())
+            .partition(SocketAddr::is_ipv6);
+
+        let mut sorted = Vec::with_capacity(v6.len() + v4.len());
+        let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+        loop {
+            match (v6.next(), v4.next()) {
+                (None, None) => return sorted,
+                (a, b) => sorted.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+
+    fn local_for(&self, addr: &SocketAddr) -> Option<SocketAddr> {
+        match addr {
+            SocketAddr::V4(_) => self.local_v4,
+            SocketAddr::V6(_) => self.local_v6,
+        }
+    }
+
+    /// Creates a socket and connects it to `addr`, within `deadline`.
+    async fn attempt(&self, addr: SocketAddr, deadline: Option<Instant>) -> io::Result<TcpStream> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+
+        // SAFETY: the request references no memory
+        let (cqe, ()) = unsafe {
+            Op::submit((), |_, sqe| {
+                sqe.prep_socket(domain, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0);
+            })
+        }?
+        // Losing attempts are dropped once another connects, possibly with the socket in flight
+        .on_orphaned(close_orphaned(false))
+        .await;
+        let stream = TcpStream::new(Fd::Raw(result(cqe)?));
+
+        if let Some(local) = self.local_for(&addr) {
+            stream.bind(local)?;
+        }
+
+        let addr_buf = Box::new(from_socket_addr(addr));
+        let prep = |addr: &mut Box<(libc::sockaddr_storage, libc::socklen_t)>,
+                    sqe: &mut SubmissionEntry<'_>| {
+            sqe.prep_connect(stream.fd.raw(), &addr.0, addr.1);
+        };
+        // SAFETY: the address is on the heap, owned by the operation
+        let op = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(timed_out());
+                }
+                unsafe { Op::submit_with_timeout(addr_buf, remaining, prep) }?
+            }
+            None => unsafe { Op::submit(addr_buf, prep) }?,
+        };
+
+        match result(op.await.0) {
+            Ok(_) => {
+                let mut stream = stream;
+                stream.peer = Some(addr);
+                Ok(stream)
+            }
+            Err(e) if e.raw_os_error() == Some(libc::ECANCELED) => Err(timed_out()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "connection timed out")
+}
+
+/// A TCP connection, whose IO is submitted to the current runtime's ring.
+///
+/// Dropping the stream closes it. Use [`TcpStream::close`] to wait for it to be closed, and
+/// observe any error.
+#[derive(Debug)]
+pub struct TcpStream {
+    fd: Fd,
+    peer: Option<SocketAddr>,
+}
+
+impl TcpStream {
+    pub(crate) fn new(fd: Fd) -> Self {
+        Self { fd, peer: None }
+    }
+
+    /// Connects to one of the addresses `addr` resolves to, waiting at most `timeout`. See
+    /// [`TcpConnector`] for more options.
+    pub async fn connect(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<Self> {
+        TcpConnector::new().timeout(timeout).connect(addr).await
+    }
+
+    /// Wraps a connection created elsewhere.
+    pub fn from_std(stream: net::TcpStream) -> Self {
+        Self::new(Fd::Raw(stream.into_raw_fd()))
+    }
+
+    /// Whether the connection is a direct descriptor, only usable with the runtime's ring.
+    pub fn is_direct(&self) -> bool {
+        matches!(self.fd, Fd::Fixed(_))
+    }
+
+    /// Gets the address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self.peer {
+            Some(peer) => Ok(peer),
+            None => self.with_std(net::TcpStream::peer_addr),
+        }
+    }
+
+    /// Gets the local address of the connection.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.with_std(net::TcpStream::local_addr)
+    }
+
+    /// Binds the socket to the local address `addr`, before it is connected.
+    fn bind(&self, addr: SocketAddr) -> io::Result<()> {
+        self.with_std(|stream| {
+            let fd = stream.as_raw_fd();
+            if addr.port() == 0 {
+                // Defer choosing a port to connect, where it need only be unique per destination
+                let on: libc::c_int = 1;
+                let res = unsafe {
+                    libc::setsockopt(
+                        fd,
+                        libc::IPPROTO_IP,
+                        libc::IP_BIND_ADDRESS_NO_PORT,
+                        (&on as *const libc::c_int).cast(),
+                        mem::size_of::<libc::c_int>() as libc::socklen_t,
+                    )
+                };
+                if res == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            let (storage, len) = from_socket_addr(addr);
+            let res =
+                unsafe { libc::bind(fd, (&storage as *const libc::sockaddr_storage).cast(), len) };
+            if res == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        })
+    }
+
+    fn with_std<T>(&self, f: impl FnOnce(&net::TcpStream) -> io::Result<T>) -> io::Result<T> {
+        let Fd::Raw(fd) = self.fd else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "not available for direct descriptors",
+            ));
+        };
+
+        // SAFETY: the stream is not dropped, leaving `fd` open
+        let stream = ManuallyDrop::new(unsafe { net::TcpStream::from_raw_fd(fd) });
+        f(&stream)
+    }
+
+    /// Reads into the spare capacity of `buf`, returning the number of bytes read, which are
+    /// appended to `buf`. Returns 0 once the peer has shut down writing, or if `buf` has no spare
+    /// capacity.
+    ///
+    /// See [recv(2)](https://man.archlinux.org/man/recv.2)
+    pub async fn read(&self, buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        let fd = self.fd;
+        // SAFETY: the buffer is on the heap, owned by the operation
+        let op = unsafe {
+            Op::try_submit(buf, |buf, sqe| {
+                let spare = slice::from_raw_parts_mut(
+                    buf.as_mut_ptr().add(buf.len()),
+                    buf.capacity() - buf.len(),
+                );
+                sqe.prep_recv(fd.raw(), spare).set_flags(fd.flags());
+            })
+        };
+        let (cqe, mut buf) = match op {
+            Ok(op) => op.await,
+            Err((e, buf)) => return (Err(e), buf),
+        };
+
+        match result(cqe) {
+            Ok(n) => {
+                // SAFETY: the kernel initialized `n` bytes of the spare capacity
+                unsafe { buf.set_len(buf.len() + n as usize) };
+                (Ok(n as usize), buf)
+            }
+            Err(e) => (Err(e), buf),
+        }
+    }
+
+    /// Writes from `buf`, returning the number of bytes written.
+    ///
+    /// See [send(2)](https://man.archlinux.org/man/send.2)
+    pub async fn write(&self, buf: Vec<u8>) -> (io::Result<usize>, Vec<u8>) {
+        let (res, buf) = self.write_at(buf, 0).await;
+        (res.map(|n| n as usize), buf)
+    }
+
+    /// Writes all of `buf`, submitting further writes until it has been written in full.
+    pub async fn write_all(&self, mut buf: Vec<u8>) -> (io::Result<()>, Vec<u8>) {
+        let mut written = 0;
+
+        while written < buf.len() {
+            let res;
+            (res, buf) = self.write_at(buf, written).await;
+            match res {
+                Ok(0) => return (Err(io::ErrorKind::WriteZero.into()), buf),
+                Ok(n) => written += n as usize,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return (Err(e), buf),
+            }
+        }
+
+        (Ok(()), buf)
+    }
+
+    async fn write_at(&self, buf: Vec<u8>, offset: usize) -> (io::Result<i32>, Vec<u8>) {
+        let fd = self.fd;
+        // SAFETY: the buffer is on the heap, owned by the operation
+        let op = unsafe {
+            Op::try_submit(buf, |buf, sqe| {
+                sqe.prep_send(fd.raw(), &buf[offset..])
+                    .set_flags(fd.flags());
+            })
+        };
+
+        match op {
+            Ok(op) => {
+                let (cqe, buf) = op.await;
+                (result(cqe), buf)
+            }
+            Err((e, buf)) => (Err(e), buf),
+        }
+    }
+
+    /// Writes from each of `bufs` in order with a single request, returning the number of bytes
+    /// written.
+    ///
+    /// See [writev(2)](https://man.archlinux.org/man/writev.2)
+    pub async fn write_vectored(&self, bufs: Vec<Vec<u8>>) -> (io::Result<usize>, Vec<Vec<u8>>) {
+        let fd = self.fd;
+        // SAFETY: the buffers and the slices referring to them are on the heap, owned by the
+        // operation
+        let op = unsafe {
+            Op::try_submit(
+                (bufs, Vec::<IoSlice<'static>>::new()),
+                |(bufs, slices), sqe| {
+                    *slices = bufs
+                        .iter()
+                        .map(|buf| IoSlice::new(slice::from_raw_parts(buf.as_ptr(), buf.len())))
+                        .collect();
+                    sqe.prep_writev(fd.raw(), slices).set_flags(fd.flags());
+                },
+            )
+        };
+
+        match op {
+            Ok(op) => {
+                let (cqe, (bufs, _)) = op.await;
+                (result(cqe).map(|n| n as usize), bufs)
+            }
+            Err((e, (bufs, _))) => (Err(e), bufs),
+        }
+    }
+
+    /// Sends `len` bytes of `file` from `offset`, splicing them through a pipe rather than copying
+    /// them into userspace, as for static files. Returns the number of bytes sent, which is less
+    /// than `len` only if the file ends first.
+    ///
+    /// See [splice(2)](https://man.archlinux.org/man/splice.2)
+    pub async fn send_file(&self, file: &File, offset: u64, len: u64) -> io::Result<u64> {
+        splice(Fd::Raw(file.as_raw_fd()), Some(offset), self.fd, len).await
+    }
+
+    /// Sends up to `len` bytes read from `src`, splicing them through a pipe rather than copying
+    /// them into userspace, as for proxied bodies. Returns the number of bytes sent, which is less
+    /// than `len` only if `src` shuts down writing first.
+    ///
+    /// See [splice(2)](https://man.archlinux.org/man/splice.2)
+    pub async fn splice_from(&self, src: &TcpStream, len: u64) -> io::Result<u64> {
+        splice(src.fd, None, self.fd, len).await
+    }
+
+    /// Shuts down the read, write, or both halves of the connection.
+    ///
+    /// See [shutdown(2)](https://man.archlinux.org/man/shutdown.2)
+    pub async fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let fd = self.fd;
+        // SAFETY: the request references no memory
+        let (cqe, ()) = unsafe {
+            Op::submit((), |_, sqe| {
+                sqe.prep_shutdown(fd.raw(), how).set_flags(fd.flags());
+            })
+        }?
+        .await;
+
+        result(cqe).map(|_| ())
+    }
+
+    /// Closes the connection, waiting for the close to complete.
+    ///
+    /// See [close(2)](https://man.archlinux.org/man/close.2)
+    pub async fn close(self) -> io::Result<()> {
+        let fd = self.fd;
+
+        // SAFETY: the request references no memory
+        let op = unsafe {
+            Op::submit((), |_, sqe| match fd {
+                Fd::Raw(fd) => {
+                    sqe.prep_close(fd);
+                }
+                Fd::Fixed(index) => {
+                    sqe.prep_close_direct(index);
+                }
+            })
+        };
+        // Only once the close is queued does it own the descriptor, which is otherwise closed
+        // when the stream is dropped
+        let op = op?;
+        mem::forget(self);
+
+        let (cqe, ()) = op.await;
+        result(cqe).map(|_| ())
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        // SAFETY: the stream owns the descriptor
+        unsafe { self.fd.close() };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        io::{self, Read, Write},
+        net::{self, Shutdown, SocketAddr},
+        os::fd::AsRawFd,
+        pin::pin,
+        task::{Context, Waker},
+        thread,
+        time::Duration,
+    };
+
+    use rask_liburing::{IoUring, NapiSettings};
+
+    use super::{TcpConnector, TcpListener, TcpStream};
+    use crate::{
+        runtime::{time::sleep, Runtime},
+        sys::unix::tcp::ListenerBuilder,
+    };
+
+    #[test]
+    fn accepts_and_echoes() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = net::TcpStream::connect(addr).unwrap();
+            stream.write_all(b"ping").unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).unwrap();
+            (stream.local_addr().unwrap(), reply)
+        });
+
+        let rt = Runtime::new().unwrap();
+        let peer = rt.block_on(async {
+            let (stream, peer) = listener.accept().await.unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), peer);
+            assert_eq!(stream.local_addr().unwrap(), addr);
+
+            let mut buf = Vec::with_capacity(16);
+            loop {
+                let (n, b) = stream.read(buf).await;
+                buf = b;
+                if n.unwrap() == 0 {
+                    break;
+                }
+            }
+            assert_eq!(buf, b"ping");
+
+            let (res, _) = stream
+                .write_vectored(vec![b"po".to_vec(), b"ng".to_vec()])
+                .await;
+            assert_eq!(res.unwrap(), 4);
+            let (res, _) = stream.write_all(b"!".to_vec()).await;
+            res.unwrap();
+            stream.shutdown(Shutdown::Write).await.unwrap();
+            stream.close().await.unwrap();
+
+            peer
+        });
+
+        let (local, reply) = client.join().unwrap();
+        assert_eq!(peer, local);
+        assert_eq!(reply, b"pong!");
+    }
+
+    #[test]
+    fn closes_connections_accepted_for_dropped_accepts() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let clients: Vec<_> = (0..2)
+            .map(|_| {
+                let client = net::TcpStream::connect(addr).unwrap();
+                client
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                client
+            })
+            .collect();
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let mut cx = Context::from_waker(Waker::noop());
+
+            // Each accept is submitted, and completes with a queued connection, but is never
+            // polled again
+            let mut accept = pin!(listener.accept());
+            assert!(accept.as_mut().poll(&mut cx).is_pending());
+            let mut incoming = listener.incoming();
+            let mut next = pin!(incoming.next());
+            assert!(next.as_mut().poll(&mut cx).is_pending());
+
+            sleep(Duration::from_millis(50)).await;
+        });
+
+        for mut client in clients {
+            assert_eq!(client.read(&mut [0; 1]).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn accepts_with_busy_polling() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || net::TcpStream::connect(addr).unwrap());
+
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let settings = NapiSettings::new(Duration::from_micros(50));
+            match listener.busy_poll(settings) {
+                // NAPI registration is only available since Linux 6.9
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+                res => res.unwrap(),
+            }
+
+            let (stream, _) = listener.accept().await.unwrap();
+            assert_eq!(stream.local_addr().unwrap(), addr);
+        });
+
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn accepts_with_multishot_closing_on_exec() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || net::TcpStream::connect(addr).unwrap());
+
+        let rt = Runtime::new().unwrap();
+        let flags = rt.block_on(async {
+            let stream = listener.incoming().next().await.unwrap();
+            // SAFETY: F_GETFD only reads the descriptor's flags
+            stream.with_std(|stream| {
+                match unsafe { libc::fcntl(stream.as_raw_fd(), libc::F_GETFD) } {
+                    -1 => Err(io::Error::last_os_error()),
+                    flags => Ok(flags),
+                }
+            })
+        });
+
+        assert_ne!(flags.unwrap() & libc::FD_CLOEXEC, 0);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn accepts_direct_descriptors_with_multishot() {
+        let mut ring = IoUring::new(8).unwrap();
+        ring.register_files_sparse(16).unwrap();
+        let rt = Runtime::with_ring(ring).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let clients = thread::spawn(move || {
+            (0..3)
+                .map(|_| {
+                    let mut stream = net::TcpStream::connect(addr).unwrap();
+                    let mut reply = [0; 2];
+                    stream.read_exact(&mut reply).unwrap();
+                    reply
+                })
+                .collect::<Vec<_>>()
+        });
+
+        rt.block_on(async {
+            let mut incoming = listener.incoming_direct();
+            for _ in 0..3 {
+                let stream = incoming.next().await.unwrap();
+                assert!(stream.is_direct());
+                assert!(stream.peer_addr().is_err());
+
+                let (res, _) = stream.write_all(b"hi".to_vec()).await;
+                res.unwrap();
+            }
+        });
+
+        assert_eq!(clients.join().unwrap(), vec![*b"hi"; 3]);
+    }
+
+    #[test]
+    fn plain_accept_after_direct_accept_in_the_same_slot() {
+        // With a single SQE, every request is prepared in the same slot
+        let mut ring = IoUring::new(1).unwrap();
+        ring.register_files_sparse(16).unwrap();
+        let rt = Runtime::with_ring(ring).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let clients = thread::spawn(move || {
+            let direct = net::TcpStream::connect(addr).unwrap();
+            let plain = net::TcpStream::connect(addr).unwrap();
+            (direct, plain.local_addr().unwrap())
+        });
+
+        let (stream, peer) = rt.block_on(async {
+            let (direct, _) = listener.accept_direct().await.unwrap();
+            assert!(direct.is_direct());
+            listener.accept().await.unwrap()
+        });
+
+        let (_direct, local) = clients.join().unwrap();
+        assert!(!stream.is_direct());
+        assert_eq!(peer, local);
+        // A direct descriptor wrapped as a regular one would not be the accepted socket
+        assert_eq!(stream.local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn sorts_addresses_alternating_families() {
+        let addrs: Vec<SocketAddr> = ["1.1.1.1:80", "2.2.2.2:80", "[::1]:80", "3.3.3.3:80"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+
+        let sorted = TcpConnector::new().sort(addrs.iter().copied());
+        assert_eq!(sorted, vec![addrs[2], addrs[0], addrs[1], addrs[3]]);
+
+        let sorted = TcpConnector::new()
+            .local_addr("127.0.0.1:0".parse().unwrap())
+            .sort(addrs.iter().copied());
+        assert_eq!(sorted, vec![addrs[0], addrs[1], addrs[3]]);
+    }
+
+    #[test]
+    fn connects_from_local_addr_after_failed_attempt() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Nothing listens on the port over IPv6, so the first attempt is refused
+        let refused = SocketAddr::new("::1".parse().unwrap(), addr.port());
+
+        let rt = Runtime::new().unwrap();
+        let stream = rt
+            .block_on(
+                TcpConnector::new()
+                    .timeout(Duration::from_secs(5))
+                    .local_addr("127.0.0.1:0".parse().unwrap())
+                    .local_addr("[::1]:0".parse().unwrap())
+                    .connect(&[refused, addr][..]),
+            )
+            .unwrap();
+
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        assert_eq!(stream.local_addr().unwrap(), peer);
+    }
+
+    #[test]
+    fn connect_times_out() {
+        // Once the accept queue is full, further connection requests are dropped
+        let listener = ListenerBuilder::new()
+            .backlog(0)
+            .bind("127.0.0.1:0")
+            .unwrap()
+            .remove(0);
+        let addr = listener.local_addr().unwrap();
+        let _queued: Vec<_> = (0..2)
+            .filter_map(|_| net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)).ok())
+            .collect();
+
+        let rt = Runtime::new().unwrap();
+        let err = rt
+            .block_on(TcpStream::connect(addr, Duration::from_millis(100)))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+}
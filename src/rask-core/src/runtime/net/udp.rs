@@ -0,0 +1,398 @@
+use std::{
+    fmt, io, mem,
+    net::{self, SocketAddr},
+    os::fd::{AsRawFd, RawFd},
+    ptr,
+    rc::Rc,
+};
+
+use rask_liburing::{BufRing, RecvMsgOut};
+
+use super::{from_socket_addr, result, to_socket_addr};
+use crate::runtime::{Multishot, Op};
+use crate::sys::unix::udp::{gro_segment_size, SegmentControl, SocketBuilder, MAX_SEGMENTS};
+
+/// The space given to control messages received alongside each datagram, enough for a `UDP_GRO`
+/// segment size.
+const CONTROL_LEN: usize = 64;
+
+/// A UDP socket, sending and receiving datagrams with requests submitted to the current runtime's
+/// ring.
+///
+/// Datagrams are received with a multishot request, into buffers selected from a [`BufRing`]
+/// registered with the runtime. Sends may be segmented with `UDP_SEGMENT`, handing the kernel
+/// several datagrams in a single request.
+#[derive(Debug)]
+pub struct UdpSocket {
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket {
+    /// Binds a socket to `addr`, with `UDP_GRO` enabled.
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = SocketBuilder::new().gro(true).cloexec(true).bind(addr)?;
+        Ok(Self::from_std(socket))
+    }
+
+    /// Wraps a socket bound elsewhere, i.e. with [`SocketBuilder`].
+    pub fn from_std(socket: net::UdpSocket) -> Self {
+        Self { inner: socket }
+    }
+
+    /// Gets the address the socket is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    /// Sends `buf` as a single datagram to `addr`, returning the number of bytes sent.
+    ///
+    /// See [sendmsg(2)](https://man.archlinux.org/man/sendmsg.2)
+    pub async fn send_to(&self, buf: Vec<u8>, addr: SocketAddr) -> (io::Result<usize>, Vec<u8>) {
+        self.send_msg(buf, addr, None).await
+    }
+
+    /// Sends `buf` to `addr` as datagrams of `segment_size` bytes, the last of which may be
+    /// shorter, with generic segmentation offload. Returns the number of bytes sent.
+    ///
+    /// `buf` may hold at most [`MAX_SEGMENTS`] datagrams.
+    pub async fn send_segments(
+        &self,
+        buf: Vec<u8>,
+        segment_size: u16,
+        addr: SocketAddr,
+    ) -> (io::Result<usize>, Vec<u8>) {
+        if segment_size == 0 || buf.len().div_ceil(segment_size as usize) > MAX_SEGMENTS {
+            let err = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer must hold between 1 and 64 segments",
+            );
+            return (Err(err), buf);
+        }
+
+        self.send_msg(buf, addr, Some(SegmentControl::new(segment_size)))
+            .await
+    }
+
+    async fn send_msg(
+        &self,
+        buf: Vec<u8>,
+        addr: SocketAddr,
+        control: Option<SegmentControl>,
+    ) -> (io::Result<usize>, Vec<u8>) {
+        let fd = self.as_raw_fd();
+        let (addr, addr_len) = from_socket_addr(addr);
+        let send = Box::new(SendMsg {
+            buf,
+            addr,
+            control,
+            // SAFETY: iovec and msghdr are plain data, for which zero is a valid value
+            iov: unsafe { mem::zeroed() },
+            msg: unsafe { mem::zeroed() },
+        });
+
+        // SAFETY: the message and everything it refers to is on the heap, owned by the operation
+        let op = unsafe {
+            Op::try_submit(send, |send, sqe| {
+                let send = &mut **send;
+                send.iov.iov_base = send.buf.as_mut_ptr().cast();
+                send.iov.iov_len = send.buf.len();
+                send.msg.msg_name = ptr::addr_of_mut!(send.addr).cast();
+                send.msg.msg_namelen = addr_len;
+                send.msg.msg_iov = &mut send.iov;
+                send.msg.msg_iovlen = 1;
+                if let Some(control) = &send.control {
+                    send.msg.msg_control = control.as_ptr().cast_mut();
+                    send.msg.msg_controllen = control.len();
+                }
+                sqe.prep_sendmsg(fd, &send.msg);
+            })
+        };
+
+        match op {
+            Ok(op) => {
+                let (cqe, send) = op.await;
+                (result(cqe).map(|n| n as usize), send.buf)
+            }
+            Err((e, send)) => (Err(e), send.buf),
+        }
+    }
+
+    /// Receives datagrams with a multishot recvmsg request, into buffers selected from
+    /// `buf_ring`, which must have been registered with
+    /// [`register_buf_ring`](crate::runtime::register_buf_ring).
+    ///
+    /// Each buffer must be large enough for the `io_uring_recvmsg_out` header, the source address,
+    /// and control messages, as well as the payload.
+    ///
+    /// See [io_uring_prep_recvmsg_multishot(3)](https://man.archlinux.org/man/io_uring_prep_recvmsg_multishot.3)
+    pub fn recv_multishot(&self, buf_ring: Rc<BufRing>) -> Recv<'_> {
+        Recv {
+            socket: self,
+            buf_ring,
+            op: None,
+        }
+    }
+}
+
+impl From<net::UdpSocket> for UdpSocket {
+    fn from(socket: net::UdpSocket) -> Self {
+        Self::from_std(socket)
+    }
+}
+
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+/// The resources of a sendmsg request.
+struct SendMsg {
+    buf: Vec<u8>,
+    addr: libc::sockaddr_storage,
+    control: Option<SegmentControl>,
+    iov: libc::iovec,
+    msg: libc::msghdr,
+}
+
+/// The `msghdr` of a multishot recvmsg request, sizing the space given to the source address and
+/// control messages in each buffer.
+fn recv_msghdr() -> libc::msghdr {
+    // SAFETY: msghdr is plain data, for which zero is a valid value
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_controllen = CONTROL_LEN;
+    msg
+}
+
+/// Datagrams received by [`UdpSocket::recv_multishot`].
+///
+/// If the buffer ring runs out of buffers, the request terminates, and [`Recv::next`] returns an
+/// error. The request is submitted again by the next call, once buffers have been recycled by
+/// dropping received [`Datagram`]s.
+pub struct Recv<'a> {
+    socket: &'a UdpSocket,
+    buf_ring: Rc<BufRing>,
+    op: Option<Multishot<Box<libc::msghdr>>>,
+}
+
+impl Recv<'_> {
+    /// Waits for the next datagram.
+    pub async fn next(&mut self) -> io::Result<Datagram> {
+        loop {
+            let op = match &mut self.op {
+                Some(op) if !op.is_terminated() => op,
+                op => {
+                    let fd = self.socket.as_raw_fd();
+                    let group = self.buf_ring.group();
+                    // SAFETY: the msghdr is on the heap, owned by the operation
+                    let recv = unsafe {
+                        Multishot::submit(Box::new(recv_msghdr()), |msg, sqe| {
+                            sqe.prep_recvmsg_multishot(fd, msg, group);
+                        })
+                    }?;
+
+                    // Buffers selected for completions which will never be yielded go back to
+                    // the kernel, including those arriving until the request is cancelled
+                    let buf_ring = self.buf_ring.clone();
+                    op.insert(recv.on_orphaned(move |cqe| {
+                        if let Some(id) = cqe.flags().buffer_id() {
+                            buf_ring.recycle(id);
+                        }
+                    }))
+                }
+            };
+
+            let Some(cqe) = op.next().await else {
+                continue;
+            };
+
+            let len = result(cqe)? as usize;
+            let Some(id) = cqe.flags().buffer_id() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no buffer was selected for the datagram",
+                ));
+            };
+
+            return Datagram::new(self.buf_ring.clone(), id, len);
+        }
+    }
+}
+
+impl fmt::Debug for Recv<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recv")
+            .field("socket", &self.socket)
+            .field("buf_ring", &self.buf_ring)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A datagram received by [`UdpSocket::recv_multishot`], or several datagrams of the same size
+/// coalesced by `UDP_GRO`.
+///
+/// The buffer the datagram was received into is recycled once it is dropped.
+#[derive(Debug)]
+pub struct Datagram {
+    buf_ring: Rc<BufRing>,
+    id: u16,
+    len: usize,
+    payload: usize,
+    peer: SocketAddr,
+    segment_size: Option<u16>,
+    truncated: bool,
+}
+
+impl Datagram {
+    fn new(buf_ring: Rc<BufRing>, id: u16, len: usize) -> io::Result<Self> {
+        // SAFETY: the buffer was selected by the completion, and is recycled once parsed, or when
+        // the datagram is dropped
+        let buf = unsafe { buf_ring.buffer(id, len) };
+        let Some(out) = RecvMsgOut::parse(buf, &recv_msghdr()) else {
+            buf_ring.recycle(id);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "received message is too short",
+            ));
+        };
+
+        // SAFETY: sockaddr_storage is plain data, for which zero is a valid value
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let name = out.name();
+        // SAFETY: the name is no longer than the storage, as sized by the msghdr
+        unsafe {
+            ptr::copy_nonoverlapping(name.as_ptr(), ptr::addr_of_mut!(storage).cast(), name.len());
+        }
+
+        let peer = match to_socket_addr(&storage, name.len() as libc::socklen_t) {
+            Ok(peer) => peer,
+            Err(e) => {
+                buf_ring.recycle(id);
+                return Err(e);
+            }
+        };
+        let segment_size = gro_segment_size(out.control());
+        let truncated = out.is_payload_truncated();
+        let payload = len - out.payload().len();
+
+        Ok(Self {
+            buf_ring,
+            id,
+            len,
+            payload,
+            peer,
+            segment_size,
+            truncated,
+        })
+    }
+
+    /// The address the datagram was sent from.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// The payload of the datagram, or of every coalesced datagram.
+    pub fn payload(&self) -> &[u8] {
+        // SAFETY: the buffer is not recycled until the datagram is dropped
+        unsafe { &self.buf_ring.buffer(self.id, self.len)[self.payload..] }
+    }
+
+    /// The size of the datagrams coalesced by `UDP_GRO`, or `None` if a single datagram was
+    /// received.
+    pub fn segment_size(&self) -> Option<u16> {
+        self.segment_size
+    }
+
+    /// Iterates over each datagram coalesced into the payload.
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        let payload = self.payload();
+        let size = self.segment_size.map_or(payload.len(), usize::from);
+        payload.chunks(size.max(1))
+    }
+
+    /// Whether the payload was longer than the buffer could hold, and was truncated.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Drop for Datagram {
+    fn drop(&mut self) {
+        self.buf_ring.recycle(self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, rc::Rc, time::Duration};
+
+    use rask_liburing::BufRing;
+
+    use super::UdpSocket;
+    use crate::runtime::{register_buf_ring, time::sleep, Runtime};
+
+    #[test]
+    fn receives_segmented_sends_coalesced() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let buf_ring = Rc::new(BufRing::new(8, 4096, 0).unwrap());
+            register_buf_ring(buf_ring.clone()).unwrap();
+
+            let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+            let receiver = UdpSocket::bind(addr).unwrap();
+            let sender = UdpSocket::bind(addr).unwrap();
+            let to = receiver.local_addr().unwrap();
+            let mut recv = receiver.recv_multishot(buf_ring);
+
+            let (res, _) = sender.send_to(b"ping".to_vec(), to).await;
+            assert_eq!(res.unwrap(), 4);
+            let datagram = recv.next().await.unwrap();
+            assert_eq!(datagram.payload(), b"ping");
+            assert_eq!(datagram.peer(), sender.local_addr().unwrap());
+            assert_eq!(datagram.segment_size(), None);
+            drop(datagram);
+
+            let (res, _) = sender.send_segments(vec![1; 250], 100, to).await;
+            assert_eq!(res.unwrap(), 250);
+            let datagram = recv.next().await.unwrap();
+            assert_eq!(datagram.segment_size(), Some(100));
+            let lens: Vec<_> = datagram.segments().map(<[u8]>::len).collect();
+            assert_eq!(lens, [100, 100, 50]);
+            assert!(!datagram.is_truncated());
+        });
+    }
+
+    #[test]
+    fn recycles_buffers_of_completions_after_drop() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let buf_ring = Rc::new(BufRing::new(4, 4096, 0).unwrap());
+            register_buf_ring(buf_ring.clone()).unwrap();
+
+            let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+            let receiver = UdpSocket::bind(addr).unwrap();
+            let sender = std::net::UdpSocket::bind(addr).unwrap();
+            let to = receiver.local_addr().unwrap();
+
+            // Datagrams received after the first are never yielded
+            let mut recv = receiver.recv_multishot(buf_ring.clone());
+            for _ in 0..3 {
+                sender.send_to(b"ping", to).unwrap();
+            }
+            drop(recv.next().await.unwrap());
+            drop(recv);
+            sleep(Duration::from_millis(50)).await;
+
+            // Every buffer is available to hold datagrams at once
+            let mut recv = receiver.recv_multishot(buf_ring);
+            for _ in 0..4 {
+                sender.send_to(b"pong", to).unwrap();
+            }
+            let mut datagrams = Vec::new();
+            while datagrams.len() < 4 {
+                datagrams.push(recv.next().await.unwrap());
+            }
+        });
+    }
+}
@@ -0,0 +1,539 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use rask_liburing::{CompletionEntry, CqeFlags, SqeFlags, SubmissionEntry};
+
+use super::CONTEXT;
+use crate::sys::unix::io::{next_sqe, reserve};
+
+/// Handles the completions of an [`Op`] or [`Multishot`] which was dropped before its final one.
+type OnOrphaned = Box<dyn FnMut(&CompletionEntry)>;
+
+/// Where an in-flight ring operation is in its life.
+pub(crate) enum Lifecycle {
+    /// Submitted, but not yet polled.
+    Submitted,
+    /// Polled, waiting for the completion to wake the task.
+    Waiting(Waker),
+    /// Completed, waiting for the task to be polled.
+    Completed(CompletionEntry),
+    /// The [`Op`] was dropped before completing. The resources the kernel may still be using are
+    /// kept alive here until the completion arrives.
+    Ignored(Box<dyn Any>),
+    /// An [`Op`] or [`Multishot`] dropped before its final completion. Like
+    /// [`Lifecycle::Ignored`], its resources are kept alive until then, while its late completions
+    /// are handed to `f`.
+    Orphaned { data: Box<dyn Any>, f: OnOrphaned },
+    /// A [`Multishot`] operation, buffering completions until they are polled.
+    Multishot {
+        cqes: VecDeque<CompletionEntry>,
+        waker: Option<Waker>,
+    },
+}
+
+impl Lifecycle {
+    /// Whether the kernel has yet to post the operation's final completion.
+    pub(crate) fn is_in_flight(&self) -> bool {
+        match self {
+            Lifecycle::Completed(_) => false,
+            Lifecycle::Multishot { cqes, .. } => cqes
+                .back()
+                .is_none_or(|cqe| cqe.flags().contains(CqeFlags::More)),
+            _ => true,
+        }
+    }
+
+    /// Records the completion, returning `true` if the entry is no longer needed.
+    pub(crate) fn complete(&mut self, cqe: CompletionEntry) -> bool {
+        let more = cqe.flags().contains(CqeFlags::More);
+        match self {
+            Lifecycle::Multishot { cqes, waker } => {
+                cqes.push_back(cqe);
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+                return false;
+            }
+            // A dropped multishot operation holds on to its resources until the final completion
+            Lifecycle::Ignored(_) if more => return false,
+            Lifecycle::Orphaned { f, .. } if more => {
+                f(&cqe);
+                return false;
+            }
+            _ => {}
+        }
+
+        match std::mem::replace(self, Lifecycle::Completed(cqe)) {
+            Lifecycle::Submitted => false,
+            Lifecycle::Waiting(waker) => {
+                waker.wake();
+                false
+            }
+            Lifecycle::Completed(_) | Lifecycle::Multishot { .. } => {
+                unreachable!("operation completed twice")
+            }
+            Lifecycle::Ignored(data) => {
+                drop(data);
+                true
+            }
+            Lifecycle::Orphaned { data, mut f } => {
+                f(&cqe);
+                drop(data);
+                true
+            }
+        }
+    }
+}
+
+/// A single-shot operation on the current runtime's ring, resolving once its CQE is reaped.
+///
+/// The operation owns `data`, which holds any resources referenced by the SQE, and hands it back
+/// alongside the completion. If the `Op` is dropped before completing, the operation is cancelled,
+/// and `data` is kept alive until the kernel is done with it.
+pub struct Op<T: 'static> {
+    key: Option<usize>,
+    data: Option<T>,
+    orphaned: Option<OnOrphaned>,
+}
+
+// `data` is never pinned, it is only handed back by value.
+impl<T> Unpin for Op<T> {}
+
+impl<T: 'static> Op<T> {
+    /// Prepares an SQE on the current runtime's ring with `prep`, which is given access to `data`.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](super::Runtime).
+    ///
+    /// # Safety
+    /// Any memory referenced by the SQE must be owned by `data`, and must not move when `data` is
+    /// moved, i.e. it lives on the heap. The SQE must not be multishot, nor carry user_data, which
+    /// is set by the runtime.
+    pub unsafe fn submit<F>(data: T, prep: F) -> io::Result<Self>
+    where
+        F: FnOnce(&mut T, &mut SubmissionEntry<'_>),
+    {
+        Self::try_submit(data, prep).map_err(|(e, _)| e)
+    }
+
+    /// Like [`Op::submit`], but hands `data` back if the SQE could not be prepared.
+    ///
+    /// # Safety
+    /// As for [`Op::submit`].
+    pub(crate) unsafe fn try_submit<F>(mut data: T, prep: F) -> Result<Self, (io::Error, T)>
+    where
+        F: FnOnce(&mut T, &mut SubmissionEntry<'_>),
+    {
+        CONTEXT.with_borrow(|cx| {
+            let cx = cx.as_ref().expect("no runtime is running on this thread");
+            let mut ops = cx.ops.borrow_mut();
+            let entry = ops.vacant_entry();
+            let key = entry.key();
+
+            let mut ring = cx.ring.borrow_mut();
+            let mut sqe = match next_sqe(&mut ring) {
+                Ok(sqe) => sqe,
+                Err(e) => return Err((e, data)),
+            };
+            prep(&mut data, &mut sqe);
+            sqe.set_user_data(key as u64);
+            entry.insert(Lifecycle::Submitted);
+
+            Ok(Self::new(key, data))
+        })
+    }
+
+    fn new(key: usize, data: T) -> Self {
+        Self {
+            key: Some(key),
+            data: Some(data),
+            orphaned: None,
+        }
+    }
+
+    /// Hands `f` the completion the `Op` does not resolve with because it was dropped first, i.e.
+    /// to close the descriptor it created.
+    pub(crate) fn on_orphaned(mut self, f: impl FnMut(&CompletionEntry) + 'static) -> Self {
+        self.orphaned = Some(Box::new(f));
+        self
+    }
+}
+
+impl<T: 'static> Op<T> {
+    /// Like [`Op::submit`], but links a timeout to the SQE. If the operation has not completed
+    /// once `timeout` has elapsed, it is cancelled, and completes with `-ECANCELED`.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](super::Runtime).
+    ///
+    /// # Safety
+    /// As for [`Op::submit`].
+    pub unsafe fn submit_with_timeout<F>(
+        mut data: T,
+        timeout: Duration,
+        prep: F,
+    ) -> io::Result<Self>
+    where
+        F: FnOnce(&mut T, &mut SubmissionEntry<'_>),
+    {
+        CONTEXT.with_borrow(|cx| {
+            let cx = cx.as_ref().expect("no runtime is running on this thread");
+            let mut ops = cx.ops.borrow_mut();
+            let mut ring = cx.ring.borrow_mut();
+
+            // The link only holds if both SQEs are submitted together
+            reserve(&mut ring, 2)?;
+
+            let key = ops.insert(Lifecycle::Submitted);
+            let mut sqe = match ring.get_sqe() {
+                Ok(sqe) => sqe,
+                Err(e) => {
+                    ops.remove(key);
+                    return Err(io::Error::other(e));
+                }
+            };
+            prep(&mut data, &mut sqe);
+            sqe.add_flags(SqeFlags::IoLink).set_user_data(key as u64);
+
+            // The timespec is read when the timeout is submitted, and is kept alive until its
+            // completion is reaped.
+            let mut ts = Box::new(libc::timespec {
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_nsec: timeout.subsec_nanos() as libc::c_long,
+            });
+            let mut sqe = ring.get_sqe().map_err(io::Error::other)?;
+            sqe.prep_link_timeout(&mut ts);
+            sqe.set_user_data(ops.insert(Lifecycle::Ignored(ts)) as u64);
+
+            Ok(Self::new(key, data))
+        })
+    }
+}
+
+impl<T: 'static> Op<T> {
+    /// Like [`Op::submit`], but links a second operation, prepared with `next_prep`, to the SQE.
+    /// The second is only started once the first has completed in full, and otherwise completes
+    /// with `-ECANCELED`.
+    ///
+    /// # Safety
+    /// As for [`Op::submit`], for both `data` and `next_data`.
+    pub(crate) unsafe fn submit_linked<U, F, G>(
+        mut data: T,
+        prep: F,
+        mut next_data: U,
+        next_prep: G,
+    ) -> io::Result<(Self, Op<U>)>
+    where
+        U: 'static,
+        F: FnOnce(&mut T, &mut SubmissionEntry<'_>),
+        G: FnOnce(&mut U, &mut SubmissionEntry<'_>),
+    {
+        CONTEXT.with_borrow(|cx| {
+            let cx = cx.as_ref().expect("no runtime is running on this thread");
+            let mut ops = cx.ops.borrow_mut();
+            let mut ring = cx.ring.borrow_mut();
+
+            // The link only holds if both SQEs are submitted together
+            reserve(&mut ring, 2)?;
+
+            let key = ops.insert(Lifecycle::Submitted);
+            let next_key = ops.insert(Lifecycle::Submitted);
+            let mut sqe = match ring.get_sqe() {
+                Ok(sqe) => sqe,
+                Err(e) => {
+                    ops.remove(key);
+                    ops.remove(next_key);
+                    return Err(io::Error::other(e));
+                }
+            };
+            prep(&mut data, &mut sqe);
+            sqe.add_flags(SqeFlags::IoLink).set_user_data(key as u64);
+
+            // There is room for the second, as checked above
+            let mut sqe = ring.get_sqe().map_err(io::Error::other)?;
+            next_prep(&mut next_data, &mut sqe);
+            sqe.set_user_data(next_key as u64);
+
+            Ok((Self::new(key, data), Op::new(next_key, next_data)))
+        })
+    }
+}
+
+impl<T: 'static> Future for Op<T> {
+    type Output = (CompletionEntry, T);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let key = this.key.expect("polled after completion");
+
+        CONTEXT.with_borrow(|rt| {
+            let rt = rt.as_ref().expect("no runtime is running on this thread");
+            let mut ops = rt.ops.borrow_mut();
+
+            match &mut ops[key] {
+                Lifecycle::Completed(cqe) => {
+                    let cqe = *cqe;
+                    ops.remove(key);
+                    this.key = None;
+                    Poll::Ready((cqe, this.data.take().unwrap()))
+                }
+                lifecycle => {
+                    *lifecycle = Lifecycle::Waiting(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<T: 'static> Drop for Op<T> {
+    fn drop(&mut self) {
+        let Some(key) = self.key else {
+            return;
+        };
+
+        let data = self.data.take();
+        let mut f = self.orphaned.take();
+        let orphaned = CONTEXT.with_borrow(|rt| {
+            let Some(rt) = rt.as_ref() else {
+                return Some(data);
+            };
+
+            let mut ops = rt.ops.borrow_mut();
+            match &ops[key] {
+                &Lifecycle::Completed(cqe) => {
+                    ops.remove(key);
+                    drop(ops);
+                    if let Some(f) = &mut f {
+                        f(&cqe);
+                    }
+                }
+                _ => {
+                    let data = Box::new(data);
+                    ops[key] = match f {
+                        Some(f) => Lifecycle::Orphaned { data, f },
+                        None => Lifecycle::Ignored(data),
+                    };
+                    drop(ops);
+                    submit_untracked(move |sqe| {
+                        sqe.prep_cancel(key as u64);
+                    });
+                }
+            }
+            None
+        });
+
+        // Outside of the runtime, the kernel may still be using the resources held by `data`, and
+        // there is nowhere to keep them.
+        std::mem::forget(orphaned);
+    }
+}
+
+/// A multishot operation on the current runtime's ring, yielding a CQE for each completion until
+/// the kernel terminates it.
+///
+/// Like [`Op`], the operation owns `data`, which is kept alive until the final completion. If the
+/// `Multishot` is dropped before then, the operation is cancelled.
+pub struct Multishot<T: 'static> {
+    key: Option<usize>,
+    data: Option<T>,
+    orphaned: Option<OnOrphaned>,
+}
+
+impl<T: 'static> Multishot<T> {
+    /// Prepares a multishot SQE on the current runtime's ring with `prep`, which is given access
+    /// to `data`.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](super::Runtime).
+    ///
+    /// # Safety
+    /// As for [`Op::submit`], except the SQE must be multishot.
+    pub unsafe fn submit<F>(mut data: T, prep: F) -> io::Result<Self>
+    where
+        F: FnOnce(&mut T, &mut SubmissionEntry<'_>),
+    {
+        CONTEXT.with_borrow(|cx| {
+            let cx = cx.as_ref().expect("no runtime is running on this thread");
+            let mut ops = cx.ops.borrow_mut();
+            let entry = ops.vacant_entry();
+            let key = entry.key();
+
+            let mut ring = cx.ring.borrow_mut();
+            let mut sqe = next_sqe(&mut ring)?;
+            prep(&mut data, &mut sqe);
+            sqe.set_user_data(key as u64);
+            entry.insert(Lifecycle::Multishot {
+                cqes: VecDeque::new(),
+                waker: None,
+            });
+
+            Ok(Self {
+                key: Some(key),
+                data: Some(data),
+                orphaned: None,
+            })
+        })
+    }
+
+    /// Hands `f` each completion the `Multishot` does not yield because it was dropped first,
+    /// whether buffered or arriving until the final one, i.e. to recycle the buffers they
+    /// selected.
+    pub(crate) fn on_orphaned(mut self, f: impl FnMut(&CompletionEntry) + 'static) -> Self {
+        self.orphaned = Some(Box::new(f));
+        self
+    }
+
+    /// Whether the kernel has terminated the operation, after which it yields no more CQEs.
+    pub fn is_terminated(&self) -> bool {
+        self.key.is_none()
+    }
+
+    /// Polls for the next completion, returning `None` once the final one has been yielded.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<CompletionEntry>> {
+        let Some(key) = self.key else {
+            return Poll::Ready(None);
+        };
+
+        CONTEXT.with_borrow(|rt| {
+            let rt = rt.as_ref().expect("no runtime is running on this thread");
+            let mut ops = rt.ops.borrow_mut();
+
+            let Lifecycle::Multishot { cqes, waker } = &mut ops[key] else {
+                unreachable!("multishot operation is not tracked as one");
+            };
+            let Some(cqe) = cqes.pop_front() else {
+                *waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+
+            if !cqe.flags().contains(CqeFlags::More) {
+                ops.remove(key);
+                self.key = None;
+            }
+            Poll::Ready(Some(cqe))
+        })
+    }
+
+    /// Waits for the next completion, returning `None` once the final one has been yielded.
+    pub async fn next(&mut self) -> Option<CompletionEntry> {
+        std::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl<T: 'static> Drop for Multishot<T> {
+    fn drop(&mut self) {
+        let Some(key) = self.key else {
+            return;
+        };
+
+        let data = self.data.take();
+        let mut f = self.orphaned.take();
+        let orphaned = CONTEXT.with_borrow(|rt| {
+            let Some(rt) = rt.as_ref() else {
+                return Some(data);
+            };
+
+            let mut ops = rt.ops.borrow_mut();
+            let Lifecycle::Multishot { cqes, .. } = &mut ops[key] else {
+                unreachable!("multishot operation is not tracked as one");
+            };
+            let terminated = cqes
+                .back()
+                .is_some_and(|cqe| !cqe.flags().contains(CqeFlags::More));
+            if let Some(f) = &mut f {
+                cqes.iter().for_each(&mut **f);
+            }
+            if terminated {
+                ops.remove(key);
+                return None;
+            }
+
+            // The rest of the completions are reaped once cancelled
+            let data = Box::new(data);
+            ops[key] = match f {
+                Some(f) => Lifecycle::Orphaned { data, f },
+                None => Lifecycle::Ignored(data),
+            };
+            drop(ops);
+            submit_untracked(move |sqe| {
+                sqe.prep_cancel(key as u64);
+            });
+            None
+        });
+
+        std::mem::forget(orphaned);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Op<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Op")
+            .field("key", &self.key)
+            .field("data", &self.data)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Multishot<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multishot")
+            .field("key", &self.key)
+            .field("data", &self.data)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The user_data of requests whose completion is not tracked, which are reaped and discarded.
+pub(crate) const UNTRACKED: u64 = u64::MAX;
+
+/// Prepares an SQE on the current runtime's ring with `prep`, whose completion is discarded.
+/// Does nothing outside of a runtime, or if no SQE is available. While the runtime is reaping
+/// completions, i.e. from the handler of an orphaned operation, it is prepared once the runtime
+/// next parks.
+///
+/// Any memory referenced by the SQE must outlive the request.
+pub(crate) fn submit_untracked(prep: impl FnOnce(&mut SubmissionEntry<'_>) + 'static) {
+    CONTEXT.with_borrow(|cx| {
+        let Some(cx) = cx.as_ref() else {
+            return;
+        };
+
+        let Ok(mut ring) = cx.ring.try_borrow_mut() else {
+            // Completions are being reaped, whose handlers may submit requests
+            cx.deferred.borrow_mut().push(Box::new(|sqe| {
+                prep(sqe);
+                sqe.set_user_data(UNTRACKED);
+            }));
+            return;
+        };
+        if let Ok(mut sqe) = next_sqe(&mut ring) {
+            prep(&mut sqe);
+            sqe.set_user_data(UNTRACKED);
+        }
+    });
+}
+
+/// Submits a no-op to the ring, completing once the kernel has processed it.
+pub async fn nop() -> io::Result<()> {
+    // SAFETY: a nop references no memory
+    let (cqe, ()) = unsafe {
+        Op::submit((), |_, sqe| {
+            sqe.prep_nop();
+        })
+    }?
+    .await;
+
+    match cqe.result() {
+        res if res < 0 => Err(io::Error::from_raw_os_error(-res)),
+        _ => Ok(()),
+    }
+}
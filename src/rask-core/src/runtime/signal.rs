@@ -0,0 +1,138 @@
+//! Signals received by reading a [`SignalFd`] on the current runtime's ring, rather than by a
+//! handler interrupting whichever thread they land on.
+//!
+//! A signal sent to the process is delivered to one thread which does not block it. So that
+//! signals are only ever received through a [`Signals`] stream, they must be blocked on every
+//! thread, i.e. with [`ThreadPerCore::block_signals`](super::ThreadPerCore::block_signals). Each
+//! signal sent to the process is then read by one stream, whichever reads it first.
+//!
+//! ```no_run
+//! # use rask_core::runtime::{signal, Runtime};
+//! let rt = Runtime::new().unwrap();
+//! rt.block_on(async {
+//!     let mut signals = signal::signals(&[libc::SIGHUP, libc::SIGTERM]).unwrap();
+//!     loop {
+//!         match signals.recv().await.unwrap() {
+//!             libc::SIGHUP => { /* reload the configuration */ }
+//!             _ => break,
+//!         }
+//!     }
+//! });
+//! ```
+
+use std::{
+    future::{self, Future},
+    io,
+    os::fd::AsRawFd,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub use crate::sys::unix::signal::SignalFd;
+
+use super::Op;
+use crate::sys::unix::signal::SIGINFO_LEN;
+
+/// Receives `signals` on the current runtime, blocking them on the calling thread.
+pub fn signals(signals: &[libc::c_int]) -> io::Result<Signals> {
+    Ok(Signals::new(SignalFd::new(signals)?))
+}
+
+/// A stream of signals, returned by [`signals`].
+#[derive(Debug)]
+pub struct Signals {
+    /// Dropped before the file descriptor, cancelling the read.
+    read: Option<Op<Box<[u8]>>>,
+    buf: Option<Box<[u8]>>,
+    fd: SignalFd,
+}
+
+impl Signals {
+    /// Receives the signals read from `fd`.
+    pub fn new(fd: SignalFd) -> Self {
+        Self {
+            read: None,
+            buf: Some(vec![0; SIGINFO_LEN].into_boxed_slice()),
+            fd,
+        }
+    }
+
+    /// Polls for the next signal, returning its number.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](super::Runtime).
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<libc::c_int>> {
+        let read = match &mut self.read {
+            Some(read) => read,
+            None => {
+                let buf = self.buf.take().expect("signal buffer is held by the read");
+                let fd = self.fd.as_raw_fd();
+                // SAFETY: the buffer is boxed, and owned by the operation
+                let read = unsafe {
+                    Op::submit(buf, |buf, sqe| {
+                        sqe.prep_read(fd, buf, u64::MAX);
+                    })
+                };
+                match read {
+                    Ok(read) => self.read.insert(read),
+                    Err(e) => {
+                        // The buffer is lost with the closure, so is replaced
+                        self.buf = Some(vec![0; SIGINFO_LEN].into_boxed_slice());
+                        return Poll::Ready(Err(e));
+                    }
+                }
+            }
+        };
+
+        let Poll::Ready((cqe, buf)) = Pin::new(read).poll(cx) else {
+            return Poll::Pending;
+        };
+        self.read = None;
+
+        let res = cqe.result();
+        let signal = if res < 0 {
+            Err(io::Error::from_raw_os_error(-res))
+        } else {
+            SignalFd::signal(&buf[..res as usize]).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "short read from signalfd")
+            })
+        };
+        self.buf = Some(buf);
+
+        Poll::Ready(signal)
+    }
+
+    /// Waits for the next signal, returning its number.
+    ///
+    /// # Panics
+    /// Panics if called outside of a [`Runtime`](super::Runtime).
+    pub async fn recv(&mut self) -> io::Result<libc::c_int> {
+        future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::signals;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn receives_signals_through_the_ring() {
+        // Signals are blocked on a thread of its own, so other tests' threads are unaffected
+        thread::spawn(|| {
+            let rt = Runtime::new().unwrap();
+            rt.block_on(async {
+                let mut signals = signals(&[libc::SIGUSR1, libc::SIGUSR2]).unwrap();
+                unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR2) };
+                assert_eq!(signals.recv().await.unwrap(), libc::SIGUSR2);
+
+                unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR1) };
+                assert_eq!(signals.recv().await.unwrap(), libc::SIGUSR1);
+            });
+        })
+        .join()
+        .unwrap();
+    }
+}
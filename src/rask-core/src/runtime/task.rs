@@ -0,0 +1,199 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    future::Future,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    pin::Pin,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+pub(crate) type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Ids of tasks which have been woken, and whether the future passed to `block_on` has been.
+///
+/// Wakers may be sent to and woken from other threads, i.e. by
+/// [`spawn_blocking`](super::spawn_blocking), so the queue is shared. While the runtime is parked
+/// waiting on its ring, waking a task writes to an eventfd the ring has a read armed on, so the
+/// task resumes on the runtime's own thread.
+#[derive(Debug)]
+pub(crate) struct Queue {
+    ready: Mutex<VecDeque<usize>>,
+    main: AtomicBool,
+    /// Set while the runtime is parked, and cleared by whichever wake notifies it.
+    parked: AtomicBool,
+    eventfd: OwnedFd,
+}
+
+impl Queue {
+    pub(crate) fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ready: Mutex::new(VecDeque::new()),
+            main: AtomicBool::new(true),
+            parked: AtomicBool::new(false),
+            // SAFETY: the descriptor was just created, and is owned by nothing else
+            eventfd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    pub(crate) fn push(&self, id: usize) {
+        self.ready.lock().unwrap().push_back(id);
+        self.notify();
+    }
+
+    /// The eventfd written to when the runtime is woken while parked.
+    pub(crate) fn eventfd(&self) -> RawFd {
+        self.eventfd.as_raw_fd()
+    }
+
+    /// Marks the runtime as about to park, returning `false` if a task has been woken since it
+    /// last checked, in which case it must not.
+    pub(crate) fn park(&self) -> bool {
+        self.parked.store(true, Ordering::SeqCst);
+        if self.is_empty() {
+            return true;
+        }
+
+        self.parked.store(false, Ordering::SeqCst);
+        false
+    }
+
+    /// Marks the runtime as no longer parked.
+    pub(crate) fn unpark(&self) {
+        self.parked.store(false, Ordering::SeqCst);
+    }
+
+    /// Wakes the runtime, if it is parked.
+    fn notify(&self) {
+        if self.parked.swap(false, Ordering::SeqCst) {
+            let one = 1u64;
+            // A failed write leaves the counter as it was, which can only be non-zero
+            unsafe {
+                libc::write(
+                    self.eventfd.as_raw_fd(),
+                    (&one as *const u64).cast(),
+                    mem::size_of::<u64>(),
+                )
+            };
+        }
+    }
+
+    pub(crate) fn take(&self) -> VecDeque<usize> {
+        mem::take(&mut *self.ready.lock().unwrap())
+    }
+
+    pub(crate) fn take_main(&self) -> bool {
+        self.main.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.main.load(Ordering::SeqCst) && self.ready.lock().unwrap().is_empty()
+    }
+}
+
+/// Wakes a task by queueing its id to be polled.
+#[derive(Debug)]
+pub(crate) struct TaskWaker {
+    id: Option<usize>,
+    queue: Arc<Queue>,
+}
+
+impl TaskWaker {
+    pub(crate) fn task(id: usize, queue: Arc<Queue>) -> Waker {
+        Waker::from(Arc::new(Self {
+            id: Some(id),
+            queue,
+        }))
+    }
+
+    pub(crate) fn main(queue: Arc<Queue>) -> Waker {
+        Waker::from(Arc::new(Self { id: None, queue }))
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        match self.id {
+            Some(id) => self.queue.push(id),
+            None => {
+                self.queue.main.store(true, Ordering::SeqCst);
+                self.queue.notify();
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// An owned handle to a spawned task, resolving to its output once it completes.
+///
+/// Dropping the handle detaches the task, which continues to run to completion.
+#[derive(Debug)]
+pub struct JoinHandle<T> {
+    state: Rc<RefCell<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Determines if the task has completed.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().output.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.borrow_mut();
+
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Wraps `future` into a task, storing its output for the returned [`JoinHandle`].
+pub(crate) fn task<F>(future: F) -> (Task, JoinHandle<F::Output>)
+where
+    F: Future + 'static,
+{
+    let state = Rc::new(RefCell::new(JoinState {
+        output: None,
+        waker: None,
+    }));
+    let task_state = state.clone();
+
+    let task = Box::pin(async move {
+        let output = future.await;
+
+        let mut state = task_state.borrow_mut();
+        state.output = Some(output);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+
+    (task, JoinHandle { state })
+}
@@ -0,0 +1,247 @@
+use std::{
+    future::Future,
+    io, mem,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    sync::Arc,
+    thread,
+};
+
+use super::Runtime;
+use crate::sys::unix::{signal, tcp};
+
+/// Runs one single-threaded [`Runtime`], each with its own ring, per CPU.
+///
+/// Every worker thread is given its own listener for each address, bound with `SO_REUSEPORT` so the
+/// kernel spreads incoming connections across workers without a shared accept queue. Worker `n` is
+/// optionally pinned to the `n`th CPU the process may run on, and connections optionally steered
+/// to the worker on the CPU that handled them with [`tcp::steer_by_cpu`].
+///
+/// ```no_run
+/// # use rask_core::runtime::ThreadPerCore;
+/// let workers = ThreadPerCore::new()
+///     .pin_threads(true)
+///     .steer_by_cpu(true)
+///     .spawn("[::]:8080", |_worker, _listeners| async move {
+///         // accept and serve connections
+///     })
+///     .unwrap();
+///
+/// workers.join().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThreadPerCore {
+    threads: usize,
+    pin_threads: bool,
+    steer_by_cpu: bool,
+    signals: Vec<libc::c_int>,
+}
+
+impl Default for ThreadPerCore {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            pin_threads: false,
+            steer_by_cpu: false,
+            signals: Vec::new(),
+        }
+    }
+}
+
+impl ThreadPerCore {
+    /// Configures a worker per available CPU.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of worker threads.
+    pub fn threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Pin worker `n` to the `n`th CPU in the process's affinity mask, wrapping around if there are
+    /// more workers than CPUs.
+    ///
+    /// See [sched_getaffinity(2)](https://man.archlinux.org/man/sched_getaffinity.2)
+    pub fn pin_threads(&mut self, pin: bool) -> &mut Self {
+        self.pin_threads = pin;
+        self
+    }
+
+    /// Steer connections to the worker with the same index as the CPU handling them. This is most
+    /// effective alongside [`ThreadPerCore::pin_threads`], and with receive queues steered to CPUs.
+    ///
+    /// The kernel picks the listener by CPU number, so this requires the process's affinity mask to
+    /// start with CPUs `0..n` for `n` workers. Otherwise, i.e. in a cpuset of other CPUs, steering
+    /// is skipped and the kernel hashes connections across workers.
+    pub fn steer_by_cpu(&mut self, steer: bool) -> &mut Self {
+        self.steer_by_cpu = steer;
+        self
+    }
+
+    /// Block `signals` on every worker, so they are only received by reading them, i.e. with
+    /// [`signal::signals`](super::signal::signals). They are blocked on the thread calling
+    /// [`ThreadPerCore::spawn`], from which the workers inherit them.
+    pub fn block_signals(&mut self, signals: &[libc::c_int]) -> &mut Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Binds listeners for `addr` and starts the workers, running `serve` on each with the
+    /// worker's index and its listeners.
+    ///
+    /// Listeners are bound up front, in worker order, so that errors are returned here and the
+    /// reuseport group's order matches the workers'. Binding to port 0 picks one port, shared by
+    /// every worker.
+    pub fn spawn<A, F, Fut>(&self, addr: A, serve: F) -> io::Result<Workers>
+    where
+        A: ToSocketAddrs,
+        F: Fn(usize, Vec<TcpListener>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let first = tcp::bind(addr)?;
+        let local_addrs = first
+            .iter()
+            .map(TcpListener::local_addr)
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut listeners = vec![first];
+        for _ in 1..self.threads {
+            listeners.push(tcp::bind(&local_addrs[..])?);
+        }
+
+        let cpus = allowed_cpus()?;
+        let steer =
+            self.steer_by_cpu && cpus.iter().take(self.threads).copied().eq(0..self.threads);
+        if steer {
+            for listener in &listeners[0] {
+                tcp::steer_by_cpu(listener)?;
+            }
+        }
+
+        signal::block(&self.signals)?;
+
+        let serve = Arc::new(serve);
+        let mut threads = Vec::with_capacity(self.threads);
+        for (worker, listeners) in listeners.into_iter().enumerate() {
+            let serve = serve.clone();
+            let pin = self.pin_threads.then(|| cpus[worker % cpus.len()]);
+
+            let thread = thread::Builder::new()
+                .name(format!("rask-worker-{worker}"))
+                .spawn(move || {
+                    if let Some(cpu) = pin {
+                        pin_to_cpu(cpu)?;
+                    }
+
+                    let rt = Runtime::new()?;
+                    rt.block_on(serve(worker, listeners));
+                    Ok(())
+                })?;
+            threads.push(thread);
+        }
+
+        Ok(Workers {
+            threads,
+            local_addrs,
+        })
+    }
+}
+
+/// Handles to the worker threads started by [`ThreadPerCore::spawn`].
+#[derive(Debug)]
+pub struct Workers {
+    threads: Vec<thread::JoinHandle<io::Result<()>>>,
+    local_addrs: Vec<SocketAddr>,
+}
+
+impl Workers {
+    /// Gets the addresses the workers' listeners are bound to.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Waits for every worker to finish, returning the first error a worker failed to start with.
+    ///
+    /// # Panics
+    /// Panics if a worker panicked.
+    pub fn join(self) -> io::Result<()> {
+        let mut res = Ok(());
+        for thread in self.threads {
+            let worker = thread.join().unwrap();
+            if res.is_ok() {
+                res = worker;
+            }
+        }
+
+        res
+    }
+}
+
+/// Gets the CPUs the process may run on, in ascending order.
+fn allowed_cpus() -> io::Result<Vec<usize>> {
+    // SAFETY: a zeroed cpu_set_t is an empty set
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    let res = unsafe { libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
+fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    // SAFETY: a zeroed cpu_set_t is an empty set
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    unsafe { libc::CPU_SET(cpu, &mut set) };
+
+    let res = unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) };
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::{allowed_cpus, ThreadPerCore};
+
+    #[test]
+    fn binds_a_listener_per_worker() {
+        let (tx, rx) = mpsc::channel();
+        let workers = ThreadPerCore::new()
+            .threads(3)
+            .steer_by_cpu(true)
+            .spawn("127.0.0.1:0", move |worker, listeners| {
+                let tx = tx.clone();
+                async move {
+                    let addrs: Vec<_> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+                    tx.send((worker, addrs)).unwrap();
+                }
+            })
+            .unwrap();
+
+        let expected = workers.local_addrs().to_vec();
+        workers.join().unwrap();
+
+        let mut seen: Vec<_> = rx.try_iter().collect();
+        seen.sort();
+        assert_eq!(
+            seen,
+            (0..3).map(|w| (w, expected.clone())).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn allows_the_current_cpu() {
+        let cpus = allowed_cpus().unwrap();
+        let current = unsafe { libc::sched_getcpu() };
+        assert!(cpus.contains(&(current as usize)));
+        assert!(cpus.is_sorted());
+    }
+}
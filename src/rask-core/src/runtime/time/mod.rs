@@ -0,0 +1,374 @@
+//! Timers driven by the current runtime.
+//!
+//! Rather than submitting a timeout request for every timer, timers are kept in a hierarchical
+//! timing wheel with millisecond resolution. Before waiting on the ring, the runtime arms a single
+//! timeout request for the wheel's next deadline, and re-arms it with `IORING_TIMEOUT_UPDATE`
+//! when an earlier timer is added. Timers are cancelled by dropping them.
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use rask_core::runtime::{self, time, Runtime};
+//! let rt = Runtime::new().unwrap();
+//! rt.block_on(async {
+//!     time::sleep(Duration::from_millis(10)).await;
+//!
+//!     let res = time::timeout(Duration::from_secs(1), runtime::nop()).await;
+//!     assert!(res.is_ok());
+//! });
+//! ```
+
+mod wheel;
+pub(crate) use wheel::Wheel;
+
+use std::{
+    error::Error,
+    fmt,
+    future::{self, Future},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use rask_liburing::IoUring;
+
+use super::{op::UNTRACKED, CONTEXT};
+use crate::sys::unix::io::next_sqe;
+
+/// The user_data of the ring's timeout request.
+pub(crate) const TIMEOUT: u64 = u64::MAX - 1;
+
+/// The single timeout request on the ring, armed for the wheel's next deadline.
+pub(crate) struct RingTimeout {
+    armed: Option<Instant>,
+    /// Read by the kernel when the request is submitted.
+    ts: Box<libc::timespec>,
+}
+
+impl RingTimeout {
+    pub(crate) fn new() -> Self {
+        Self {
+            armed: None,
+            ts: Box::new(libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            }),
+        }
+    }
+
+    /// Arms the timeout to complete at `deadline`, unless it is already armed for an earlier one.
+    /// An armed timeout is updated in place, rather than submitting another request.
+    pub(crate) fn arm(&mut self, ring: &mut IoUring, deadline: Instant) -> io::Result<()> {
+        if self.armed.is_some_and(|armed| armed <= deadline) {
+            return Ok(());
+        }
+
+        let wait = deadline.saturating_duration_since(Instant::now());
+        *self.ts = libc::timespec {
+            tv_sec: wait.as_secs() as libc::time_t,
+            tv_nsec: wait.subsec_nanos() as libc::c_long,
+        };
+
+        let mut sqe = next_sqe(ring)?;
+        if self.armed.is_some() {
+            // Fails with -ENOENT if the timeout has just fired, in which case its completion is
+            // reaped alongside this one's, and the timeout armed again
+            sqe.prep_timeout_update(TIMEOUT, &mut self.ts);
+            sqe.set_user_data(UNTRACKED);
+        } else {
+            sqe.prep_timeout(&mut self.ts);
+            sqe.set_user_data(TIMEOUT);
+        }
+        self.armed = Some(deadline);
+
+        Ok(())
+    }
+
+    /// Whether the timeout request is in flight.
+    pub(crate) fn is_armed(&self) -> bool {
+        self.armed.is_some()
+    }
+
+    /// Records the completion of the timeout request.
+    pub(crate) fn complete(&mut self) {
+        self.armed = None;
+    }
+}
+
+/// Runs `f` with the current runtime's timer wheel.
+///
+/// # Panics
+/// Panics if called outside of a [`Runtime`](super::Runtime).
+fn with_wheel<R>(f: impl FnOnce(&mut Wheel) -> R) -> R {
+    CONTEXT.with_borrow(|cx| {
+        let cx = cx.as_ref().expect("no runtime is running on this thread");
+        let mut timers = cx.timers.borrow_mut();
+        f(&mut timers)
+    })
+}
+
+/// Waits until `duration` has elapsed.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// Waits until `deadline` is reached.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        key: None,
+    }
+}
+
+/// A future which completes once its deadline is reached, returned by [`sleep`] and
+/// [`sleep_until`].
+///
+/// The timer is added to the runtime's wheel when first polled, and removed when dropped.
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    key: Option<usize>,
+}
+
+impl Sleep {
+    /// The instant the sleep completes at.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Whether the deadline has been reached.
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Moves the deadline to `deadline`, whether or not the sleep has completed.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deadline = deadline;
+        if let Some(key) = self.key {
+            with_wheel(|wheel| wheel.reset(key, deadline));
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let deadline = self.deadline;
+        let key = *self
+            .key
+            .get_or_insert_with(|| with_wheel(|wheel| wheel.insert(deadline)));
+
+        if with_wheel(|wheel| wheel.poll(key, cx.waker())) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        let Some(key) = self.key else {
+            return;
+        };
+
+        CONTEXT.with_borrow(|cx| {
+            if let Some(cx) = cx.as_ref() {
+                cx.timers.borrow_mut().remove(key);
+            }
+        });
+    }
+}
+
+/// Runs `future`, failing with [`Elapsed`] if it does not complete within `duration`. Dropping
+/// the returned [`Timeout`] drops `future`, cancelling any ring operations it is waiting on.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    timeout_at(Instant::now() + duration, future)
+}
+
+/// Runs `future`, failing with [`Elapsed`] if it does not complete before `deadline`.
+pub fn timeout_at<F: Future>(deadline: Instant, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep_until(deadline),
+    }
+}
+
+/// A future with a deadline, returned by [`timeout`] and [`timeout_at`].
+#[derive(Debug)]
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+impl<F> Timeout<F> {
+    /// The instant the future times out at.
+    pub fn deadline(&self) -> Instant {
+        self.sleep.deadline()
+    }
+
+    /// Moves the deadline to `deadline`, i.e. to extend it as progress is made.
+    pub fn reset(&mut self, deadline: Instant) {
+        self.sleep.reset(deadline);
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: the future is never moved out of `self`, and `Sleep` is `Unpin`
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match Pin::new(&mut this.sleep).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed(()))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The error returned by a [`Timeout`] whose deadline was reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed(());
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("deadline has elapsed")
+    }
+}
+
+impl Error for Elapsed {}
+
+impl From<Elapsed> for io::Error {
+    fn from(elapsed: Elapsed) -> Self {
+        io::Error::new(io::ErrorKind::TimedOut, elapsed)
+    }
+}
+
+/// Ticks every `period`, with the first tick completing immediately.
+///
+/// # Panics
+/// Panics if `period` is zero.
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Ticks every `period`, with the first tick completing at `start`.
+///
+/// # Panics
+/// Panics if `period` is zero.
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+
+    Interval {
+        period,
+        sleep: sleep_until(start),
+    }
+}
+
+/// A timer ticking at a fixed period, returned by [`interval`] and [`interval_at`].
+///
+/// Ticks are scheduled relative to the first, rather than to when the previous tick was polled,
+/// so they do not drift. If ticks are missed, as the task was busy, the next tick completes
+/// immediately, and those after it are skipped until the schedule has caught up.
+#[derive(Debug)]
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    /// The period between ticks.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Polls for the next tick, returning the instant it was scheduled at.
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let tick = self.sleep.deadline();
+        let now = Instant::now();
+        let mut next = tick + self.period;
+        if next <= now {
+            let behind = (now - tick).as_nanos() / self.period.as_nanos();
+            next = tick + self.period * (behind as u32 + 1);
+        }
+        self.sleep.reset(next);
+
+        Poll::Ready(tick)
+    }
+
+    /// Waits for the next tick, returning the instant it was scheduled at.
+    pub async fn tick(&mut self) -> Instant {
+        future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    /// Restarts the interval, with the next tick a period from now.
+    pub fn reset(&mut self) {
+        self.sleep.reset(Instant::now() + self.period);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{interval, sleep, timeout};
+    use crate::runtime::{spawn, Runtime};
+
+    #[test]
+    fn sleeps_share_the_ring_timeout() {
+        let rt = Runtime::new().unwrap();
+        let start = Instant::now();
+
+        rt.block_on(async {
+            let tasks: Vec<_> = (1..=50)
+                .map(|i| spawn(sleep(Duration::from_millis(i))))
+                .collect();
+            for task in tasks {
+                task.await;
+            }
+        });
+
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn times_out_and_cancels_the_future() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let res = timeout(Duration::from_millis(10), sleep(Duration::from_secs(60))).await;
+            assert!(res.is_err());
+
+            let res = timeout(Duration::from_secs(60), sleep(Duration::from_millis(1))).await;
+            assert!(res.is_ok());
+        });
+    }
+
+    #[test]
+    fn interval_ticks_on_schedule() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let period = Duration::from_millis(5);
+            let mut interval = interval(period);
+            let mut last = interval.tick().await;
+            for _ in 0..3 {
+                let tick = interval.tick().await;
+                assert!(tick > last);
+                assert_eq!((tick - last).as_nanos() % period.as_nanos(), 0);
+                last = tick;
+            }
+        });
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    mem,
+    task::Waker,
+    time::{Duration, Instant},
+};
+
+use slab::Slab;
+
+/// The number of levels in the wheel.
+const LEVELS: usize = 6;
+
+/// Each level has 64 slots, so a level's occupied slots fit in a u64.
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+
+/// The furthest ahead of the wheel's current time a deadline can be, roughly two years. Later
+/// deadlines are clamped.
+const MAX_TICKS: u64 = (1 << (SLOT_BITS * LEVELS)) - 1;
+
+/// A hierarchical timing wheel, holding timers with millisecond resolution.
+///
+/// Each level has 64 slots, each of which covers 64 times the range of the one below. Timers are
+/// placed in the level whose slots most closely match how far away their deadline is. As time
+/// advances past the start of a slot in a higher level, its timers cascade down into lower levels,
+/// until they expire. Inserting, removing and expiring a timer are constant time.
+#[derive(Debug)]
+pub(crate) struct Wheel {
+    start: Instant,
+    /// Milliseconds since `start` the wheel has advanced to.
+    elapsed: u64,
+    levels: [Level; LEVELS],
+    timers: Slab<Timer>,
+}
+
+#[derive(Debug)]
+struct Timer {
+    /// Milliseconds since the wheel's start.
+    deadline: u64,
+    waker: Option<Waker>,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Scheduled {
+        level: usize,
+        slot: usize,
+        index: usize,
+    },
+    Fired,
+}
+
+#[derive(Debug)]
+struct Level {
+    /// A bit for each slot holding timers.
+    occupied: u64,
+    slots: [Vec<usize>; SLOTS],
+}
+
+impl Wheel {
+    pub(crate) fn new(start: Instant) -> Self {
+        Self {
+            start,
+            elapsed: 0,
+            levels: std::array::from_fn(|_| Level {
+                occupied: 0,
+                slots: std::array::from_fn(|_| Vec::new()),
+            }),
+            timers: Slab::new(),
+        }
+    }
+
+    /// Adds a timer expiring at `deadline`, returning its key. A timer whose deadline has already
+    /// been reached fires immediately.
+    pub(crate) fn insert(&mut self, deadline: Instant) -> usize {
+        let key = self.timers.insert(Timer {
+            deadline: self.deadline_ticks(deadline),
+            waker: None,
+            state: State::Fired,
+        });
+        self.schedule(key);
+
+        key
+    }
+
+    /// Moves the timer `key` to expire at `deadline`, whether or not it has already fired.
+    pub(crate) fn reset(&mut self, key: usize, deadline: Instant) {
+        self.unschedule(key);
+        self.timers[key].deadline = self.deadline_ticks(deadline);
+        self.schedule(key);
+    }
+
+    /// Removes the timer `key`.
+    pub(crate) fn remove(&mut self, key: usize) {
+        self.unschedule(key);
+        self.timers.remove(key);
+    }
+
+    /// Whether the timer `key` has fired. If not, `waker` is woken once it does.
+    pub(crate) fn poll(&mut self, key: usize, waker: &Waker) -> bool {
+        let timer = &mut self.timers[key];
+        if timer.state == State::Fired {
+            return true;
+        }
+
+        match &mut timer.waker {
+            Some(current) => current.clone_from(waker),
+            current => *current = Some(waker.clone()),
+        }
+        false
+    }
+
+    /// The next time the wheel must be advanced for a timer to fire, or to cascade down a level.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let (_, _, deadline) = self.next_expiration()?;
+        Some(self.start + Duration::from_millis(deadline))
+    }
+
+    /// Advances the wheel to `now`, firing every timer whose deadline has been reached, and waking
+    /// the tasks waiting on them.
+    pub(crate) fn advance(&mut self, now: Instant) {
+        let now = self.ticks(now);
+
+        while let Some((level, slot, deadline)) = self.next_expiration() {
+            if deadline > now {
+                break;
+            }
+
+            self.elapsed = deadline;
+            self.levels[level].occupied &= !(1 << slot);
+            for key in mem::take(&mut self.levels[level].slots[slot]) {
+                self.schedule(key);
+            }
+        }
+
+        self.elapsed = self.elapsed.max(now);
+    }
+
+    /// Milliseconds since the wheel's start at `instant`, rounded down.
+    fn ticks(&self, instant: Instant) -> u64 {
+        instant.saturating_duration_since(self.start).as_millis() as u64
+    }
+
+    /// Milliseconds since the wheel's start at `deadline`, rounded up so timers never fire early.
+    fn deadline_ticks(&self, deadline: Instant) -> u64 {
+        let since = deadline.saturating_duration_since(self.start);
+        let ticks = since.as_nanos().div_ceil(1_000_000) as u64;
+        ticks.min(self.elapsed + MAX_TICKS)
+    }
+
+    /// Places the timer `key` in the slot for its deadline, or fires it if it has been reached.
+    fn schedule(&mut self, key: usize) {
+        let timer = &mut self.timers[key];
+        if timer.deadline <= self.elapsed {
+            timer.state = State::Fired;
+            if let Some(waker) = timer.waker.take() {
+                waker.wake();
+            }
+            return;
+        }
+
+        let level = level_for(self.elapsed, timer.deadline);
+        let slot = (timer.deadline >> (level * SLOT_BITS)) as usize % SLOTS;
+        let slots = &mut self.levels[level].slots[slot];
+        timer.state = State::Scheduled {
+            level,
+            slot,
+            index: slots.len(),
+        };
+        slots.push(key);
+        self.levels[level].occupied |= 1 << slot;
+    }
+
+    /// Takes the timer `key` out of its slot, if it has not fired.
+    fn unschedule(&mut self, key: usize) {
+        let State::Scheduled { level, slot, index } = self.timers[key].state else {
+            return;
+        };
+
+        let slots = &mut self.levels[level].slots[slot];
+        slots.swap_remove(index);
+        if let Some(&moved) = slots.get(index) {
+            self.timers[moved].state = State::Scheduled { level, slot, index };
+        }
+        if slots.is_empty() {
+            self.levels[level].occupied &= !(1 << slot);
+        }
+        self.timers[key].state = State::Fired;
+    }
+
+    /// Finds the next occupied slot, returning its level, slot, and the tick it starts at.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        self.levels
+            .iter()
+            .enumerate()
+            .find(|(_, level)| level.occupied != 0)
+            .map(|(level, slots)| {
+                let slot_range = 1u64 << (level * SLOT_BITS);
+                let level_range = slot_range << SLOT_BITS;
+
+                // Search from the slot the current time is in, wrapping around
+                let now_slot = (self.elapsed / slot_range) as u32 % SLOTS as u32;
+                let slot = (slots.occupied.rotate_right(now_slot).trailing_zeros() + now_slot)
+                    as usize
+                    % SLOTS;
+
+                let level_start = self.elapsed & !(level_range - 1);
+                let mut deadline = level_start + slot as u64 * slot_range;
+                if deadline <= self.elapsed {
+                    deadline += level_range;
+                }
+
+                (level, slot, deadline)
+            })
+    }
+}
+
+/// The level a timer expiring at `deadline` is placed in, being the level of the highest slot
+/// bit it differs from `elapsed` by.
+fn level_for(elapsed: u64, deadline: u64) -> usize {
+    let masked = ((elapsed ^ deadline) | (SLOTS as u64 - 1)).min(MAX_TICKS);
+    let significant = 63 - masked.leading_zeros() as usize;
+
+    significant / SLOT_BITS
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Wake, Waker},
+        time::{Duration, Instant},
+    };
+
+    use super::{level_for, Wheel};
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn places_timers_by_distance() {
+        assert_eq!(level_for(0, 1), 0);
+        assert_eq!(level_for(0, 63), 0);
+        assert_eq!(level_for(0, 64), 1);
+        assert_eq!(level_for(0, 4095), 1);
+        assert_eq!(level_for(0, 4096), 2);
+        assert_eq!(level_for(60, 70), 1);
+        assert_eq!(level_for(0, u64::MAX), 5);
+    }
+
+    #[test]
+    fn fires_timers_in_deadline_order_across_levels() {
+        let start = Instant::now();
+        let mut wheel = Wheel::new(start);
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+
+        let deadlines = [ms(5), ms(70), ms(5_000), ms(300_000), ms(20_000_000)];
+        let keys: Vec<_> = deadlines
+            .iter()
+            .map(|&deadline| wheel.insert(start + deadline))
+            .collect();
+        for &key in &keys {
+            assert!(!wheel.poll(key, &waker));
+        }
+
+        for (i, (&key, &deadline)) in keys.iter().zip(&deadlines).enumerate() {
+            let next = wheel.next_deadline().unwrap();
+            assert!(next <= start + deadline);
+
+            wheel.advance(start + deadline - ms(1));
+            assert!(!wheel.poll(key, &waker));
+            wheel.advance(start + deadline);
+            assert!(wheel.poll(key, &waker));
+            assert_eq!(counter.0.load(Ordering::Relaxed), i + 1);
+        }
+        assert_eq!(wheel.next_deadline(), None);
+    }
+
+    #[test]
+    fn removed_and_reset_timers() {
+        let start = Instant::now();
+        let mut wheel = Wheel::new(start);
+        let waker = Waker::noop();
+
+        let removed = wheel.insert(start + ms(10));
+        let reset = wheel.insert(start + ms(10));
+        let kept = wheel.insert(start + ms(10));
+        wheel.remove(removed);
+        wheel.reset(reset, start + ms(100));
+
+        wheel.advance(start + ms(10));
+        assert!(wheel.poll(kept, waker));
+        assert!(!wheel.poll(reset, waker));
+
+        wheel.reset(kept, start + ms(20));
+        assert!(!wheel.poll(kept, waker));
+        wheel.advance(start + ms(100));
+        assert!(wheel.poll(kept, waker));
+        assert!(wheel.poll(reset, waker));
+
+        let elapsed = wheel.insert(start);
+        assert!(wheel.poll(elapsed, waker));
+    }
+}
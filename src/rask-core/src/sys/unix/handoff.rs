@@ -0,0 +1,344 @@
+//! Hands listening sockets off to a replacement process, so the server can be upgraded without
+//! refusing connections.
+//!
+//! The old process binds a [`HandoffServer`] to a known path before exec'ing the new binary. The
+//! new process calls [`adopt`] to receive duplicates of the old process's listeners over the Unix
+//! socket with `SCM_RIGHTS`, and starts accepting on them. Once the new process acknowledges the
+//! handoff, the old process stops accepting, with
+//! [`EventLoop::stop_accepting`](super::io::EventLoop::stop_accepting), and drains its open
+//! connections.
+//!
+//! As both processes share the same sockets, connections queued while neither is accepting wait
+//! in the listen backlog, rather than being refused.
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::{fs, io, mem, ptr};
+
+use super::{systemd, uds};
+
+/// The most file descriptors the kernel passes in a single message.
+const SCM_MAX_FD: usize = 253;
+
+/// Sent by the new process once it has adopted the listeners.
+const ACK: u8 = 1;
+
+/// The old process's end of a handoff, waiting for a replacement process to take its listeners.
+#[derive(Debug)]
+pub struct HandoffServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl HandoffServer {
+    /// Binds the handoff socket at `path`, which the new process is given to [`adopt`] from.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let listener = uds::ListenerBuilder::new()
+            .mode(0o600)
+            .cloexec(true)
+            .bind(path)?;
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Waits for a new process to connect, sends it `listeners`, and waits for it to acknowledge
+    /// them. Once this returns, the new process is accepting connections, and the old process
+    /// should stop.
+    pub fn handoff(&self, listeners: &[impl AsRawFd]) -> io::Result<()> {
+        let (mut stream, _) = self.listener.accept()?;
+        let fds: Vec<_> = listeners.iter().map(AsRawFd::as_raw_fd).collect();
+        send_fds(&stream, &fds)?;
+
+        let mut ack = [0];
+        stream.read_exact(&mut ack)?;
+        if ack[0] != ACK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected handoff acknowledgement",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for HandoffServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Adopts the listeners of the old process waiting at `path` in [`HandoffServer::handoff`], in the
+/// order they were sent.
+///
+/// Fails if any received file descriptor is not a listening TCP socket.
+pub fn adopt(path: impl AsRef<Path>) -> io::Result<Vec<TcpListener>> {
+    let mut stream = UnixStream::connect(path)?;
+    let fds = recv_fds(&stream)?;
+
+    let listeners = fds
+        .into_iter()
+        .map(|fd| {
+            systemd::validate(fd.as_raw_fd(), &[libc::AF_INET, libc::AF_INET6])?;
+            Ok(TcpListener::from(fd))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    stream.write_all(&[ACK])?;
+    Ok(listeners)
+}
+
+/// Sends `fds` over `stream` with `SCM_RIGHTS`. The number of file descriptors is sent as the
+/// message's payload.
+pub fn send_fds(stream: &UnixStream, fds: &[RawFd]) -> io::Result<()> {
+    if fds.is_empty() || fds.len() > SCM_MAX_FD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "must send between 1 and 253 file descriptors",
+        ));
+    }
+
+    let fds_len = mem::size_of_val(fds) as libc::c_uint;
+    let mut control = control_buffer(fds.len());
+    let mut count = fds.len() as u32;
+    let mut iov = libc::iovec {
+        iov_base: ptr::addr_of_mut!(count).cast(),
+        iov_len: mem::size_of::<u32>(),
+    };
+
+    // SAFETY: msghdr is plain data, for which zero is a valid value
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(fds_len) } as usize;
+
+    // SAFETY: the control buffer is sized to hold a single header followed by `fds`
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as usize;
+        ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast(), fds.len());
+    }
+
+    syscall!(sendmsg(stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL))?;
+
+    Ok(())
+}
+
+/// Receives file descriptors sent over `stream` by [`send_fds`], with `FD_CLOEXEC` set.
+pub fn recv_fds(stream: &UnixStream) -> io::Result<Vec<OwnedFd>> {
+    let mut control = control_buffer(SCM_MAX_FD);
+    let mut count = 0u32;
+    let mut iov = libc::iovec {
+        iov_base: ptr::addr_of_mut!(count).cast(),
+        iov_len: mem::size_of::<u32>(),
+    };
+
+    // SAFETY: msghdr is plain data, for which zero is a valid value
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control[..]);
+
+    let n = syscall!(recvmsg(
+        stream.as_raw_fd(),
+        &mut msg,
+        libc::MSG_CMSG_CLOEXEC
+    ))?;
+
+    let mut fds = Vec::new();
+    // SAFETY: the kernel wrote `msg_controllen` bytes of control messages to the buffer
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                let len = (*cmsg).cmsg_len - libc::CMSG_LEN(0) as usize;
+                for i in 0..len / mem::size_of::<RawFd>() {
+                    fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data.add(i))));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file descriptors were truncated",
+        ));
+    }
+    if n as usize != mem::size_of::<u32>() || count as usize != fds.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "received an unexpected number of file descriptors",
+        ));
+    }
+
+    Ok(fds)
+}
+
+/// Allocates a control message buffer able to hold `fds` file descriptors, aligned for `cmsghdr`.
+fn control_buffer(fds: usize) -> Vec<u64> {
+    let space = unsafe { libc::CMSG_SPACE((fds * mem::size_of::<RawFd>()) as libc::c_uint) };
+    vec![0; (space as usize).div_ceil(mem::size_of::<u64>())]
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        io::{self, Read, Write},
+        net::{TcpListener, TcpStream},
+        os::{fd::AsRawFd, unix::net::UnixStream},
+        process::{self, Command, Stdio},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{adopt, recv_fds, send_fds, HandoffServer};
+
+    #[test]
+    fn passes_fds_over_unix_socket() {
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+            TcpListener::bind("127.0.0.1:0").unwrap(),
+        ];
+        let fds: Vec<_> = listeners.iter().map(AsRawFd::as_raw_fd).collect();
+
+        let (tx, rx) = UnixStream::pair().unwrap();
+        send_fds(&tx, &fds).unwrap();
+        let received = recv_fds(&rx).unwrap();
+
+        assert_eq!(received.len(), 2);
+        for (listener, fd) in listeners.iter().zip(received) {
+            let adopted = TcpListener::from(fd);
+            assert_ne!(adopted.as_raw_fd(), listener.as_raw_fd());
+            assert_eq!(
+                adopted.local_addr().unwrap(),
+                listener.local_addr().unwrap()
+            );
+        }
+    }
+
+    /// Set for the child process of [`new_process_takes_over_without_refusing_connections`], to
+    /// the path it adopts listeners from.
+    const HANDOFF_PATH: &str = "RASK_TEST_HANDOFF_PATH";
+
+    /// Run in a child process, as the new process of a handoff, serving connections until killed.
+    /// Does nothing when run with the rest of the tests.
+    #[test]
+    fn adopts_in_child() {
+        let Some(path) = env::var_os(HANDOFF_PATH) else {
+            return;
+        };
+
+        let listeners = adopt(path).unwrap();
+        loop {
+            match listeners[0].accept() {
+                Ok((mut conn, _)) => {
+                    let _ = conn.write_all(b"new");
+                }
+                // The old process's listener is non-blocking, which the duplicate shares
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                Err(e) => panic!("accept failed: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn new_process_takes_over_without_refusing_connections() {
+        let path = env::temp_dir().join(format!("rask-{}-handoff.sock", process::id()));
+        let old = TcpListener::bind("127.0.0.1:0").unwrap();
+        old.set_nonblocking(true).unwrap();
+        let addr = old.local_addr().unwrap();
+        let server = HandoffServer::bind(&path).unwrap();
+
+        let old_fd = old.as_raw_fd();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Connects back to back across the whole handoff, until the new process has served a few
+        let client = thread::spawn(move || {
+            let (mut refused, mut old, mut new) = (0, 0, 0);
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while new < 20 && Instant::now() < deadline {
+                let mut stream = match TcpStream::connect(addr) {
+                    Ok(stream) => stream,
+                    Err(e) if e.raw_os_error() == Some(libc::ECONNREFUSED) => {
+                        refused += 1;
+                        continue;
+                    }
+                    Err(e) => panic!("connect failed: {e}"),
+                };
+
+                let mut reply = Vec::new();
+                stream.read_to_end(&mut reply).unwrap();
+                match &reply[..] {
+                    b"old" => old += 1,
+                    b"new" => new += 1,
+                    reply => panic!("unexpected reply {reply:?}"),
+                }
+            }
+            (refused, old, new)
+        });
+
+        // The old process serves until the new one has acknowledged the handoff
+        let serving = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::SeqCst) {
+                    match old.accept() {
+                        Ok((mut conn, _)) => {
+                            conn.set_nonblocking(false).unwrap();
+                            conn.write_all(b"old").unwrap();
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        Err(e) => panic!("accept failed: {e}"),
+                    }
+                }
+                // Closes the old process's listener, leaving the new process's duplicate
+            }
+        });
+
+        // Let the old process serve for a while before upgrading
+        thread::sleep(Duration::from_millis(50));
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "sys::unix::handoff::test::adopts_in_child"])
+            .env(HANDOFF_PATH, &path)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        server.handoff(&[old_fd]).unwrap();
+        stop.store(true, Ordering::SeqCst);
+        serving.join().unwrap();
+        drop(server);
+        assert!(!path.exists());
+
+        let (refused, old, new) = client.join().unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(refused, 0);
+        assert!(old > 0);
+        assert_eq!(new, 20);
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    fmt, mem,
+    ops::{Index, IndexMut},
+};
+
+use slab::Slab;
+
+/// The largest buffer kept in a [`ConnectionTable`]'s pool. Larger buffers, i.e. those which held
+/// a large response, are shrunk to it before being pooled.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+/// The number of buffers kept in a [`ConnectionTable`]'s pool without a memory budget.
+const MAX_POOLED_BUFFERS: usize = 1024;
+
+/// What a connection in a [`ConnectionTable`] is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Serving a request: reading it, or writing its response.
+    Active,
+    /// Waiting for its first or next request.
+    Idle,
+    /// Its pending request has been cancelled, and it is closed once the request completes.
+    Closing,
+}
+
+/// Counts of the connections in a [`ConnectionTable`] by [`Status`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Connections serving a request.
+    pub active: usize,
+    /// Connections waiting for a request.
+    pub idle: usize,
+    /// Connections waiting to be closed.
+    pub closing: usize,
+}
+
+impl ConnectionStats {
+    fn count(&mut self, status: Status) -> &mut usize {
+        match status {
+            Status::Active => &mut self.active,
+            Status::Idle => &mut self.idle,
+            Status::Closing => &mut self.closing,
+        }
+    }
+}
+
+struct Entry<T> {
+    value: T,
+    status: Status,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    /// The capacity of the buffers, as last accounted for.
+    held: usize,
+}
+
+/// A per-core table of connections, keyed by the index of their slot in a [`Slab`], holding each
+/// connection's protocol state alongside its read and write buffers.
+///
+/// Every connection has a read buffer of a fixed size, while its write buffer is only held while
+/// it is serving a request. Once it goes [idle](Status::Idle), the write buffer is handed back to
+/// a pool shared by the table, from which the buffers of new connections and requests are taken.
+///
+/// The table may be given a memory budget, bounding the bytes of buffers held by connections and
+/// the pool. Pooled buffers are freed to stay within it, and [`ConnectionTable::has_room`] tells
+/// whether another connection fits, so it can be shed rather than admitted.
+pub struct ConnectionTable<T> {
+    entries: Slab<Entry<T>>,
+    pool: Vec<Vec<u8>>,
+    read_size: usize,
+    budget: Option<usize>,
+    /// The bytes of buffers held by connections.
+    held: usize,
+    /// The bytes of buffers held by the pool.
+    pooled: usize,
+    stats: ConnectionStats,
+}
+
+impl<T> ConnectionTable<T> {
+    /// Creates an empty table, whose connections read into buffers of `read_size` bytes.
+    pub fn new(read_size: usize) -> Self {
+        Self {
+            entries: Slab::new(),
+            pool: Vec::new(),
+            read_size,
+            budget: None,
+            held: 0,
+            pooled: 0,
+            stats: ConnectionStats::default(),
+        }
+    }
+
+    /// Sets the bytes of buffers the table holds across connections and its pool, or removes the
+    /// budget if `None`. Pooled buffers are freed to bring the table within the new budget.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    /// Gets the memory budget.
+    pub fn budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Whether another connection's read buffer fits within the memory budget, on top of the
+    /// buffers held by connections. Pooled buffers do not count, as they are freed to make room.
+    pub fn has_room(&self) -> bool {
+        self.budget
+            .is_none_or(|budget| self.held + self.read_size <= budget)
+    }
+
+    /// Gets the bytes of buffers held by connections.
+    pub fn held(&self) -> usize {
+        self.held
+    }
+
+    /// Gets the bytes of buffers held by the pool.
+    pub fn pooled(&self) -> usize {
+        self.pooled
+    }
+
+    /// Gets the number of connections.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the table holds no connections.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Gets the number of connections of each status.
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats
+    }
+
+    /// Inserts an [idle](Status::Idle) connection, returning its key. It is given a read buffer
+    /// whether or not it fits within the memory budget, which is checked with
+    /// [`ConnectionTable::has_room`].
+    pub fn insert(&mut self, value: T) -> usize {
+        let mut read_buf = self.take();
+        read_buf.resize(self.read_size, 0);
+        let held = read_buf.capacity();
+
+        self.held += held;
+        self.stats.idle += 1;
+        self.entries.insert(Entry {
+            value,
+            status: Status::Idle,
+            read_buf,
+            write_buf: Vec::new(),
+            held,
+        })
+    }
+
+    /// Removes the connection, returning its buffers to the pool.
+    ///
+    /// # Panics
+    /// Panics if there is no connection for `key`.
+    pub fn remove(&mut self, key: usize) -> T {
+        let entry = self.entries.remove(key);
+        self.held -= entry.held;
+        *self.stats.count(entry.status) -= 1;
+
+        self.give(entry.read_buf);
+        self.give(entry.write_buf);
+        self.trim();
+
+        entry.value
+    }
+
+    /// Iterates over the connections, with their keys and statuses.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T, Status)> {
+        self.entries
+            .iter()
+            .map(|(key, entry)| (key, &entry.value, entry.status))
+    }
+
+    /// Gets the status of the connection.
+    pub fn status(&self, key: usize) -> Status {
+        self.entries[key].status
+    }
+
+    /// Sets the status of the connection. Going idle hands its write buffer back to the pool, so
+    /// it must have been flushed.
+    pub fn set_status(&mut self, key: usize, status: Status) {
+        let entry = &mut self.entries[key];
+        *self.stats.count(entry.status) -= 1;
+        *self.stats.count(status) += 1;
+        entry.status = status;
+
+        if status == Status::Idle {
+            let write_buf = mem::take(&mut entry.write_buf);
+            self.held -= write_buf.capacity();
+            entry.held -= write_buf.capacity();
+            self.give(write_buf);
+            self.trim();
+        }
+    }
+
+    /// Gets the buffer the connection reads into.
+    pub fn read_buf(&mut self, key: usize) -> &mut [u8] {
+        &mut self.entries[key].read_buf
+    }
+
+    /// Gets the output buffered for the connection.
+    pub fn write_buf(&self, key: usize) -> &[u8] {
+        &self.entries[key].write_buf
+    }
+
+    /// Empties the connection's write buffer, keeping its capacity for the rest of the request.
+    pub fn clear_write_buf(&mut self, key: usize) {
+        self.entries[key].write_buf.clear();
+    }
+
+    /// Calls `f` with the connection, its read buffer, and its write buffer, taking one from the
+    /// pool if it has none. The buffers' capacity is accounted for once `f` returns.
+    pub fn with_buffers<R>(
+        &mut self,
+        key: usize,
+        f: impl FnOnce(&mut T, &[u8], &mut Vec<u8>) -> R,
+    ) -> R {
+        if self.entries[key].write_buf.capacity() == 0 {
+            let write_buf = self.take();
+            let entry = &mut self.entries[key];
+            entry.write_buf = write_buf;
+        }
+
+        let entry = &mut self.entries[key];
+        let res = f(&mut entry.value, &entry.read_buf, &mut entry.write_buf);
+
+        let held = entry.read_buf.capacity() + entry.write_buf.capacity();
+        self.held = self.held - entry.held + held;
+        entry.held = held;
+
+        res
+    }
+
+    /// Takes an empty buffer from the pool, or an unallocated one if the pool is empty.
+    fn take(&mut self) -> Vec<u8> {
+        match self.pool.pop() {
+            Some(buf) => {
+                self.pooled -= buf.capacity();
+                buf
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns a buffer to the pool, shrinking it if it has grown beyond the largest pooled.
+    fn give(&mut self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 {
+            return;
+        }
+
+        buf.clear();
+        buf.shrink_to(MAX_POOLED_CAPACITY);
+        self.pooled += buf.capacity();
+        self.pool.push(buf);
+    }
+
+    /// Frees pooled buffers until the table is within its memory budget, or the pool is within
+    /// its size without one.
+    fn trim(&mut self) {
+        while let Some(buf) = self.pool.last() {
+            let over = match self.budget {
+                Some(budget) => self.held + self.pooled > budget,
+                None => self.pool.len() > MAX_POOLED_BUFFERS,
+            };
+            if !over {
+                break;
+            }
+
+            self.pooled -= buf.capacity();
+            self.pool.pop();
+        }
+    }
+}
+
+impl<T> fmt::Debug for ConnectionTable<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionTable")
+            .field("stats", &self.stats)
+            .field("budget", &self.budget)
+            .field("held", &self.held)
+            .field("pooled", &self.pooled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Index<usize> for ConnectionTable<T> {
+    type Output = T;
+
+    fn index(&self, key: usize) -> &T {
+        &self.entries[key].value
+    }
+}
+
+impl<T> IndexMut<usize> for ConnectionTable<T> {
+    fn index_mut(&mut self, key: usize) -> &mut T {
+        &mut self.entries[key].value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ConnectionStats, ConnectionTable, Status};
+
+    #[test]
+    fn pools_idle_buffers_within_the_budget() {
+        let mut table = ConnectionTable::new(4096);
+        let a = table.insert('a');
+        let b = table.insert('b');
+        assert_eq!(table.held(), 2 * 4096);
+
+        table.set_status(a, Status::Active);
+        let n = table.with_buffers(a, |_, _, out| {
+            out.extend_from_slice(&[0; 10_000]);
+            out.len()
+        });
+        assert_eq!(n, 10_000);
+        assert!(table.held() >= 2 * 4096 + 10_000);
+        table.set_status(b, Status::Closing);
+        assert_eq!(
+            table.stats(),
+            ConnectionStats {
+                active: 1,
+                idle: 0,
+                closing: 1,
+            }
+        );
+
+        // Going idle hands the write buffer back to the pool
+        table.set_status(a, Status::Idle);
+        assert_eq!(table.held(), 2 * 4096);
+        assert!(table.pooled() >= 10_000);
+
+        // The pool is freed to make room for the connections within the budget
+        table.set_budget(Some(3 * 4096));
+        assert_eq!(table.pooled(), 0);
+        assert!(table.has_room());
+        table.insert('c');
+        assert!(!table.has_room());
+
+        assert_eq!(table.remove(b), 'b');
+        assert_eq!(table.stats().idle, 2);
+        assert_eq!(table.held(), 2 * 4096);
+        assert!(table.held() + table.pooled() <= 3 * 4096);
+    }
+}
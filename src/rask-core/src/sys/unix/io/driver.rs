@@ -0,0 +1,81 @@
+use std::{io, os::fd::RawFd, time::Duration};
+
+/// The completion of a request submitted to a [`Driver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Completion {
+    /// The token the request was submitted with.
+    pub token: u64,
+    /// The result of the request, as would be returned by the equivalent syscall, with errors
+    /// negated, i.e. `-EAGAIN`.
+    pub result: i32,
+    /// Whether the request will produce more completions.
+    pub more: bool,
+}
+
+/// Performs IO on behalf of an [`EventLoop`](super::EventLoop), reporting the results as
+/// completions.
+///
+/// Requests are identified by a token, which is handed back in their [`Completion`]. A driver may
+/// complete requests in any order, but at most one recv and one send may be in flight per file
+/// descriptor at a time.
+pub trait Driver {
+    /// Accept connections on `listener` until an error occurs, completing once per connection with
+    /// the accepted file descriptor.
+    fn accept_multi(&mut self, listener: RawFd, token: u64) -> io::Result<()>;
+
+    /// Receive into `buf` from `fd`, completing with the number of bytes read.
+    ///
+    /// # Safety
+    /// `buf` must remain valid until the request completes.
+    unsafe fn recv(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()>;
+
+    /// Read into `buf` from `fd`, which need not be a socket, such as a
+    /// [`SignalFd`](crate::sys::unix::signal::SignalFd), completing with the number of bytes read.
+    /// Drivers may set `O_NONBLOCK` on `fd`.
+    ///
+    /// # Safety
+    /// `buf` must remain valid until the request completes.
+    unsafe fn read(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()>;
+
+    /// Send `buf` to `fd`, completing with the number of bytes written.
+    ///
+    /// # Safety
+    /// `buf` must remain valid until the request completes.
+    unsafe fn send(&mut self, fd: RawFd, buf: *const [u8], token: u64) -> io::Result<()>;
+
+    /// Cancel the in-flight request submitted with the token `target`, which completes with
+    /// `-ECANCELED`. This request completes with 0 if the target was cancelled, or `-ENOENT` if it
+    /// could not be found.
+    fn cancel(&mut self, target: u64, token: u64) -> io::Result<()>;
+
+    /// Close `fd`, after which no further completions are produced for it.
+    fn close(&mut self, fd: RawFd, token: u64) -> io::Result<()>;
+
+    /// Waits for at least one request to complete, or for `timeout` to elapse if set, appending
+    /// the completions that are ready to `completions`.
+    fn wait(
+        &mut self,
+        completions: &mut Vec<Completion>,
+        timeout: Option<Duration>,
+    ) -> io::Result<()>;
+}
+
+/// Selects the best driver the host supports.
+///
+/// io_uring is preferred, but may be disabled with `kernel.io_uring_disabled`, or blocked by a
+/// seccomp filter, in which case this falls back to epoll.
+#[cfg(target_os = "linux")]
+pub fn driver() -> io::Result<Box<dyn Driver>> {
+    match super::IoUringDriver::new() {
+        Ok(driver) => Ok(Box::new(driver)),
+        Err(e)
+            if matches!(
+                e.raw_os_error(),
+                Some(libc::ENOSYS | libc::EPERM | libc::EACCES)
+            ) =>
+        {
+            Ok(Box::new(super::EpollDriver::new()?))
+        }
+        Err(e) => Err(e),
+    }
+}
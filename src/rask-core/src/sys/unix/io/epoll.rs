@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    ptr,
+    time::{Duration, Instant},
+};
+
+use super::{Completion, Driver};
+
+const MAX_EVENTS: usize = 256;
+
+/// Requests waiting on a file descriptor to become ready.
+#[derive(Debug, Default)]
+struct Pending {
+    accept: Option<u64>,
+    recv: Option<(*mut [u8], u64)>,
+    read: Option<(*mut [u8], u64)>,
+    send: Option<(*const [u8], u64)>,
+    registered: bool,
+}
+
+impl Pending {
+    fn events(&self) -> u32 {
+        let mut events = 0;
+        if self.accept.is_some() || self.recv.is_some() || self.read.is_some() {
+            events |= libc::EPOLLIN;
+        }
+        if self.send.is_some() {
+            events |= libc::EPOLLOUT;
+        }
+
+        events as u32
+    }
+}
+
+/// A [`Driver`] emulating completions with epoll, for hosts where io_uring is unavailable.
+///
+/// Each request is attempted without blocking as soon as it is submitted. If the file descriptor is
+/// not ready, it is registered with epoll, and the request is retried once it becomes ready.
+pub struct EpollDriver {
+    epoll: OwnedFd,
+    pending: HashMap<RawFd, Pending>,
+    ready: Vec<Completion>,
+    events: Vec<libc::epoll_event>,
+}
+
+impl EpollDriver {
+    /// Creates a driver backed by a new epoll instance.
+    pub fn new() -> io::Result<Self> {
+        let epoll = syscall!(epoll_create1(libc::EPOLL_CLOEXEC))?;
+
+        Ok(Self {
+            epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+            pending: HashMap::new(),
+            ready: Vec::new(),
+            events: Vec::with_capacity(MAX_EVENTS),
+        })
+    }
+
+    /// Registers interest in `fd` matching its pending requests, or deregisters it if there are
+    /// none.
+    fn update(&mut self, fd: RawFd) -> io::Result<()> {
+        let Some(pending) = self.pending.get_mut(&fd) else {
+            return Ok(());
+        };
+
+        let events = pending.events();
+        let op = match (pending.registered, events) {
+            (false, 0) => {
+                self.pending.remove(&fd);
+                return Ok(());
+            }
+            (true, 0) => libc::EPOLL_CTL_DEL,
+            (false, _) => libc::EPOLL_CTL_ADD,
+            (true, _) => libc::EPOLL_CTL_MOD,
+        };
+
+        let mut event = libc::epoll_event {
+            events,
+            u64: fd as u64,
+        };
+        syscall!(epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut event))?;
+
+        if events == 0 {
+            self.pending.remove(&fd);
+        } else {
+            pending.registered = true;
+        }
+
+        Ok(())
+    }
+
+    /// Attempts every pending request on `fd`, completing those which no longer block.
+    fn poll(&mut self, fd: RawFd) -> io::Result<()> {
+        let Some(pending) = self.pending.get_mut(&fd) else {
+            return Ok(());
+        };
+
+        if let Some(token) = pending.accept {
+            loop {
+                match syscall!(accept4(
+                    fd,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    libc::SOCK_CLOEXEC
+                )) {
+                    Ok(conn) => self.ready.push(completion(token, Ok(conn), true)),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        pending.accept = None;
+                        self.ready.push(completion(token, Err(e), false));
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some((buf, token)) = pending.recv {
+            let res = syscall!(recv(fd, buf.cast(), buf.len(), libc::MSG_DONTWAIT));
+            if !would_block(&res) {
+                pending.recv = None;
+                self.ready
+                    .push(completion(token, res.map(|n| n as i32), false));
+            }
+        }
+
+        if let Some((buf, token)) = pending.read {
+            let res = syscall!(read(fd, buf.cast(), buf.len()));
+            if !would_block(&res) {
+                pending.read = None;
+                self.ready
+                    .push(completion(token, res.map(|n| n as i32), false));
+            }
+        }
+
+        if let Some((buf, token)) = pending.send {
+            let res = syscall!(send(
+                fd,
+                buf.cast(),
+                buf.len(),
+                libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL
+            ));
+            if !would_block(&res) {
+                pending.send = None;
+                self.ready
+                    .push(completion(token, res.map(|n| n as i32), false));
+            }
+        }
+
+        self.update(fd)
+    }
+}
+
+impl Driver for EpollDriver {
+    fn accept_multi(&mut self, listener: RawFd, token: u64) -> io::Result<()> {
+        let flags = syscall!(fcntl(listener, libc::F_GETFL))?;
+        syscall!(fcntl(listener, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+
+        self.pending.entry(listener).or_default().accept = Some(token);
+        self.poll(listener)
+    }
+
+    unsafe fn recv(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()> {
+        self.pending.entry(fd).or_default().recv = Some((buf, token));
+        self.poll(fd)
+    }
+
+    unsafe fn read(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()> {
+        // Reads are attempted as soon as they are submitted, so must not block
+        let flags = syscall!(fcntl(fd, libc::F_GETFL))?;
+        syscall!(fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+
+        self.pending.entry(fd).or_default().read = Some((buf, token));
+        self.poll(fd)
+    }
+
+    unsafe fn send(&mut self, fd: RawFd, buf: *const [u8], token: u64) -> io::Result<()> {
+        self.pending.entry(fd).or_default().send = Some((buf, token));
+        self.poll(fd)
+    }
+
+    fn cancel(&mut self, target: u64, token: u64) -> io::Result<()> {
+        let found = self.pending.iter_mut().find_map(|(&fd, pending)| {
+            if pending.accept == Some(target) {
+                pending.accept = None;
+            } else if pending.recv.is_some_and(|(_, t)| t == target) {
+                pending.recv = None;
+            } else if pending.read.is_some_and(|(_, t)| t == target) {
+                pending.read = None;
+            } else if pending.send.is_some_and(|(_, t)| t == target) {
+                pending.send = None;
+            } else {
+                return None;
+            }
+
+            Some(fd)
+        });
+
+        let Some(fd) = found else {
+            self.ready.push(completion(
+                token,
+                Err(io::Error::from_raw_os_error(libc::ENOENT)),
+                false,
+            ));
+            return Ok(());
+        };
+
+        self.ready.push(completion(
+            target,
+            Err(io::Error::from_raw_os_error(libc::ECANCELED)),
+            false,
+        ));
+        self.ready.push(completion(token, Ok(0), false));
+        self.update(fd)
+    }
+
+    fn close(&mut self, fd: RawFd, token: u64) -> io::Result<()> {
+        if let Some(pending) = self.pending.remove(&fd) {
+            if pending.registered {
+                let mut event = libc::epoll_event { events: 0, u64: 0 };
+                syscall!(epoll_ctl(
+                    self.epoll.as_raw_fd(),
+                    libc::EPOLL_CTL_DEL,
+                    fd,
+                    &mut event
+                ))?;
+            }
+        }
+
+        let res = syscall!(close(fd));
+        self.ready.push(completion(token, res, false));
+
+        Ok(())
+    }
+
+    fn wait(
+        &mut self,
+        completions: &mut Vec<Completion>,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        while self.ready.is_empty() {
+            // Rounded up to whole milliseconds, so the wait does not end early
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    remaining
+                        .as_nanos()
+                        .div_ceil(1_000_000)
+                        .min(i32::MAX as u128) as i32
+                }
+                None => -1,
+            };
+
+            let n = match syscall!(epoll_wait(
+                self.epoll.as_raw_fd(),
+                self.events.as_mut_ptr(),
+                MAX_EVENTS as i32,
+                timeout_ms
+            )) {
+                Ok(n) => n as usize,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            // SAFETY: epoll_wait initialized the first `n` events
+            unsafe { self.events.set_len(n) };
+            let ready: Vec<_> = self.events.drain(..).map(|e| e.u64 as RawFd).collect();
+            for fd in ready {
+                self.poll(fd)?;
+            }
+        }
+
+        completions.append(&mut self.ready);
+
+        Ok(())
+    }
+}
+
+fn completion(token: u64, res: io::Result<i32>, more: bool) -> Completion {
+    Completion {
+        token,
+        result: res.unwrap_or_else(|e| -e.raw_os_error().unwrap_or(libc::EIO)),
+        more,
+    }
+}
+
+fn would_block<T>(res: &io::Result<T>) -> bool {
+    matches!(res, Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted))
+}
@@ -0,0 +1,1340 @@
+use std::{
+    collections::BTreeSet,
+    io, mem,
+    os::fd::{AsRawFd, IntoRawFd},
+    time::{Duration, Instant},
+};
+
+use super::{driver, Completion, ConnectionStats, ConnectionTable, Driver, Status};
+use crate::sys::unix::{
+    net::{Listener, Stream},
+    signal::{SignalFd, SIGINFO_LEN},
+};
+
+const READ_BUFFER_SIZE: usize = 4096;
+
+const OP_SHIFT: u64 = 56;
+const KEY_MASK: u64 = (1 << OP_SHIFT) - 1;
+
+/// What the event loop should do with a connection once the handler has processed its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Write any buffered output, then wait for more data.
+    Read,
+    /// Write any buffered output, then close the connection.
+    Close,
+}
+
+/// The deadlines connections are held to, protecting the event loop from clients which hold
+/// connections open without making progress, i.e. slowloris attacks. A connection which misses a
+/// deadline has its pending request cancelled, and is closed.
+///
+/// By default, no deadlines are enforced.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rask_core::sys::unix::io::Timeouts;
+/// let mut timeouts = Timeouts::new();
+/// timeouts
+///     .first_byte(Duration::from_secs(5))
+///     .header(Duration::from_secs(10))
+///     .idle(Duration::from_secs(60))
+///     .write(Duration::from_secs(30));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    idle: Option<Duration>,
+    first_byte: Option<Duration>,
+    header: Option<Duration>,
+    write: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Creates a set of timeouts, none of which are enforced.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how long a connection may wait for the next request once a response has been written,
+    /// and between reads of a request's body.
+    pub fn idle(&mut self, timeout: Duration) -> &mut Self {
+        self.idle = Some(timeout);
+        self
+    }
+
+    /// Sets how long a connection may wait after being accepted before sending any data.
+    pub fn first_byte(&mut self, timeout: Duration) -> &mut Self {
+        self.first_byte = Some(timeout);
+        self
+    }
+
+    /// Sets how long a request's headers may take to arrive in full, from its first byte. The
+    /// headers are complete once [`Handler::headers_complete`] returns `true`, or the handler
+    /// writes a response.
+    pub fn header(&mut self, timeout: Duration) -> &mut Self {
+        self.header = Some(timeout);
+        self
+    }
+
+    /// Sets how long writing the handler's output may take, from when it starts to when it has
+    /// been flushed in full.
+    pub fn write(&mut self, timeout: Duration) -> &mut Self {
+        self.write = Some(timeout);
+        self
+    }
+}
+
+/// Limits on the number of connections an event loop holds open, protecting it from running out
+/// of file descriptors under overload.
+///
+/// Once a limit is reached, accepting on the listeners it applies to is paused, leaving new
+/// connections queued in the listen backlog, and resumed once the number of connections falls to
+/// the low-water mark. Connections accepted before the pause takes effect are closed immediately.
+/// Accepts which fail with `EMFILE` or `ENFILE` are retried after a backoff, rather than
+/// immediately. A memory budget limits the bytes of buffers held for connections in the same way.
+///
+/// By default, the number of connections is unlimited.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rask_core::sys::unix::io::ConnectionLimits;
+/// let mut limits = ConnectionLimits::new();
+/// limits
+///     .max_connections(10_000)
+///     .max_per_listener(8_000)
+///     .memory_budget(256 * 1024 * 1024)
+///     .low_water(90)
+///     .backoff(Duration::from_millis(100));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionLimits {
+    max: Option<usize>,
+    per_listener: Option<usize>,
+    memory: Option<usize>,
+    low_water: usize,
+    backoff: Duration,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max: None,
+            per_listener: None,
+            memory: None,
+            low_water: 90,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ConnectionLimits {
+    /// Creates a set of limits, none of which are enforced.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of connections the event loop holds open across all listeners. With an
+    /// event loop per core, this is a per-core limit.
+    pub fn max_connections(&mut self, max: usize) -> &mut Self {
+        self.max = Some(max);
+        self
+    }
+
+    /// Sets the number of connections accepted on any one listener the event loop holds open.
+    pub fn max_per_listener(&mut self, max: usize) -> &mut Self {
+        self.per_listener = Some(max);
+        self
+    }
+
+    /// Sets the bytes of read and write buffers the event loop holds for connections, including
+    /// those pooled for reuse. With an event loop per core, this is a per-core budget. Pooled
+    /// buffers are freed before accepting is paused.
+    pub fn memory_budget(&mut self, bytes: usize) -> &mut Self {
+        self.memory = Some(bytes);
+        self
+    }
+
+    /// Sets the percentage of a limit the number of connections must fall to before accepting is
+    /// resumed, 90 by default.
+    pub fn low_water(&mut self, percent: u8) -> &mut Self {
+        self.low_water = usize::from(percent.min(100));
+        self
+    }
+
+    /// Sets how long accepting on a listener is paused after it fails with `EMFILE` or `ENFILE`,
+    /// 100ms by default.
+    pub fn backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Whether `connections` has reached `limit`.
+    fn reached(limit: Option<usize>, connections: usize) -> bool {
+        limit.is_some_and(|limit| connections >= limit)
+    }
+
+    /// Whether `connections` is at or below the low-water mark of `limit`.
+    fn below_low_water(&self, limit: Option<usize>, connections: usize) -> bool {
+        limit.is_none_or(|limit| connections * 100 <= limit * self.low_water)
+    }
+}
+
+/// The deadline a connection was closed for missing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// See [`Timeouts::idle`].
+    Idle,
+    /// See [`Timeouts::first_byte`].
+    FirstByte,
+    /// See [`Timeouts::header`].
+    Header,
+    /// See [`Timeouts::write`].
+    Write,
+}
+
+/// Counts of connections closed for missing each deadline.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeoutStats {
+    /// Connections which missed the idle deadline.
+    pub idle: u64,
+    /// Connections which missed the first-byte deadline.
+    pub first_byte: u64,
+    /// Connections which missed the header deadline.
+    pub header: u64,
+    /// Connections which missed the write deadline.
+    pub write: u64,
+}
+
+impl TimeoutStats {
+    fn record(&mut self, kind: TimeoutKind) {
+        match kind {
+            TimeoutKind::Idle => self.idle += 1,
+            TimeoutKind::FirstByte => self.first_byte += 1,
+            TimeoutKind::Header => self.header += 1,
+            TimeoutKind::Write => self.write += 1,
+        }
+    }
+}
+
+/// Protocol logic driven by an [`EventLoop`].
+///
+/// The event loop owns the sockets and performs all IO through its [`Driver`]. A handler only ever sees the
+/// bytes read from a connection and fills in the bytes to be written back.
+///
+/// ```no_run
+/// # use rask_core::sys::unix::{io::{Action, EventLoop, Handler}, net::Stream, tcp};
+/// struct Echo;
+///
+/// impl Handler for Echo {
+///     type Connection = ();
+///
+///     fn on_accept(&mut self, _stream: &Stream) -> Self::Connection {}
+///
+///     fn on_data(&mut self, _conn: &mut (), data: &[u8], out: &mut Vec<u8>) -> Action {
+///         out.extend_from_slice(data);
+///         Action::Read
+///     }
+/// }
+///
+/// let listeners = tcp::bind("127.0.0.1:8080").unwrap();
+/// EventLoop::new(listeners, Echo).unwrap().run().unwrap();
+/// ```
+pub trait Handler {
+    /// Protocol state kept for each connection.
+    type Connection;
+
+    /// Called when a connection is accepted, returning the state to keep for it. Unix connections
+    /// expose the peer's credentials through [`Stream::peer_cred`].
+    fn on_accept(&mut self, stream: &Stream) -> Self::Connection;
+
+    /// Called when `data` has been read from a connection. Output appended to `out` is written to
+    /// the connection before the returned [`Action`] is carried out.
+    fn on_data(&mut self, conn: &mut Self::Connection, data: &[u8], out: &mut Vec<u8>) -> Action;
+
+    /// Called after data has been read for a request whose headers are incomplete, returning
+    /// whether they have now been read in full. Until they have, or the handler writes output,
+    /// the [header timeout](Timeouts::header) applies.
+    fn headers_complete(&mut self, conn: &Self::Connection) -> bool {
+        let _ = conn;
+        false
+    }
+
+    /// Called when a connection misses a deadline, before it is closed.
+    fn on_timeout(&mut self, conn: &mut Self::Connection, kind: TimeoutKind) {
+        let _ = (conn, kind);
+    }
+
+    /// Called once a connection has been closed, either by the peer, by an error, or by returning
+    /// [`Action::Close`].
+    fn on_close(&mut self, conn: Self::Connection) {
+        let _ = conn;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Accept = 1,
+    Recv,
+    Send,
+    Close,
+    Cancel,
+    Signal,
+}
+
+/// Identifies the request a completion belongs to, packed into the SQE's user_data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token {
+    op: Op,
+    key: usize,
+}
+
+impl Token {
+    fn new(op: Op, key: usize) -> Self {
+        Self { op, key }
+    }
+
+    fn to_user_data(self) -> u64 {
+        (self.op as u64) << OP_SHIFT | (self.key as u64 & KEY_MASK)
+    }
+
+    fn from_user_data(data: u64) -> Option<Self> {
+        let op = match data >> OP_SHIFT {
+            1 => Op::Accept,
+            2 => Op::Recv,
+            3 => Op::Send,
+            4 => Op::Close,
+            5 => Op::Cancel,
+            6 => Op::Signal,
+            _ => return None,
+        };
+
+        Some(Self::new(op, (data & KEY_MASK) as usize))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// A recv is in flight.
+    Reading,
+    /// A send is in flight, after which the connection is read from or closed.
+    Writing(Action),
+}
+
+/// Which read deadline applies to a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Accepted, but no data has been read.
+    FirstByte,
+    /// Reading a request's headers.
+    Headers,
+    /// Reading the rest of a request.
+    Body,
+    /// Waiting for the next request.
+    Idle,
+}
+
+/// The state of accepting on a listener.
+#[derive(Debug, Default)]
+struct Accept {
+    /// Whether a multishot accept is in flight.
+    armed: bool,
+    /// Whether the multishot accept has been cancelled, and has not yet terminated.
+    cancelled: bool,
+    /// Set once a connection limit is reached, and cleared once the low-water mark is.
+    limited: bool,
+    /// Set when accepting fails as file descriptors have run out, until which it is paused.
+    backoff: Option<Instant>,
+    /// The number of open connections accepted on the listener.
+    connections: usize,
+}
+
+struct Connection<C> {
+    stream: Stream,
+    listener: usize,
+    state: State,
+    phase: Phase,
+    deadline: Option<(Instant, TimeoutKind)>,
+    written: usize,
+    inner: C,
+}
+
+/// A single-threaded event loop, accepting connections on a set of listeners and driving them
+/// with a [`Handler`].
+///
+/// Each listener has a multishot accept armed with the driver. Accepted connections are tracked in a
+/// [`ConnectionTable`], keyed by the index packed into each request's user_data, and have at most
+/// one request in flight at a time: a recv while waiting for data, or a send while flushing the
+/// handler's output. A connection is only removed from the table once it has no request in flight,
+/// so a key is never reused while a completion for it may still arrive. A connection whose request
+/// has been cancelled, i.e. as it missed a deadline or the event loop is shutting down, is
+/// [closing](Status::Closing), and closed as soon as the request completes.
+///
+/// Each connection has at most one deadline at a time, for the phase it is in, as configured by
+/// [`Timeouts`]. The driver's wait is bounded by the earliest deadline.
+///
+/// Once [shut down](Self::shutdown), the event loop stops accepting and lets open connections
+/// finish the request they are serving, up to a grace period, after which their requests are
+/// cancelled. It has shut down once every connection has been closed and every request it
+/// submitted has completed, so the driver can be dropped without the kernel still referencing
+/// its buffers.
+pub struct EventLoop<H: Handler> {
+    driver: Box<dyn Driver>,
+    listeners: Vec<Listener>,
+    accepts: Vec<Accept>,
+    limits: ConnectionLimits,
+    connections: ConnectionTable<Connection<H::Connection>>,
+    deadlines: BTreeSet<(Instant, usize)>,
+    timeouts: Timeouts,
+    timeout_stats: TimeoutStats,
+    completions: Vec<Completion>,
+    handler: H,
+    accepting: bool,
+    signals: Option<Signals>,
+    /// The deadline connections must close by, once shutting down.
+    shutdown: Option<Instant>,
+    /// The number of requests in flight which belong to no connection: accepts, closes, cancels
+    /// and signal reads.
+    in_flight: usize,
+}
+
+/// The signals which shut an event loop down, read with the driver.
+struct Signals {
+    fd: SignalFd,
+    /// Boxed, as the kernel writes to it until the read completes.
+    buf: Box<[u8]>,
+    grace: Duration,
+    /// Whether a read is in flight, and has not been cancelled.
+    armed: bool,
+}
+
+impl<H: Handler> EventLoop<H> {
+    /// Creates an event loop accepting on `listeners`, i.e. those returned by
+    /// [`tcp::bind`](crate::sys::unix::tcp::bind) or [`uds::bind`](crate::sys::unix::uds::bind),
+    /// using the best [`driver`] the host supports.
+    pub fn new<L>(listeners: impl IntoIterator<Item = L>, handler: H) -> io::Result<Self>
+    where
+        L: Into<Listener>,
+    {
+        Self::with_driver(driver()?, listeners, handler)
+    }
+
+    /// Creates an event loop accepting on `listeners`, performing IO with `driver`.
+    pub fn with_driver<L>(
+        driver: Box<dyn Driver>,
+        listeners: impl IntoIterator<Item = L>,
+        handler: H,
+    ) -> io::Result<Self>
+    where
+        L: Into<Listener>,
+    {
+        let listeners: Vec<_> = listeners.into_iter().map(Into::into).collect();
+        let mut event_loop = Self {
+            driver,
+            accepts: listeners.iter().map(|_| Accept::default()).collect(),
+            listeners,
+            limits: ConnectionLimits::default(),
+            connections: ConnectionTable::new(READ_BUFFER_SIZE),
+            deadlines: BTreeSet::new(),
+            timeouts: Timeouts::default(),
+            timeout_stats: TimeoutStats::default(),
+            completions: Vec::new(),
+            handler,
+            accepting: true,
+            signals: None,
+            shutdown: None,
+            in_flight: 0,
+        };
+
+        event_loop.update_accepts()?;
+
+        Ok(event_loop)
+    }
+
+    /// Gets the number of open connections.
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Gets the number of open connections serving a request, waiting for one, and closing.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.connections.stats()
+    }
+
+    /// Gets the listeners connections are accepted on, i.e. to hand them off to another process
+    /// with [`handoff`](crate::sys::unix::handoff).
+    pub fn listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    /// Sets the deadlines connections are held to. Connections which are already open are held to
+    /// the new deadlines from their next phase.
+    pub fn set_timeouts(&mut self, timeouts: &Timeouts) {
+        self.timeouts = *timeouts;
+    }
+
+    /// Sets the limits on the number of connections held open. If they have already been exceeded,
+    /// accepting is paused until the number of connections falls to the new low-water mark.
+    pub fn set_connection_limits(&mut self, limits: &ConnectionLimits) -> io::Result<()> {
+        self.limits = *limits;
+        self.connections.set_budget(limits.memory);
+        self.update_accepts()
+    }
+
+    /// Gets the number of connections closed for missing each deadline.
+    pub fn timeout_stats(&self) -> TimeoutStats {
+        self.timeout_stats
+    }
+
+    /// Gets the handler driving connections.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Shuts the event loop down when one of `signals` is received, giving open connections
+    /// `grace` to finish. A second signal closes them without waiting further.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use rask_core::sys::unix::{io::{Action, EventLoop, Handler}, net::Stream, signal::SignalFd, tcp};
+    /// # struct Echo;
+    /// # impl Handler for Echo {
+    /// #     type Connection = ();
+    /// #     fn on_accept(&mut self, _stream: &Stream) {}
+    /// #     fn on_data(&mut self, _conn: &mut (), _data: &[u8], _out: &mut Vec<u8>) -> Action {
+    /// #         Action::Close
+    /// #     }
+    /// # }
+    /// // Created before any other threads are spawned, so none of them handle the signals
+    /// let signals = SignalFd::new(&[libc::SIGTERM, libc::SIGINT]).unwrap();
+    ///
+    /// let listeners = tcp::bind("127.0.0.1:8080").unwrap();
+    /// let mut event_loop = EventLoop::new(listeners, Echo).unwrap();
+    /// event_loop.shutdown_on(signals, Duration::from_secs(30)).unwrap();
+    /// event_loop.run().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if the event loop already shuts down on
+    /// signals.
+    pub fn shutdown_on(&mut self, signals: SignalFd, grace: Duration) -> io::Result<()> {
+        if self.signals.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "event loop already shuts down on signals",
+            ));
+        }
+
+        self.signals = Some(Signals {
+            fd: signals,
+            buf: vec![0; SIGINFO_LEN].into_boxed_slice(),
+            grace,
+            armed: false,
+        });
+
+        self.arm_signals()
+    }
+
+    /// Begins shutting down: stops accepting, closes connections waiting for a request, and
+    /// gives the rest `grace` to finish the request they are serving. Once written, responses are
+    /// followed by closing the connection, rather than waiting for the next request.
+    ///
+    /// Calling it again while shutting down only ever brings the deadline forward.
+    pub fn shutdown(&mut self, grace: Duration) -> io::Result<()> {
+        self.stop_accepting()?;
+
+        let deadline = Instant::now() + grace;
+        self.shutdown = Some(self.shutdown.map_or(deadline, |d| d.min(deadline)));
+
+        let waiting: Vec<_> = self
+            .connections
+            .iter()
+            .filter(|(_, conn, _)| {
+                conn.state == State::Reading && matches!(conn.phase, Phase::FirstByte | Phase::Idle)
+            })
+            .map(|(key, _, _)| key)
+            .collect();
+        for key in waiting {
+            self.cancel_connection(key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the event loop has shut down, having closed every connection and reaped every
+    /// request it submitted.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown.is_some() && self.connections.is_empty() && self.in_flight == 0
+    }
+
+    /// Runs the event loop until it has [shut down](Self::shutdown), or an error occurs.
+    pub fn run(&mut self) -> io::Result<()> {
+        while !self.is_shut_down() {
+            self.turn()?;
+        }
+
+        Ok(())
+    }
+
+    /// Stops accepting new connections, leaving the listeners open. Connections which have already
+    /// been accepted continue to be served.
+    pub fn stop_accepting(&mut self) -> io::Result<()> {
+        self.accepting = false;
+        self.update_accepts()
+    }
+
+    /// Runs the event loop until every open connection has been closed.
+    pub fn drain(&mut self) -> io::Result<()> {
+        while !self.connections.is_empty() {
+            self.turn()?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits for at least one request to complete, or the earliest deadline, and dispatches
+    /// every completion that is ready. Connections which have missed their deadline, or the
+    /// shutdown deadline, are then cancelled.
+    pub fn turn(&mut self) -> io::Result<()> {
+        // Once every connection has been cancelled, there is nothing left to wait on the
+        // shutdown deadline for
+        let shutdown = self.shutdown.filter(|_| {
+            self.connections
+                .iter()
+                .any(|(_, _, status)| status != Status::Closing)
+        });
+        let backoff = self.accepts.iter().filter_map(|accept| accept.backoff);
+        let timeout = self
+            .deadlines
+            .first()
+            .map(|&(deadline, _)| deadline)
+            .into_iter()
+            .chain(shutdown)
+            .chain(backoff)
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        let mut completions = mem::take(&mut self.completions);
+        completions.clear();
+        self.driver.wait(&mut completions, timeout)?;
+
+        // Every completion is dispatched, even after one fails, as each is only reaped once
+        let mut res = Ok(());
+        for &completion in &completions {
+            let Some(token) = Token::from_user_data(completion.token) else {
+                continue;
+            };
+            if let Err(e) = self.dispatch(token, completion) {
+                res = res.and(Err(e));
+            }
+        }
+
+        self.completions = completions;
+        res?;
+
+        let now = Instant::now();
+        self.expire(now)?;
+
+        let mut backed_off = false;
+        for accept in &mut self.accepts {
+            if accept.backoff.is_some_and(|backoff| backoff <= now) {
+                accept.backoff = None;
+                backed_off = true;
+            }
+        }
+        if backed_off {
+            self.update_accepts()?;
+        }
+
+        let Some(deadline) = self.shutdown else {
+            return Ok(());
+        };
+
+        if deadline <= now {
+            let open: Vec<_> = self
+                .connections
+                .iter()
+                .filter(|(_, _, status)| *status != Status::Closing)
+                .map(|(key, _, _)| key)
+                .collect();
+            for key in open {
+                self.cancel_connection(key)?;
+            }
+        }
+
+        // No more signals are waited for once every connection has closed
+        if self.connections.is_empty() {
+            if let Some(signals) = &mut self.signals {
+                if signals.armed {
+                    signals.armed = false;
+                    self.in_flight += 1;
+                    self.driver.cancel(
+                        Token::new(Op::Signal, 0).to_user_data(),
+                        Token::new(Op::Cancel, 0).to_user_data(),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cancels the pending request of every connection whose deadline has passed by `now`.
+    fn expire(&mut self, now: Instant) -> io::Result<()> {
+        while let Some(&(deadline, key)) = self.deadlines.first() {
+            if deadline > now {
+                break;
+            }
+
+            self.deadlines.pop_first();
+            let Some((_, kind)) = self.connections[key].deadline.take() else {
+                continue;
+            };
+
+            if self.connections.status(key) != Status::Closing {
+                self.timeout_stats.record(kind);
+                self.handler
+                    .on_timeout(&mut self.connections[key].inner, kind);
+                self.cancel_connection(key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cancels the connection's pending request, closing it once the request completes.
+    fn cancel_connection(&mut self, key: usize) -> io::Result<()> {
+        if self.connections.status(key) == Status::Closing {
+            return Ok(());
+        }
+
+        self.connections.set_status(key, Status::Closing);
+        let op = match self.connections[key].state {
+            State::Reading => Op::Recv,
+            State::Writing(_) => Op::Send,
+        };
+
+        self.in_flight += 1;
+        self.driver.cancel(
+            Token::new(op, key).to_user_data(),
+            Token::new(Op::Cancel, key).to_user_data(),
+        )
+    }
+
+    /// Replaces the connection's deadline with one `timeout` from now, of `kind`, or clears it if
+    /// there is no timeout.
+    fn set_deadline(&mut self, key: usize, timeout: Option<Duration>, kind: TimeoutKind) {
+        let conn = &mut self.connections[key];
+        if let Some((deadline, _)) = conn.deadline.take() {
+            self.deadlines.remove(&(deadline, key));
+        }
+
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            conn.deadline = Some((deadline, kind));
+            self.deadlines.insert((deadline, key));
+        }
+    }
+
+    fn dispatch(&mut self, token: Token, completion: Completion) -> io::Result<()> {
+        match token.op {
+            Op::Accept => self.on_accept(token.key, completion),
+            Op::Recv => self.on_recv(token.key, completion.result),
+            Op::Send => self.on_send(token.key, completion.result),
+            Op::Close | Op::Cancel => {
+                self.in_flight -= 1;
+                Ok(())
+            }
+            Op::Signal => self.on_signal(completion.result),
+        }
+    }
+
+    fn on_accept(&mut self, listener: usize, completion: Completion) -> io::Result<()> {
+        let accept = &mut self.accepts[listener];
+        if !completion.more {
+            self.in_flight -= 1;
+            accept.armed = false;
+            accept.cancelled = false;
+        }
+
+        if completion.result < 0 {
+            // Retrying immediately would fail again until file descriptors are freed
+            if matches!(-completion.result, libc::EMFILE | libc::ENFILE) {
+                accept.backoff = Some(Instant::now() + self.limits.backoff);
+            }
+            return self.update_accepts();
+        }
+
+        let stream = unsafe { self.listeners[listener].accepted(completion.result) };
+
+        // Accepted before the pause took effect, so the connection is shed
+        if ConnectionLimits::reached(self.limits.max, self.connections.len())
+            || ConnectionLimits::reached(self.limits.per_listener, accept.connections)
+            || !self.connections.has_room()
+        {
+            drop(stream);
+            return self.update_accepts();
+        }
+
+        accept.connections += 1;
+        let inner = self.handler.on_accept(&stream);
+        let key = self.connections.insert(Connection {
+            stream,
+            listener,
+            state: State::Reading,
+            phase: Phase::FirstByte,
+            deadline: None,
+            written: 0,
+            inner,
+        });
+        self.set_deadline(key, self.timeouts.first_byte, TimeoutKind::FirstByte);
+
+        self.update_accepts()?;
+        self.arm_recv(key)
+    }
+
+    fn on_recv(&mut self, key: usize, res: i32) -> io::Result<()> {
+        if res <= 0 || self.connections.status(key) == Status::Closing {
+            return self.close(key);
+        }
+
+        match self.connections[key].phase {
+            Phase::FirstByte | Phase::Idle => {
+                self.connections[key].phase = Phase::Headers;
+                self.connections.set_status(key, Status::Active);
+                self.set_deadline(key, self.timeouts.header, TimeoutKind::Header);
+            }
+            Phase::Headers => {}
+            Phase::Body => self.set_deadline(key, self.timeouts.idle, TimeoutKind::Idle),
+        }
+
+        let handler = &mut self.handler;
+        let action = self
+            .connections
+            .with_buffers(key, |conn, read_buf, write_buf| {
+                handler.on_data(&mut conn.inner, &read_buf[..res as usize], write_buf)
+            });
+
+        let conn = &self.connections[key];
+        if conn.phase == Phase::Headers
+            && (!self.connections.write_buf(key).is_empty()
+                || self.handler.headers_complete(&conn.inner))
+        {
+            self.connections[key].phase = Phase::Body;
+            self.set_deadline(key, self.timeouts.idle, TimeoutKind::Idle);
+        }
+
+        self.flush_then(key, action)
+    }
+
+    fn on_signal(&mut self, res: i32) -> io::Result<()> {
+        self.in_flight -= 1;
+        let Some(signals) = &mut self.signals else {
+            return Ok(());
+        };
+
+        // The read was cancelled once the event loop finished shutting down
+        if !signals.armed {
+            return Ok(());
+        }
+        signals.armed = false;
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        if SignalFd::signal(&signals.buf[..res as usize]).is_some() {
+            // A second signal stops waiting for connections to finish
+            let grace = if self.shutdown.is_some() {
+                Duration::ZERO
+            } else {
+                signals.grace
+            };
+            self.shutdown(grace)?;
+        }
+
+        self.arm_signals()
+    }
+
+    fn on_send(&mut self, key: usize, res: i32) -> io::Result<()> {
+        if res < 0 || self.connections.status(key) == Status::Closing {
+            return self.close(key);
+        }
+
+        let conn = &mut self.connections[key];
+        conn.written += res as usize;
+
+        match conn.state {
+            State::Writing(action) => self.flush_then(key, action),
+            State::Reading => unreachable!("send completed while reading"),
+        }
+    }
+
+    /// Writes any buffered output for the connection, carrying out `action` once it is flushed.
+    fn flush_then(&mut self, key: usize, action: Action) -> io::Result<()> {
+        let written = self.connections[key].written;
+
+        if written < self.connections.write_buf(key).len() {
+            let conn = &mut self.connections[key];
+            let started = conn.state == State::Reading;
+            conn.state = State::Writing(action);
+            if started {
+                self.set_deadline(key, self.timeouts.write, TimeoutKind::Write);
+            }
+
+            let fd = self.connections[key].stream.as_raw_fd();
+            // SAFETY: the write buffer is not touched until the send completes
+            return unsafe {
+                self.driver.send(
+                    fd,
+                    &self.connections.write_buf(key)[written..],
+                    Token::new(Op::Send, key).to_user_data(),
+                )
+            };
+        }
+
+        // Once a response has been written, the connection waits for the next request, unless
+        // shutting down. Its write buffer is handed back to the pool until then.
+        let mut action = action;
+        self.connections[key].written = 0;
+        if written > 0 {
+            self.connections[key].phase = Phase::Idle;
+            self.connections.set_status(key, Status::Idle);
+            self.set_deadline(key, self.timeouts.idle, TimeoutKind::Idle);
+            if self.shutdown.is_some() {
+                action = Action::Close;
+            }
+        } else {
+            self.connections.clear_write_buf(key);
+        }
+
+        match action {
+            Action::Read => self.arm_recv(key),
+            Action::Close => self.close(key),
+        }
+    }
+
+    /// Arms or cancels each listener's multishot accept, according to whether it should be
+    /// accepting: the event loop has not stopped accepting, no connection limit or memory budget
+    /// which applies to the listener has been reached, and it is not backing off.
+    fn update_accepts(&mut self) -> io::Result<()> {
+        let limits = self.limits;
+        let total = self.connections.len();
+        let held = self.connections.held();
+
+        for (listener, accept) in self.accepts.iter_mut().enumerate() {
+            if ConnectionLimits::reached(limits.max, total)
+                || ConnectionLimits::reached(limits.per_listener, accept.connections)
+                || !self.connections.has_room()
+            {
+                accept.limited = true;
+            } else if limits.below_low_water(limits.max, total)
+                && limits.below_low_water(limits.per_listener, accept.connections)
+                && limits.below_low_water(limits.memory, held)
+            {
+                accept.limited = false;
+            }
+
+            let accepting = self.accepting && !accept.limited && accept.backoff.is_none();
+            if accepting && !accept.armed {
+                accept.armed = true;
+                self.in_flight += 1;
+                self.driver.accept_multi(
+                    self.listeners[listener].as_raw_fd(),
+                    Token::new(Op::Accept, listener).to_user_data(),
+                )?;
+            } else if !accepting && accept.armed && !accept.cancelled {
+                // Once cancelled, the accept is re-armed as it terminates, if need be
+                accept.cancelled = true;
+                self.in_flight += 1;
+                self.driver.cancel(
+                    Token::new(Op::Accept, listener).to_user_data(),
+                    Token::new(Op::Cancel, listener).to_user_data(),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the next signal, unless the event loop has finished shutting down.
+    fn arm_signals(&mut self) -> io::Result<()> {
+        let Some(signals) = &mut self.signals else {
+            return Ok(());
+        };
+        if signals.armed || (self.shutdown.is_some() && self.connections.is_empty()) {
+            return Ok(());
+        }
+
+        signals.armed = true;
+        self.in_flight += 1;
+
+        // SAFETY: the buffer is boxed, and only freed once no read is in flight
+        unsafe {
+            self.driver.read(
+                signals.fd.as_raw_fd(),
+                &mut *signals.buf,
+                Token::new(Op::Signal, 0).to_user_data(),
+            )
+        }
+    }
+
+    fn arm_recv(&mut self, key: usize) -> io::Result<()> {
+        let conn = &mut self.connections[key];
+        conn.state = State::Reading;
+        let fd = conn.stream.as_raw_fd();
+
+        // SAFETY: the read buffer is on the heap, and is kept by the connection until it is
+        // removed, which only happens once no request is in flight
+        unsafe {
+            self.driver.recv(
+                fd,
+                self.connections.read_buf(key),
+                Token::new(Op::Recv, key).to_user_data(),
+            )
+        }
+    }
+
+    fn close(&mut self, key: usize) -> io::Result<()> {
+        let conn = self.connections.remove(key);
+        if let Some((deadline, _)) = conn.deadline {
+            self.deadlines.remove(&(deadline, key));
+        }
+
+        self.accepts[conn.listener].connections -= 1;
+        let fd = conn.stream.into_raw_fd();
+        self.handler.on_close(conn.inner);
+
+        self.in_flight += 1;
+        self.driver
+            .close(fd, Token::new(Op::Close, key).to_user_data())?;
+
+        self.update_accepts()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{Shutdown, TcpStream},
+        os::unix::net::UnixStream,
+        process, thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        Action, ConnectionLimits, ConnectionStats, EventLoop, Handler, Op, TimeoutStats, Timeouts,
+        Token,
+    };
+    use crate::sys::unix::{
+        io::{Driver, EpollDriver, IoUringDriver},
+        net::Stream,
+        signal::SignalFd,
+        tcp,
+        uds::{self, PeerCred},
+    };
+
+    #[derive(Default)]
+    struct Echo {
+        accepted: usize,
+        closed: usize,
+        /// The most connections open at once.
+        peak: usize,
+        peers: Vec<PeerCred>,
+    }
+
+    impl Handler for Echo {
+        type Connection = usize;
+
+        fn on_accept(&mut self, stream: &Stream) -> usize {
+            self.accepted += 1;
+            self.peak = self.peak.max(self.accepted - self.closed);
+            self.peers.extend(stream.peer_cred().ok());
+            0
+        }
+
+        fn on_data(&mut self, read: &mut usize, data: &[u8], out: &mut Vec<u8>) -> Action {
+            *read += data.len();
+            out.extend_from_slice(data);
+            Action::Read
+        }
+
+        fn on_close(&mut self, _read: usize) {
+            self.closed += 1;
+        }
+    }
+
+    #[test]
+    fn token_round_trips() {
+        for token in [Token::new(Op::Accept, 0), Token::new(Op::Send, 1 << 40)] {
+            assert_eq!(Token::from_user_data(token.to_user_data()), Some(token));
+        }
+        assert_eq!(Token::from_user_data(0), None);
+    }
+
+    fn echoes_across_connections(driver: Box<dyn Driver>) {
+        let listeners = tcp::bind("127.0.0.1:0").unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+        let mut event_loop = EventLoop::with_driver(driver, listeners, Echo::default()).unwrap();
+
+        let clients = thread::spawn(move || {
+            for i in 0..4u8 {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                let msg = [i; 64];
+                stream.write_all(&msg).unwrap();
+
+                let mut echoed = [0; 64];
+                stream.read_exact(&mut echoed).unwrap();
+                assert_eq!(echoed, msg);
+                stream.shutdown(Shutdown::Both).unwrap();
+            }
+        });
+
+        while event_loop.handler().closed < 4 {
+            event_loop.turn().unwrap();
+        }
+
+        clients.join().unwrap();
+        assert_eq!(event_loop.handler().accepted, 4);
+        assert_eq!(event_loop.connections(), 0);
+        assert_eq!(event_loop.connection_stats(), ConnectionStats::default());
+    }
+
+    #[test]
+    fn echoes_with_io_uring() {
+        echoes_across_connections(Box::new(IoUringDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn echoes_with_epoll() {
+        echoes_across_connections(Box::new(EpollDriver::new().unwrap()));
+    }
+
+    fn closes_connections_missing_deadlines(driver: Box<dyn Driver>) {
+        let listeners = tcp::bind("127.0.0.1:0").unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+        let mut event_loop = EventLoop::with_driver(driver, listeners, Echo::default()).unwrap();
+        event_loop.set_timeouts(
+            Timeouts::new()
+                .first_byte(Duration::from_millis(20))
+                .idle(Duration::from_millis(20)),
+        );
+
+        // Connections complete in the listen backlog, without waiting to be accepted
+        let mut silent = TcpStream::connect(addr).unwrap();
+        let mut idle = TcpStream::connect(addr).unwrap();
+        idle.write_all(b"ping").unwrap();
+
+        while event_loop.handler().closed < 2 {
+            event_loop.turn().unwrap();
+        }
+
+        assert_eq!(
+            event_loop.timeout_stats(),
+            TimeoutStats {
+                idle: 1,
+                first_byte: 1,
+                ..Default::default()
+            }
+        );
+
+        let mut buf = Vec::new();
+        silent.read_to_end(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        idle.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"ping");
+    }
+
+    #[test]
+    fn closes_connections_missing_deadlines_with_io_uring() {
+        closes_connections_missing_deadlines(Box::new(IoUringDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn closes_connections_missing_deadlines_with_epoll() {
+        closes_connections_missing_deadlines(Box::new(EpollDriver::new().unwrap()));
+    }
+
+    fn sleeps_until_the_deadline(driver: Box<dyn Driver>) {
+        let listeners = tcp::bind("127.0.0.1:0").unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+        let mut event_loop = EventLoop::with_driver(driver, listeners, Echo::default()).unwrap();
+        event_loop.set_timeouts(Timeouts::new().idle(Duration::from_millis(300)));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"ping").unwrap();
+
+        // Accepting, reading and echoing take a handful of turns, after which the connection sits
+        // idle, and the event loop should wait for its deadline rather than spin
+        let start = Instant::now();
+        let mut turns = 0;
+        while start.elapsed() < Duration::from_millis(100) {
+            event_loop.turn().unwrap();
+            turns += 1;
+        }
+
+        assert!(turns < 10, "event loop turned {turns} times while idle");
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[test]
+    fn sleeps_until_the_deadline_with_io_uring() {
+        sleeps_until_the_deadline(Box::new(IoUringDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn sleeps_until_the_deadline_with_epoll() {
+        sleeps_until_the_deadline(Box::new(EpollDriver::new().unwrap()));
+    }
+
+    fn pauses_accepting_at_the_limit(driver: Box<dyn Driver>) {
+        let listeners = tcp::bind("127.0.0.1:0").unwrap();
+        let addr = listeners[0].local_addr().unwrap();
+        let mut event_loop = EventLoop::with_driver(driver, listeners, Echo::default()).unwrap();
+        event_loop
+            .set_connection_limits(ConnectionLimits::new().max_connections(1))
+            .unwrap();
+
+        let clients = thread::spawn(move || {
+            let mut first = TcpStream::connect(addr).unwrap();
+            first.write_all(b"first").unwrap();
+            let mut echoed = [0; 5];
+            first.read_exact(&mut echoed).unwrap();
+
+            // Waits in the listen backlog until the first connection closes
+            let mut second = TcpStream::connect(addr).unwrap();
+            second.write_all(b"second").unwrap();
+            first.shutdown(Shutdown::Both).unwrap();
+
+            let mut echoed = [0; 6];
+            second.read_exact(&mut echoed).unwrap();
+            assert_eq!(&echoed, b"second");
+        });
+
+        while event_loop.handler().closed < 2 {
+            event_loop.turn().unwrap();
+        }
+
+        clients.join().unwrap();
+        assert_eq!(event_loop.handler().accepted, 2);
+        assert_eq!(event_loop.handler().peak, 1);
+    }
+
+    #[test]
+    fn pauses_accepting_at_the_limit_with_io_uring() {
+        pauses_accepting_at_the_limit(Box::new(IoUringDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn pauses_accepting_at_the_limit_with_epoll() {
+        pauses_accepting_at_the_limit(Box::new(EpollDriver::new().unwrap()));
+    }
+
+    /// Echoes each line once it has been read in full.
+    #[derive(Default)]
+    struct Lines {
+        accepted: usize,
+        read: usize,
+        closed: usize,
+    }
+
+    impl Handler for Lines {
+        type Connection = Vec<u8>;
+
+        fn on_accept(&mut self, _stream: &Stream) -> Vec<u8> {
+            self.accepted += 1;
+            Vec::new()
+        }
+
+        fn on_data(&mut self, line: &mut Vec<u8>, data: &[u8], out: &mut Vec<u8>) -> Action {
+            self.read += data.len();
+            line.extend_from_slice(data);
+            if line.ends_with(b"\n") {
+                out.append(line);
+            }
+            Action::Read
+        }
+
+        fn on_close(&mut self, _line: Vec<u8>) {
+            self.closed += 1;
+        }
+    }
+
+    fn drains_connections_on_signal(driver: fn() -> Box<dyn Driver>) {
+        // Signals are blocked on the event loop's thread only, so other tests are unaffected
+        thread::spawn(move || {
+            let listeners = tcp::bind("127.0.0.1:0").unwrap();
+            let addr = listeners[0].local_addr().unwrap();
+            let mut event_loop =
+                EventLoop::with_driver(driver(), listeners, Lines::default()).unwrap();
+            let signals = SignalFd::new(&[libc::SIGUSR1]).unwrap();
+            event_loop
+                .shutdown_on(signals, Duration::from_secs(60))
+                .unwrap();
+
+            let mut idle = TcpStream::connect(addr).unwrap();
+            let mut busy = TcpStream::connect(addr).unwrap();
+            busy.write_all(b"pi").unwrap();
+            while event_loop.handler().read < 2 {
+                event_loop.turn().unwrap();
+            }
+
+            // The idle connection is closed, while the busy one finishes its line first
+            unsafe { libc::pthread_kill(libc::pthread_self(), libc::SIGUSR1) };
+            while event_loop.handler().closed < 1 {
+                event_loop.turn().unwrap();
+            }
+
+            busy.write_all(b"ng\n").unwrap();
+            event_loop.run().unwrap();
+            assert!(event_loop.is_shut_down());
+            assert_eq!(event_loop.handler().accepted, 2);
+            assert_eq!(event_loop.handler().closed, 2);
+
+            let mut buf = Vec::new();
+            idle.read_to_end(&mut buf).unwrap();
+            assert!(buf.is_empty());
+            busy.read_to_end(&mut buf).unwrap();
+            assert_eq!(buf, b"ping\n");
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn drains_connections_on_signal_with_io_uring() {
+        drains_connections_on_signal(|| Box::new(IoUringDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn drains_connections_on_signal_with_epoll() {
+        drains_connections_on_signal(|| Box::new(EpollDriver::new().unwrap()));
+    }
+
+    #[test]
+    fn echoes_over_unix_socket() {
+        let name = format!("rask-{}-event-loop", process::id());
+        let listener = uds::bind_abstract(name.as_bytes()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut event_loop = EventLoop::new([listener], Echo::default()).unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = UnixStream::connect_addr(&addr).unwrap();
+            stream.write_all(b"ping").unwrap();
+
+            let mut echoed = [0; 4];
+            stream.read_exact(&mut echoed).unwrap();
+            assert_eq!(&echoed, b"ping");
+        });
+
+        while event_loop.handler().closed < 1 {
+            event_loop.turn().unwrap();
+        }
+
+        client.join().unwrap();
+        let peers = &event_loop.handler().peers;
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].pid, process::id() as i32);
+    }
+}
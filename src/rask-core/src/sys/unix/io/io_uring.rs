@@ -1 +1,207 @@
+use std::{
+    fmt, io,
+    os::fd::RawFd,
+    time::{Duration, Instant},
+};
 
+use rask_liburing::{CqeFlags, IoUring, SqeFlags, SubmissionEntry};
+
+use super::{Completion, Driver};
+
+const RING_ENTRIES: u32 = 256;
+
+/// The user_data of the timeout request bounding [`Driver::wait`], and of requests updating it.
+/// Their completions are not reported, and updates only post one if they fail.
+const WAIT_TIMEOUT: u64 = u64::MAX;
+const WAIT_TIMEOUT_UPDATE: u64 = u64::MAX - 1;
+
+/// A [`Driver`] submitting requests to an io_uring.
+pub struct IoUringDriver {
+    ring: IoUring,
+    /// The deadline of the timeout request in flight, if any.
+    timeout_armed: Option<Instant>,
+    /// Read by the kernel when the timeout request is submitted.
+    timeout: Box<libc::timespec>,
+}
+
+impl fmt::Debug for IoUringDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoUringDriver")
+            .field("ring", &self.ring)
+            .field("timeout_armed", &self.timeout_armed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IoUringDriver {
+    /// Creates a driver backed by a new ring.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self::with_ring(IoUring::new(RING_ENTRIES)?))
+    }
+
+    /// Creates a driver backed by `ring`.
+    pub fn with_ring(ring: IoUring) -> Self {
+        Self {
+            ring,
+            timeout_armed: None,
+            timeout: Box::new(libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            }),
+        }
+    }
+}
+
+impl Driver for IoUringDriver {
+    fn accept_multi(&mut self, listener: RawFd, token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_accept_multi_flags(&listener, libc::SOCK_CLOEXEC)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    unsafe fn recv(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_recv(fd, &mut *buf)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    unsafe fn read(&mut self, fd: RawFd, buf: *mut [u8], token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_read(fd, &mut *buf, u64::MAX)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    unsafe fn send(&mut self, fd: RawFd, buf: *const [u8], token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_send(fd, &*buf)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    fn cancel(&mut self, target: u64, token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_cancel(target)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    fn close(&mut self, fd: RawFd, token: u64) -> io::Result<()> {
+        next_sqe(&mut self.ring)?
+            .prep_close(fd)
+            .set_user_data(token);
+
+        Ok(())
+    }
+
+    fn wait(
+        &mut self,
+        completions: &mut Vec<Completion>,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        // A timeout left armed by an earlier wait is only moved if the deadline is earlier. One
+        // armed for a later deadline wakes the wait early, at worst.
+        if let Some(timeout) = timeout {
+            let deadline = Instant::now() + timeout;
+            if self.timeout_armed.is_none_or(|armed| deadline < armed) {
+                *self.timeout = libc::timespec {
+                    tv_sec: timeout.as_secs() as libc::time_t,
+                    tv_nsec: timeout.subsec_nanos() as libc::c_long,
+                };
+
+                let mut sqe = next_sqe(&mut self.ring)?;
+                if self.timeout_armed.is_some() {
+                    // Fails with -ENOENT if the timeout has just fired, in which case its
+                    // completion is reaped alongside this one's
+                    sqe.prep_timeout_update(WAIT_TIMEOUT, &mut self.timeout)
+                        .set_flags(SqeFlags::CqeSkipSuccess)
+                        .set_user_data(WAIT_TIMEOUT_UPDATE);
+                } else {
+                    sqe.prep_timeout(&mut self.timeout)
+                        .set_user_data(WAIT_TIMEOUT);
+                }
+                self.timeout_armed = Some(deadline);
+            }
+        }
+
+        // Completions of updates alone do not end the wait
+        let reported = completions.len();
+        let mut timed_out = false;
+        while completions.len() == reported && !timed_out {
+            self.ring.enter_and_wait(1)?;
+
+            for cqe in self.ring.get_cqes() {
+                match cqe.get_user_data() {
+                    WAIT_TIMEOUT => {
+                        self.timeout_armed = None;
+                        timed_out = true;
+                    }
+                    WAIT_TIMEOUT_UPDATE => {}
+                    token => completions.push(Completion {
+                        token,
+                        result: cqe.result(),
+                        more: cqe.flags().contains(CqeFlags::More),
+                    }),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for IoUringDriver {
+    fn drop(&mut self) {
+        if self.timeout_armed.is_none() {
+            return;
+        }
+
+        // The kernel may still read the timespec until the timeout request is reaped
+        let Ok(mut sqe) = next_sqe(&mut self.ring) else {
+            return;
+        };
+        sqe.prep_timeout_remove(WAIT_TIMEOUT)
+            .set_user_data(WAIT_TIMEOUT_UPDATE);
+
+        while self.timeout_armed.is_some() {
+            if self.ring.enter_and_wait(1).is_err() {
+                return;
+            }
+            for cqe in self.ring.get_cqes() {
+                if cqe.get_user_data() == WAIT_TIMEOUT {
+                    self.timeout_armed = None;
+                }
+            }
+        }
+    }
+}
+
+/// Gets the next SQE, submitting pending requests first if the SQ is full.
+pub(crate) fn next_sqe(ring: &mut IoUring) -> io::Result<SubmissionEntry<'_>> {
+    reserve(ring, 1)?;
+    ring.get_sqe().map_err(io::Error::other)
+}
+
+/// Makes room for `entries` SQEs, submitting pending requests if the SQ lacks it. An SQPOLL ring's
+/// poller consumes them asynchronously, so this sleeps with `IORING_ENTER_SQ_WAIT` until it has
+/// freed up space, rather than leaving the SQ full.
+pub(crate) fn reserve(ring: &mut IoUring, entries: u32) -> io::Result<()> {
+    let mut submitter = ring.submitter();
+    if submitter.space_left() >= entries {
+        return Ok(());
+    }
+
+    submitter.submit()?;
+    while submitter.is_sqpoll() && submitter.space_left() < entries {
+        submitter.sq_wait()?;
+    }
+
+    Ok(())
+}
@@ -1,5 +1,23 @@
+mod driver;
+pub use driver::*;
+
+#[cfg(target_os = "linux")]
+mod epoll;
+#[cfg(target_os = "linux")]
+pub use epoll::*;
+
 #[cfg(target_os = "linux")]
 mod io_uring;
+#[cfg(target_os = "linux")]
+pub use io_uring::*;
+
+mod connections;
+pub use connections::*;
+
+#[cfg(target_os = "linux")]
+mod event_loop;
+#[cfg(target_os = "linux")]
+pub use event_loop::*;
 
 #[cfg(not(target_os = "linux"))]
 mod kqueue;
@@ -1,2 +1,20 @@
+macro_rules! syscall {
+    ($fn: ident($($arg: expr),* $(,)* ) ) => {{
+        let res = unsafe { libc::$fn($($arg, )*) };
+        if res == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(res)
+        }
+    }};
+}
+
+pub mod handoff;
 pub mod io;
+pub mod net;
+pub mod pipe;
+pub mod signal;
+pub mod systemd;
 pub mod tcp;
+pub mod udp;
+pub mod uds;
@@ -0,0 +1,90 @@
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use super::uds::{self, PeerCred};
+
+/// A listening socket an [`EventLoop`](super::io::EventLoop) accepts connections on.
+#[derive(Debug)]
+pub enum Listener {
+    /// A TCP listener, as returned by [`tcp::bind`](super::tcp::bind).
+    Tcp(TcpListener),
+    /// A Unix stream listener, as returned by [`uds::bind`].
+    Unix(UnixListener),
+}
+
+impl Listener {
+    /// Wraps a connection accepted on this listener.
+    ///
+    /// # Safety
+    /// `fd` must be an open socket accepted on this listener, which is owned by the returned
+    /// stream.
+    pub(crate) unsafe fn accepted(&self, fd: RawFd) -> Stream {
+        match self {
+            Listener::Tcp(_) => Stream::Tcp(TcpStream::from_raw_fd(fd)),
+            Listener::Unix(_) => Stream::Unix(UnixStream::from_raw_fd(fd)),
+        }
+    }
+}
+
+impl From<TcpListener> for Listener {
+    fn from(listener: TcpListener) -> Self {
+        Listener::Tcp(listener)
+    }
+}
+
+impl From<UnixListener> for Listener {
+    fn from(listener: UnixListener) -> Self {
+        Listener::Unix(listener)
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Listener::Tcp(listener) => listener.as_raw_fd(),
+            Listener::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+/// A connection accepted on a [`Listener`].
+#[derive(Debug)]
+pub enum Stream {
+    /// A TCP connection.
+    Tcp(TcpStream),
+    /// A Unix stream connection.
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Gets the credentials of the peer process. Only available for Unix connections.
+    pub fn peer_cred(&self) -> io::Result<PeerCred> {
+        match self {
+            Stream::Tcp(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "peer credentials are only available for Unix sockets",
+            )),
+            Stream::Unix(stream) => uds::peer_cred(stream),
+        }
+    }
+}
+
+impl AsRawFd for Stream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Stream::Tcp(stream) => stream.as_raw_fd(),
+            Stream::Unix(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+impl IntoRawFd for Stream {
+    fn into_raw_fd(self) -> RawFd {
+        match self {
+            Stream::Tcp(stream) => stream.into_raw_fd(),
+            Stream::Unix(stream) => stream.into_raw_fd(),
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Pipes used as the in-kernel buffer data is spliced through, between descriptors which are not
+//! themselves pipes.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+/// Both ends of a pipe, created with `O_CLOEXEC`.
+///
+/// See [pipe(7)](https://man.archlinux.org/man/pipe.7)
+#[derive(Debug)]
+pub struct Pipe {
+    read: OwnedFd,
+    write: OwnedFd,
+    capacity: usize,
+}
+
+impl Pipe {
+    /// Creates a pipe, asking for a capacity of `size` bytes.
+    ///
+    /// The kernel rounds the capacity up to a power of two pages, and keeps the default capacity
+    /// if `size` is beyond `/proc/sys/fs/pipe-max-size`, or the user's limit on pipe buffers has
+    /// been reached. [`Pipe::capacity`] is the capacity it settled on.
+    pub fn new(size: usize) -> io::Result<Self> {
+        let mut fds = [-1; 2];
+        syscall!(pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC))?;
+        // SAFETY: both descriptors were just created, and are owned by nothing else
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let fd = write.as_raw_fd();
+        let capacity = match syscall!(fcntl(fd, libc::F_SETPIPE_SZ, size as libc::c_int)) {
+            Ok(capacity) => capacity,
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EPERM | libc::EINVAL)) => {
+                syscall!(fcntl(fd, libc::F_GETPIPE_SZ))?
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            read,
+            write,
+            capacity: capacity as usize,
+        })
+    }
+
+    /// The end data is read from.
+    pub fn reader(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+
+    /// The end data is written to.
+    pub fn writer(&self) -> RawFd {
+        self.write.as_raw_fd()
+    }
+
+    /// The number of bytes the pipe holds before writes block.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::{fs::File, os::fd::FromRawFd};
+
+    use super::Pipe;
+
+    #[test]
+    fn carries_data_between_its_ends() {
+        let pipe = Pipe::new(1 << 20).unwrap();
+        assert!(pipe.capacity() >= 4096);
+        assert!(pipe.capacity().is_power_of_two());
+
+        // SAFETY: the files are forgotten below, leaving the pipe to close its ends
+        let (mut reader, mut writer) = unsafe {
+            (
+                File::from_raw_fd(pipe.reader()),
+                File::from_raw_fd(pipe.writer()),
+            )
+        };
+        writer.write_all(b"spliced").unwrap();
+        let mut buf = [0; 7];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"spliced");
+
+        std::mem::forget((reader, writer));
+    }
+}
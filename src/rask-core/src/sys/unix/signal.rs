@@ -0,0 +1,149 @@
+//! Signals delivered through a file descriptor, to be read like any other request rather than
+//! interrupting whichever thread they land on.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::{fmt, io, mem, ptr};
+
+/// The size of a signal read from a [`SignalFd`].
+pub const SIGINFO_LEN: usize = mem::size_of::<libc::signalfd_siginfo>();
+
+/// A file descriptor signals are read from.
+///
+/// Creating a `SignalFd` blocks its signals on the calling thread, so they are queued for the
+/// file descriptor rather than delivered to a handler. Threads inherit their creator's signal
+/// mask, so it must be created before any other threads are spawned, or those threads must block
+/// the signals themselves. Dropping it unblocks the signals it blocked.
+///
+/// See [signalfd(2)](https://man.archlinux.org/man/signalfd.2)
+pub struct SignalFd {
+    fd: OwnedFd,
+    /// The signals blocked on creation, which were not already blocked.
+    blocked: libc::sigset_t,
+}
+
+impl fmt::Debug for SignalFd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignalFd")
+            .field("fd", &self.fd)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SignalFd {
+    /// Blocks `signals` on the calling thread, and creates a file descriptor they are read from,
+    /// with `FD_CLOEXEC` set.
+    pub fn new(signals: &[libc::c_int]) -> io::Result<Self> {
+        let mask = sigset(signals)?;
+        let mut blocked = sigset(&[])?;
+        // SAFETY: sigset_t is plain data, written by pthread_sigmask
+        let mut previous: libc::sigset_t = unsafe { mem::zeroed() };
+
+        let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &mask, &mut previous) };
+        if res != 0 {
+            return Err(io::Error::from_raw_os_error(res));
+        }
+        for &signal in signals {
+            if unsafe { libc::sigismember(&previous, signal) } == 0 {
+                unsafe { libc::sigaddset(&mut blocked, signal) };
+            }
+        }
+
+        let fd = match syscall!(signalfd(-1, &mask, libc::SFD_CLOEXEC)) {
+            Ok(fd) => unsafe { OwnedFd::from_raw_fd(fd) },
+            Err(e) => {
+                unsafe { libc::pthread_sigmask(libc::SIG_UNBLOCK, &blocked, ptr::null_mut()) };
+                return Err(e);
+            }
+        };
+
+        Ok(Self { fd, blocked })
+    }
+
+    /// Waits for one of the signals, returning its number.
+    pub fn read(&self) -> io::Result<libc::c_int> {
+        let mut buf = [0u8; SIGINFO_LEN];
+        let n = syscall!(read(
+            self.fd.as_raw_fd(),
+            buf.as_mut_ptr().cast(),
+            buf.len()
+        ))?;
+
+        Self::signal(&buf[..n as usize])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "short read from signalfd"))
+    }
+
+    /// Gets the number of the signal read into `buf`, or `None` if it is too short to hold one.
+    pub fn signal(buf: &[u8]) -> Option<libc::c_int> {
+        if buf.len() < SIGINFO_LEN {
+            return None;
+        }
+
+        // SAFETY: the buffer holds a whole signalfd_siginfo, which may be unaligned
+        let info: libc::signalfd_siginfo = unsafe { ptr::read_unaligned(buf.as_ptr().cast()) };
+        Some(info.ssi_signo as libc::c_int)
+    }
+}
+
+/// Blocks `signals` on the calling thread, and so on every thread it spawns from then on, i.e.
+/// before spawning runtime threads which each read them with a [`SignalFd`]. Otherwise, a signal
+/// sent to the process may be delivered to a thread which does not block it, and handled there.
+///
+/// See [pthread_sigmask(3)](https://man.archlinux.org/man/pthread_sigmask.3)
+pub fn block(signals: &[libc::c_int]) -> io::Result<()> {
+    let mask = sigset(signals)?;
+    let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &mask, ptr::null_mut()) };
+    if res != 0 {
+        return Err(io::Error::from_raw_os_error(res));
+    }
+
+    Ok(())
+}
+
+fn sigset(signals: &[libc::c_int]) -> io::Result<libc::sigset_t> {
+    // SAFETY: sigset_t is plain data, initialized by sigemptyset
+    let mut set: libc::sigset_t = unsafe { mem::zeroed() };
+    unsafe { libc::sigemptyset(&mut set) };
+
+    for &signal in signals {
+        syscall!(sigaddset(&mut set, signal))?;
+    }
+
+    Ok(set)
+}
+
+impl AsRawFd for SignalFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl Drop for SignalFd {
+    fn drop(&mut self) {
+        unsafe { libc::pthread_sigmask(libc::SIG_UNBLOCK, &self.blocked, ptr::null_mut()) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::SignalFd;
+
+    #[test]
+    fn reads_signals_sent_to_the_thread() {
+        // Signals are blocked on a thread of its own, so other tests' threads are unaffected
+        thread::spawn(|| {
+            let signals = SignalFd::new(&[libc::SIGUSR1, libc::SIGUSR2]).unwrap();
+            unsafe {
+                libc::pthread_kill(libc::pthread_self(), libc::SIGUSR2);
+                libc::pthread_kill(libc::pthread_self(), libc::SIGUSR1);
+            }
+
+            // Pending standard signals are delivered lowest first
+            assert_eq!(signals.read().unwrap(), libc::SIGUSR1);
+            assert_eq!(signals.read().unwrap(), libc::SIGUSR2);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, io, mem, process, ptr};
+
+use super::net::Listener;
+
+/// The first file descriptor passed by systemd, following stdin, stdout and stderr.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Takes the TCP listeners passed to this process by systemd socket activation, in the order they
+/// are declared in the socket unit.
+///
+/// Returns no listeners if the process was not socket activated, or they have already been taken.
+/// Fails if any of the passed file descriptors is not a listening TCP socket.
+///
+/// See [sd_listen_fds(3)](https://man.archlinux.org/man/sd_listen_fds.3)
+pub fn bind() -> io::Result<Vec<TcpListener>> {
+    let passed = take(&[libc::AF_INET, libc::AF_INET6])?;
+    Ok(passed
+        .into_iter()
+        // SAFETY: the fds were passed to this process, and are owned by nothing else
+        .map(|(fd, _, _)| unsafe { TcpListener::from_raw_fd(fd) })
+        .collect())
+}
+
+/// Takes the listeners passed to this process by systemd socket activation, along with the names
+/// given to them with `FileDescriptorName=`, or `"unknown"` if unnamed.
+///
+/// Returns no listeners if the process was not socket activated, or they have already been taken.
+/// Fails if any of the passed file descriptors is not a listening TCP or Unix stream socket.
+pub fn listeners() -> io::Result<Vec<(String, Listener)>> {
+    let passed = take(&[libc::AF_INET, libc::AF_INET6, libc::AF_UNIX])?;
+    Ok(passed
+        .into_iter()
+        .map(|(fd, family, name)| {
+            // SAFETY: the fds were passed to this process, and are owned by nothing else
+            let listener = match family {
+                libc::AF_UNIX => Listener::Unix(unsafe { UnixListener::from_raw_fd(fd) }),
+                _ => Listener::Tcp(unsafe { TcpListener::from_raw_fd(fd) }),
+            };
+            (name, listener)
+        })
+        .collect())
+}
+
+/// Takes the file descriptors passed by systemd from the environment, unsetting it so they are not
+/// taken again, or inherited by child processes, and checks they are all listening sockets in one
+/// of `families`, returning each with its family and name.
+fn take(families: &[libc::c_int]) -> io::Result<Vec<(RawFd, libc::c_int, String)>> {
+    if TAKEN.swap(true, Ordering::AcqRel) {
+        return Ok(Vec::new());
+    }
+
+    let pid = env::var("LISTEN_PID").ok();
+    let fds = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").ok();
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let passed = listen_fds(
+        pid.as_deref(),
+        fds.as_deref(),
+        names.as_deref(),
+        SD_LISTEN_FDS_START,
+    )?;
+    validate_all(passed, families)
+}
+
+/// Parses the systemd socket activation environment, returning each passed file descriptor and
+/// its name.
+fn listen_fds(
+    pid: Option<&str>,
+    fds: Option<&str>,
+    names: Option<&str>,
+    start: RawFd,
+) -> io::Result<Vec<(RawFd, String)>> {
+    // The variables are meant for another process, which this one inherited them from
+    if pid.and_then(|pid| pid.parse::<u32>().ok()) != Some(process::id()) {
+        return Ok(Vec::new());
+    }
+
+    let Some(count) = fds
+        .and_then(|fds| fds.parse::<u32>().ok())
+        .filter(|&count| count > 0)
+    else {
+        return Err(invalid("LISTEN_FDS is not a valid count"));
+    };
+    let Some(end) = RawFd::try_from(count)
+        .ok()
+        .and_then(|count| start.checked_add(count))
+    else {
+        return Err(invalid("LISTEN_FDS is out of range"));
+    };
+
+    let names: Vec<_> = names.map_or(Vec::new(), |names| names.split(':').collect());
+    let mut passed = Vec::with_capacity(count as usize);
+
+    for (i, fd) in (start..end).enumerate() {
+        // The passed fds must not leak into child processes
+        let flags = syscall!(fcntl(fd, libc::F_GETFD))?;
+        syscall!(fcntl(fd, libc::F_SETFD, flags | libc::FD_CLOEXEC))?;
+
+        let name = if names.len() == count as usize {
+            names[i]
+        } else {
+            "unknown"
+        };
+        passed.push((fd, name.to_string()));
+    }
+
+    Ok(passed)
+}
+
+/// Checks every passed fd with [`validate`], pairing each with its family. If any is not one of
+/// `families`, all of them are closed, as they were already taken from the environment.
+fn validate_all(
+    passed: Vec<(RawFd, String)>,
+    families: &[libc::c_int],
+) -> io::Result<Vec<(RawFd, libc::c_int, String)>> {
+    let families: io::Result<Vec<_>> = passed
+        .iter()
+        .map(|&(fd, _)| validate(fd, families))
+        .collect();
+
+    match families {
+        Ok(families) => Ok(passed
+            .into_iter()
+            .zip(families)
+            .map(|((fd, name), family)| (fd, family, name))
+            .collect()),
+        Err(e) => {
+            for (fd, _) in passed {
+                let _ = syscall!(close(fd));
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Checks `fd` is a listening stream socket in one of `families`, returning its family.
+pub(super) fn validate(fd: RawFd, families: &[libc::c_int]) -> io::Result<libc::c_int> {
+    if get_opt(fd, libc::SO_TYPE)? != libc::SOCK_STREAM {
+        return Err(invalid(format!("fd {fd} is not a stream socket")));
+    }
+    if get_opt(fd, libc::SO_ACCEPTCONN)? != 1 {
+        return Err(invalid(format!("fd {fd} is not listening")));
+    }
+
+    let family = get_opt(fd, libc::SO_DOMAIN)?;
+    if !families.contains(&family) {
+        return Err(invalid(format!(
+            "fd {fd} is not a socket of the expected family"
+        )));
+    }
+
+    Ok(family)
+}
+
+fn get_opt(fd: RawFd, name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    syscall!(getsockopt(
+        fd,
+        libc::SOL_SOCKET,
+        name,
+        ptr::addr_of_mut!(value).cast(),
+        ptr::addr_of_mut!(len)
+    ))?;
+
+    Ok(value)
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        net::{TcpListener, UdpSocket},
+        os::fd::{AsRawFd, RawFd},
+        process,
+    };
+
+    use super::{listen_fds, validate, validate_all};
+
+    /// Duplicates `fd` to the lowest free descriptor at or above `at`.
+    fn dup_at(fd: RawFd, at: RawFd) -> RawFd {
+        syscall!(fcntl(fd, libc::F_DUPFD, at)).unwrap()
+    }
+
+    #[test]
+    fn ignores_variables_for_another_process() {
+        let fds = listen_fds(Some("1"), Some("2"), None, 3).unwrap();
+        assert!(fds.is_empty());
+
+        let fds = listen_fds(None, Some("2"), None, 3).unwrap();
+        assert!(fds.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_counts() {
+        let pid = process::id().to_string();
+        for fds in ["", "-1", "0", "two", "2147483647"] {
+            let err = listen_fds(Some(&pid), Some(fds), None, 3).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{fds:?}");
+        }
+    }
+
+    #[test]
+    fn closes_all_passed_fds_if_any_is_invalid() {
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let start = dup_at(tcp.as_raw_fd(), 950);
+        assert_eq!(dup_at(udp.as_raw_fd(), start + 1), start + 1);
+
+        let passed = vec![(start, "tcp".to_string()), (start + 1, "udp".to_string())];
+        let err = validate_all(passed, &[libc::AF_INET]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        for fd in [start, start + 1] {
+            let err = syscall!(fcntl(fd, libc::F_GETFD)).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+        }
+    }
+
+    #[test]
+    fn names_passed_listeners() {
+        let http = TcpListener::bind("127.0.0.1:0").unwrap();
+        let admin = TcpListener::bind("[::1]:0").unwrap();
+        let start = dup_at(http.as_raw_fd(), 900);
+        assert_eq!(dup_at(admin.as_raw_fd(), start + 1), start + 1);
+
+        let pid = process::id().to_string();
+        let fds = listen_fds(Some(&pid), Some("2"), Some("http:admin"), start).unwrap();
+        assert_eq!(
+            fds,
+            vec![
+                (start, "http".to_string()),
+                (start + 1, "admin".to_string())
+            ]
+        );
+
+        for (fd, _) in fds {
+            let flags = syscall!(fcntl(fd, libc::F_GETFD)).unwrap();
+            assert_ne!(flags & libc::FD_CLOEXEC, 0);
+            assert!(validate(fd, &[libc::AF_INET, libc::AF_INET6]).is_ok());
+            syscall!(close(fd)).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_unexpected_sockets() {
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let err = validate(udp.as_raw_fd(), &[libc::AF_INET]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let err = validate(tcp.as_raw_fd(), &[libc::AF_UNIX]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            validate(tcp.as_raw_fd(), &[libc::AF_INET]).unwrap(),
+            libc::AF_INET
+        );
+    }
+}
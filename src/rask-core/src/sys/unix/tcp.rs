@@ -1,47 +1,264 @@
+use std::ffi::CString;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs};
-use std::os::fd::{FromRawFd, RawFd};
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::time::Duration;
 use std::{io, mem, ptr};
 
 const SOCK_BACKLOG: i32 = 1024;
 
-macro_rules! syscall {
-    ($fn: ident($($arg: expr),* $(,)* ) ) => {{
-        let res = unsafe { libc::$fn($($arg, )*) };
-        if res == -1 {
-            Err(io::Error::last_os_error())
-        } else {
-            Ok(res)
+/// Binds a listener for each address `addr` resolves to, with the default [`ListenerBuilder`]
+/// options.
+pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Vec<TcpListener>> {
+    ListenerBuilder::new().bind(addr)
+}
+
+/// Configures the socket options TCP listeners are created with.
+///
+/// By default, listeners have a backlog of 1024 and set `SO_REUSEADDR` and `SO_REUSEPORT`. Every
+/// other option is left at the system default unless set.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use rask_core::sys::unix::tcp::ListenerBuilder;
+/// let listeners = ListenerBuilder::new()
+///     .backlog(4096)
+///     .only_v6(true)
+///     .nodelay(true)
+///     .defer_accept(Duration::from_secs(5))
+///     .cloexec(true)
+///     .bind("[::]:8080")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ListenerBuilder {
+    backlog: i32,
+    reuse_addr: bool,
+    reuse_port: bool,
+    only_v6: Option<bool>,
+    nodelay: bool,
+    defer_accept: Option<Duration>,
+    fastopen: Option<u32>,
+    keepalive: Option<Duration>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    cloexec: bool,
+    nonblocking: bool,
+    device: Option<CString>,
+}
+
+impl Default for ListenerBuilder {
+    fn default() -> Self {
+        Self {
+            backlog: SOCK_BACKLOG,
+            reuse_addr: true,
+            reuse_port: true,
+            only_v6: None,
+            nodelay: false,
+            defer_accept: None,
+            fastopen: None,
+            keepalive: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            cloexec: false,
+            nonblocking: false,
+            device: None,
         }
-    }};
+    }
 }
 
-pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Vec<TcpListener>> {
-    let mut listeners = Vec::new();
+impl ListenerBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    for address in addr.to_socket_addrs()? {
-        listeners.push(create_tcp_listener(address)?);
+    /// Sets the maximum length of the queue of pending connections.
+    ///
+    /// See [listen(2)](https://man.archlinux.org/man/listen.2)
+    pub fn backlog(&mut self, backlog: i32) -> &mut Self {
+        self.backlog = backlog;
+        self
     }
 
-    Ok(listeners)
-}
+    /// Sets `SO_REUSEADDR`, allowing the address to be bound while connections from a previous
+    /// listener linger in `TIME_WAIT`.
+    pub fn reuse_addr(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_addr = reuse;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT`, allowing multiple listeners to bind the same address, with the kernel
+    /// spreading connections between them.
+    pub fn reuse_port(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_port = reuse;
+        self
+    }
 
-fn create_tcp_listener(addr: SocketAddr) -> io::Result<TcpListener> {
-    let sock_fd = match addr.ip() {
-        IpAddr::V4(ip4_addr) => create_ip_socket(ip4_addr, addr.port()),
-        IpAddr::V6(ip6_addr) => create_ip6_socket(ip6_addr, addr.port()),
-    }?;
+    /// Sets `IPV6_V6ONLY` on IPv6 listeners, restricting them to IPv6 connections rather than also
+    /// accepting IPv4-mapped connections. Has no effect on IPv4 listeners.
+    pub fn only_v6(&mut self, only_v6: bool) -> &mut Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
 
-    syscall!(listen(sock_fd, SOCK_BACKLOG))?;
-    let listener = unsafe { TcpListener::from_raw_fd(sock_fd) };
+    /// Sets `TCP_NODELAY`, disabling Nagle's algorithm on accepted connections.
+    pub fn nodelay(&mut self, nodelay: bool) -> &mut Self {
+        self.nodelay = nodelay;
+        self
+    }
 
-    Ok(listener)
-}
+    /// Sets `TCP_DEFER_ACCEPT`, only completing accepts once data has arrived, or `timeout` has
+    /// passed. Has second granularity.
+    pub fn defer_accept(&mut self, timeout: Duration) -> &mut Self {
+        self.defer_accept = Some(timeout);
+        self
+    }
+
+    /// Sets `TCP_FASTOPEN`, accepting data in the SYN of up to `queue_len` pending connections.
+    pub fn fastopen(&mut self, queue_len: u32) -> &mut Self {
+        self.fastopen = Some(queue_len);
+        self
+    }
+
+    /// Sets `SO_KEEPALIVE` on accepted connections, sending keepalive probes once a connection has
+    /// been idle for `idle` with `TCP_KEEPIDLE`. Has second granularity.
+    pub fn keepalive(&mut self, idle: Duration) -> &mut Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Sets `SO_RCVBUF`, the size of the receive buffer. The kernel doubles this to allow for
+    /// bookkeeping overhead.
+    pub fn recv_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets `SO_SNDBUF`, the size of the send buffer. The kernel doubles this to allow for
+    /// bookkeeping overhead.
+    pub fn send_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
 
-fn create_ip_socket(addr: Ipv4Addr, port: u16) -> io::Result<RawFd> {
-    let sock_fd = syscall!(socket(libc::AF_INET, libc::SOCK_STREAM, 0))?;
-    set_reuse_addr(sock_fd)?;
-    set_reuse_port(sock_fd)?;
+    /// Creates listeners with `SOCK_CLOEXEC`, closing them on exec.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Creates listeners with `SOCK_NONBLOCK`.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Sets `SO_BINDTODEVICE`, only accepting connections arriving on the network interface
+    /// `device`, i.e. `eth0`. Before Linux 5.7, this requires the `CAP_NET_RAW` capability.
+    pub fn bind_device(&mut self, device: &str) -> io::Result<&mut Self> {
+        self.device = Some(CString::new(device)?);
+        Ok(self)
+    }
+
+    /// Binds a listener for each address `addr` resolves to.
+    pub fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<Vec<TcpListener>> {
+        let mut listeners = Vec::new();
+
+        for address in addr.to_socket_addrs()? {
+            listeners.push(self.create_tcp_listener(address)?);
+        }
+
+        Ok(listeners)
+    }
+
+    fn create_tcp_listener(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+
+        let mut ty = libc::SOCK_STREAM;
+        if self.cloexec {
+            ty |= libc::SOCK_CLOEXEC;
+        }
+        if self.nonblocking {
+            ty |= libc::SOCK_NONBLOCK;
+        }
+
+        let sock_fd = syscall!(socket(domain, ty, 0))?;
+        let sock = unsafe { OwnedFd::from_raw_fd(sock_fd) };
+        self.set_options(sock_fd, addr.is_ipv6())?;
+
+        match addr.ip() {
+            IpAddr::V4(ip4_addr) => bind_ip_socket(sock_fd, ip4_addr, addr.port()),
+            IpAddr::V6(ip6_addr) => bind_ip6_socket(sock_fd, ip6_addr, addr.port()),
+        }?;
+
+        syscall!(listen(sock_fd, self.backlog))?;
+        let listener = unsafe { TcpListener::from_raw_fd(sock.into_raw_fd()) };
+
+        Ok(listener)
+    }
 
+    fn set_options(&self, socket: RawFd, ipv6: bool) -> io::Result<()> {
+        if self.reuse_addr {
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+        }
+        if self.reuse_port {
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_REUSEPORT, 1)?;
+        }
+        if let (true, Some(only_v6)) = (ipv6, self.only_v6) {
+            set_opt(
+                socket,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_V6ONLY,
+                only_v6 as i32,
+            )?;
+        }
+        if self.nodelay {
+            set_opt(socket, libc::IPPROTO_TCP, libc::TCP_NODELAY, 1)?;
+        }
+        if let Some(timeout) = self.defer_accept {
+            let secs = secs(timeout);
+            set_opt(socket, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT, secs)?;
+        }
+        if let Some(queue_len) = self.fastopen {
+            let queue_len = i32::try_from(queue_len).unwrap_or(i32::MAX);
+            set_opt(socket, libc::IPPROTO_TCP, libc::TCP_FASTOPEN, queue_len)?;
+        }
+        if let Some(idle) = self.keepalive {
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+            set_opt(
+                socket,
+                libc::IPPROTO_TCP,
+                libc::TCP_KEEPIDLE,
+                secs(idle).max(1),
+            )?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            let size = i32::try_from(size).unwrap_or(i32::MAX);
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_RCVBUF, size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            let size = i32::try_from(size).unwrap_or(i32::MAX);
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_SNDBUF, size)?;
+        }
+        if let Some(device) = &self.device {
+            let device = device.as_bytes_with_nul();
+            syscall!(setsockopt(
+                socket,
+                libc::SOL_SOCKET,
+                libc::SO_BINDTODEVICE,
+                device.as_ptr().cast(),
+                device.len() as libc::socklen_t,
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) fn bind_ip_socket(sock_fd: RawFd, addr: Ipv4Addr, port: u16) -> io::Result<()> {
     let socket_address = libc::sockaddr_in {
         sin_family: libc::AF_INET as u16,
         sin_port: port.to_be(),
@@ -57,14 +274,10 @@ fn create_ip_socket(addr: Ipv4Addr, port: u16) -> io::Result<RawFd> {
         mem::size_of::<libc::sockaddr_in>() as u32
     ))?;
 
-    Ok(sock_fd)
+    Ok(())
 }
 
-fn create_ip6_socket(addr: Ipv6Addr, port: u16) -> io::Result<RawFd> {
-    let sock_fd = syscall!(socket(libc::AF_INET6, libc::SOCK_STREAM, 0))?;
-    set_reuse_addr(sock_fd)?;
-    set_reuse_port(sock_fd)?;
-
+pub(super) fn bind_ip6_socket(sock_fd: RawFd, addr: Ipv6Addr, port: u16) -> io::Result<()> {
     let socket_address = libc::sockaddr_in6 {
         sin6_family: libc::AF_INET6 as u16,
         sin6_port: port.to_be(),
@@ -80,28 +293,64 @@ fn create_ip6_socket(addr: Ipv6Addr, port: u16) -> io::Result<RawFd> {
         ptr::addr_of!(socket_address).cast(),
         mem::size_of::<libc::sockaddr_in6>() as u32
     ))?;
-    Ok(sock_fd)
+
+    Ok(())
+}
+
+fn secs(duration: Duration) -> i32 {
+    i32::try_from(duration.as_secs()).unwrap_or(i32::MAX)
 }
 
-fn set_reuse_addr(socket: RawFd) -> io::Result<()> {
-    let value: libc::c_int = 1;
+/// Steer incoming connections on the reuseport group `listener` belongs to by CPU, such that a
+/// connection handled by CPU `n` is accepted by the `n`th listener bound to the address.
+///
+/// This attaches a classic BPF program returning the current CPU with `SO_ATTACH_REUSEPORT_CBPF`.
+/// Where there are fewer listeners than CPUs, the kernel falls back to hashing. As the CPU number is
+/// used as the listener's index, the `n` listeners must be served from CPUs `0..n`, i.e. not from a
+/// cpuset of other CPUs.
+pub fn steer_by_cpu(listener: &TcpListener) -> io::Result<()> {
+    const BPF_A: u32 = 0x10;
+
+    let mut filter = [
+        libc::sock_filter {
+            code: (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16,
+            jt: 0,
+            jf: 0,
+            k: (libc::SKF_AD_OFF + libc::SKF_AD_CPU) as u32,
+        },
+        libc::sock_filter {
+            code: (libc::BPF_RET | BPF_A) as u16,
+            jt: 0,
+            jf: 0,
+            k: 0,
+        },
+    ];
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+
     syscall!(setsockopt(
-        socket,
+        listener.as_raw_fd(),
         libc::SOL_SOCKET,
-        libc::SO_REUSEADDR,
-        ptr::addr_of!(value).cast(),
-        mem::size_of::<libc::c_int>() as libc::socklen_t,
+        libc::SO_ATTACH_REUSEPORT_CBPF,
+        ptr::addr_of!(program).cast(),
+        mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
     ))?;
 
     Ok(())
 }
 
-fn set_reuse_port(socket: RawFd) -> io::Result<()> {
-    let value: libc::c_int = 1;
+pub(super) fn set_opt(
+    socket: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
     syscall!(setsockopt(
         socket,
-        libc::SOL_SOCKET,
-        libc::SO_REUSEPORT,
+        level,
+        name,
         ptr::addr_of!(value).cast(),
         mem::size_of::<libc::c_int>() as libc::socklen_t,
     ))?;
@@ -113,12 +362,28 @@ fn set_reuse_port(socket: RawFd) -> io::Result<()> {
 mod test {
     use std::{
         io, mem,
-        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
         os::fd::AsRawFd,
         ptr,
+        time::Duration,
     };
 
-    use super::bind;
+    use super::{bind, steer_by_cpu, ListenerBuilder};
+
+    fn get_opt(listener: &TcpListener, level: libc::c_int, name: libc::c_int) -> libc::c_int {
+        let mut value = 0;
+        let mut len: libc::socklen_t = mem::size_of::<libc::c_int>() as u32;
+        syscall!(getsockopt(
+            listener.as_raw_fd(),
+            level,
+            name,
+            ptr::addr_of_mut!(value).cast(),
+            ptr::addr_of_mut!(len)
+        ))
+        .unwrap();
+
+        value
+    }
 
     #[test]
     fn binds_to_multiple_addrs() {
@@ -158,4 +423,94 @@ mod test {
             assert_eq!(reuseport, 1);
         }
     }
+
+    #[test]
+    fn steers_reuseport_group_by_cpu() {
+        let first = bind("127.0.0.1:0").unwrap();
+        let addr = first[0].local_addr().unwrap();
+        let second = bind(addr).unwrap();
+
+        steer_by_cpu(&first[0]).unwrap();
+        assert_eq!(second[0].local_addr().unwrap(), addr);
+    }
+
+    #[test]
+    fn builder_sets_socket_options() {
+        let listeners = ListenerBuilder::new()
+            .reuse_addr(false)
+            .reuse_port(false)
+            .only_v6(true)
+            .nodelay(true)
+            .defer_accept(Duration::from_secs(5))
+            .fastopen(16)
+            .keepalive(Duration::from_secs(30))
+            .recv_buffer_size(64 * 1024)
+            .send_buffer_size(64 * 1024)
+            .bind("[::1]:0")
+            .unwrap();
+        let listener = &listeners[0];
+
+        assert_eq!(get_opt(listener, libc::SOL_SOCKET, libc::SO_REUSEADDR), 0);
+        assert_eq!(get_opt(listener, libc::SOL_SOCKET, libc::SO_REUSEPORT), 0);
+        assert_eq!(get_opt(listener, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY), 1);
+        assert_eq!(get_opt(listener, libc::IPPROTO_TCP, libc::TCP_NODELAY), 1);
+        // The kernel rounds the timeout up to a whole number of SYN-ACK retransmits
+        assert!(get_opt(listener, libc::IPPROTO_TCP, libc::TCP_DEFER_ACCEPT) >= 5);
+        assert_eq!(get_opt(listener, libc::IPPROTO_TCP, libc::TCP_FASTOPEN), 16);
+        assert_eq!(get_opt(listener, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 1);
+        assert_eq!(get_opt(listener, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE), 30);
+        assert_eq!(
+            get_opt(listener, libc::SOL_SOCKET, libc::SO_RCVBUF),
+            128 * 1024
+        );
+        assert_eq!(
+            get_opt(listener, libc::SOL_SOCKET, libc::SO_SNDBUF),
+            128 * 1024
+        );
+    }
+
+    #[test]
+    fn builder_sets_socket_flags() {
+        let listeners = ListenerBuilder::new()
+            .cloexec(true)
+            .nonblocking(true)
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let fd = listeners[0].as_raw_fd();
+
+        let fd_flags = syscall!(fcntl(fd, libc::F_GETFD)).unwrap();
+        assert_ne!(fd_flags & libc::FD_CLOEXEC, 0);
+        let status_flags = syscall!(fcntl(fd, libc::F_GETFL)).unwrap();
+        assert_ne!(status_flags & libc::O_NONBLOCK, 0);
+        assert_eq!(
+            listeners[0].accept().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn builder_binds_to_device() {
+        let res = ListenerBuilder::new()
+            .bind_device("lo")
+            .unwrap()
+            .bind("127.0.0.1:0");
+
+        let listeners = match res {
+            // Binding to a device requires CAP_NET_RAW before Linux 5.7
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => return,
+            res => res.unwrap(),
+        };
+
+        let mut device = [0u8; libc::IFNAMSIZ];
+        let mut len = device.len() as libc::socklen_t;
+        syscall!(getsockopt(
+            listeners[0].as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            device.as_mut_ptr().cast(),
+            ptr::addr_of_mut!(len)
+        ))
+        .unwrap();
+        assert_eq!(&device[..len as usize], b"lo\0");
+    }
 }
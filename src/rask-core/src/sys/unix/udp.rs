@@ -0,0 +1,344 @@
+//! UDP sockets, with support for segmentation offload.
+//!
+//! With generic segmentation offload (GSO), a single send of a buffer holding several datagrams
+//! is split into `segment_size` datagrams by the kernel, or the NIC. With generic receive offload
+//! (GRO), enabled by [`SocketBuilder::gro`], the kernel coalesces datagrams of the same flow into
+//! a single receive, reporting their size in a `UDP_GRO` control message. Both save the per
+//! datagram cost of a syscall, or ring request.
+
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::{io, mem, ptr};
+
+use super::tcp::{bind_ip6_socket, bind_ip_socket, set_opt};
+
+/// The most segments the kernel will split a single `UDP_SEGMENT` send into.
+pub const MAX_SEGMENTS: usize = 64;
+
+/// Binds a socket to the first address `addr` resolves to that can be bound, with the default
+/// [`SocketBuilder`] options.
+pub fn bind(addr: impl ToSocketAddrs) -> io::Result<UdpSocket> {
+    SocketBuilder::new().bind(addr)
+}
+
+/// Configures the socket options UDP sockets are created with.
+///
+/// By default, every option is left at the system default unless set.
+///
+/// ```no_run
+/// # use rask_core::sys::unix::udp::SocketBuilder;
+/// let socket = SocketBuilder::new()
+///     .reuse_port(true)
+///     .gro(true)
+///     .recv_buffer_size(1 << 21)
+///     .cloexec(true)
+///     .bind("[::]:4433")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SocketBuilder {
+    reuse_addr: bool,
+    reuse_port: bool,
+    only_v6: Option<bool>,
+    gro: bool,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    cloexec: bool,
+    nonblocking: bool,
+}
+
+impl SocketBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `SO_REUSEADDR`.
+    pub fn reuse_addr(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_addr = reuse;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT`, allowing multiple sockets to bind the same address, with the kernel
+    /// spreading datagrams between them by flow.
+    pub fn reuse_port(&mut self, reuse: bool) -> &mut Self {
+        self.reuse_port = reuse;
+        self
+    }
+
+    /// Sets `IPV6_V6ONLY` on IPv6 sockets, restricting them to IPv6 datagrams rather than also
+    /// receiving IPv4-mapped ones. Has no effect on IPv4 sockets.
+    pub fn only_v6(&mut self, only_v6: bool) -> &mut Self {
+        self.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// Sets `UDP_GRO`, coalescing received datagrams of the same flow and size into a single
+    /// receive. Their size is reported in a control message, read with [`gro_segment_size`].
+    ///
+    /// Requires Linux 5.0 or later.
+    pub fn gro(&mut self, gro: bool) -> &mut Self {
+        self.gro = gro;
+        self
+    }
+
+    /// Sets `SO_RCVBUF`, the size of the receive buffer. The kernel doubles this to allow for
+    /// bookkeeping overhead.
+    pub fn recv_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets `SO_SNDBUF`, the size of the send buffer. The kernel doubles this to allow for
+    /// bookkeeping overhead.
+    pub fn send_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Creates sockets with `SOCK_CLOEXEC`, closing them on exec.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Creates sockets with `SOCK_NONBLOCK`.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Binds a socket to the first address `addr` resolves to that can be bound.
+    pub fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<UdpSocket> {
+        let mut last_err = None;
+
+        for address in addr.to_socket_addrs()? {
+            match self.create_udp_socket(address) {
+                Ok(socket) => return Ok(socket),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    fn create_udp_socket(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let domain = match addr {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+
+        let mut ty = libc::SOCK_DGRAM;
+        if self.cloexec {
+            ty |= libc::SOCK_CLOEXEC;
+        }
+        if self.nonblocking {
+            ty |= libc::SOCK_NONBLOCK;
+        }
+
+        let sock_fd = syscall!(socket(domain, ty, 0))?;
+        let sock = unsafe { OwnedFd::from_raw_fd(sock_fd) };
+        self.set_options(sock_fd, addr.is_ipv6())?;
+
+        match addr.ip() {
+            IpAddr::V4(ip4_addr) => bind_ip_socket(sock_fd, ip4_addr, addr.port()),
+            IpAddr::V6(ip6_addr) => bind_ip6_socket(sock_fd, ip6_addr, addr.port()),
+        }?;
+
+        Ok(unsafe { UdpSocket::from_raw_fd(sock.into_raw_fd()) })
+    }
+
+    fn set_options(&self, socket: RawFd, ipv6: bool) -> io::Result<()> {
+        if self.reuse_addr {
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+        }
+        if self.reuse_port {
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_REUSEPORT, 1)?;
+        }
+        if let (true, Some(only_v6)) = (ipv6, self.only_v6) {
+            set_opt(
+                socket,
+                libc::IPPROTO_IPV6,
+                libc::IPV6_V6ONLY,
+                only_v6 as i32,
+            )?;
+        }
+        if self.gro {
+            set_opt(socket, libc::SOL_UDP, libc::UDP_GRO, 1)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            let size = i32::try_from(size).unwrap_or(i32::MAX);
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_RCVBUF, size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            let size = i32::try_from(size).unwrap_or(i32::MAX);
+            set_opt(socket, libc::SOL_SOCKET, libc::SO_SNDBUF, size)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A control message for `sendmsg`, splitting the message's payload into datagrams of
+/// `segment_size` bytes with `UDP_SEGMENT`. The final datagram may be shorter.
+///
+/// Requires Linux 4.18 or later.
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentControl {
+    // Aligned for `cmsghdr`, and large enough for one holding a u16
+    buf: [u64; 4],
+    len: usize,
+}
+
+impl SegmentControl {
+    /// Creates the control message for datagrams of `segment_size` bytes.
+    pub fn new(segment_size: u16) -> Self {
+        let data_len = mem::size_of::<u16>() as libc::c_uint;
+        let mut control = Self {
+            buf: [0; 4],
+            len: unsafe { libc::CMSG_SPACE(data_len) } as usize,
+        };
+
+        // SAFETY: the buffer is aligned and sized to hold a single header followed by a u16
+        unsafe {
+            let cmsg = control.buf.as_mut_ptr().cast::<libc::cmsghdr>();
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len) as usize;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<u16>(), segment_size);
+        }
+
+        control
+    }
+
+    /// A pointer to the control message, for `msg_control`.
+    pub fn as_ptr(&self) -> *const libc::c_void {
+        self.buf.as_ptr().cast()
+    }
+
+    /// The length of the control message, for `msg_controllen`.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Always `false`, as the control message holds a segment size.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Finds the size of the datagrams coalesced into a single receive by `UDP_GRO` from the control
+/// messages received with it, which need not be aligned.
+///
+/// Returns `None` if the receive holds a single datagram.
+pub fn gro_segment_size(control: &[u8]) -> Option<u16> {
+    let header_len = unsafe { libc::CMSG_LEN(0) } as usize;
+    let align = mem::size_of::<usize>();
+    let mut offset = 0;
+
+    while offset + header_len <= control.len() {
+        // SAFETY: the header is in bounds, and read without assuming alignment
+        let cmsg: libc::cmsghdr =
+            unsafe { ptr::read_unaligned(control.as_ptr().add(offset).cast()) };
+        let len = cmsg.cmsg_len;
+        if len < header_len || offset + len > control.len() {
+            return None;
+        }
+
+        if cmsg.cmsg_level == libc::SOL_UDP
+            && cmsg.cmsg_type == libc::UDP_GRO
+            && len >= header_len + mem::size_of::<libc::c_int>()
+        {
+            // SAFETY: the data is in bounds, as checked against the header's length
+            let size: libc::c_int =
+                unsafe { ptr::read_unaligned(control.as_ptr().add(offset + header_len).cast()) };
+            return u16::try_from(size).ok();
+        }
+
+        offset += len.next_multiple_of(align);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, mem, net::UdpSocket, os::fd::AsRawFd, ptr};
+
+    use super::{gro_segment_size, SegmentControl, SocketBuilder};
+
+    #[test]
+    fn finds_gro_segment_size_among_control_messages() {
+        let mut buf = [0u64; 8];
+        let first = unsafe { libc::CMSG_SPACE(mem::size_of::<u8>() as u32) } as usize;
+        unsafe {
+            let cmsg = buf.as_mut_ptr().cast::<libc::cmsghdr>();
+            (*cmsg).cmsg_level = libc::IPPROTO_IP;
+            (*cmsg).cmsg_type = libc::IP_TOS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u8>() as u32) as usize;
+
+            let cmsg = buf
+                .as_mut_ptr()
+                .cast::<u8>()
+                .add(first)
+                .cast::<libc::cmsghdr>();
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_GRO;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<libc::c_int>() as u32) as usize;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>(), 1200);
+        }
+
+        let control = unsafe {
+            std::slice::from_raw_parts(buf.as_ptr().cast::<u8>(), mem::size_of_val(&buf))
+        };
+        assert_eq!(gro_segment_size(control), Some(1200));
+        assert_eq!(gro_segment_size(&control[..first]), None);
+        assert_eq!(gro_segment_size(&[]), None);
+    }
+
+    #[test]
+    fn coalesces_segmented_send() {
+        let receiver = SocketBuilder::new().gro(true).bind("127.0.0.1:0").unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(receiver.local_addr().unwrap()).unwrap();
+
+        let payload = [7u8; 250];
+        let control = SegmentControl::new(100);
+        let mut iov = libc::iovec {
+            iov_base: payload.as_ptr() as *mut _,
+            iov_len: payload.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_ptr() as *mut _;
+        msg.msg_controllen = control.len();
+        let sent = syscall!(sendmsg(sender.as_raw_fd(), &msg, 0)).unwrap();
+        assert_eq!(sent, 250);
+
+        let mut buf = [0u8; 1024];
+        let mut control = [0u64; 8];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = mem::size_of_val(&control);
+        let received = syscall!(recvmsg(receiver.as_raw_fd(), &mut msg, 0)).unwrap();
+
+        let control = unsafe {
+            std::slice::from_raw_parts(control.as_ptr().cast::<u8>(), msg.msg_controllen)
+        };
+        assert_eq!(received, 250);
+        assert_eq!(gro_segment_size(control), Some(100));
+        assert!(buf[..250].iter().all(|&b| b == 7));
+    }
+}
@@ -0,0 +1,339 @@
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{self as unix_fs, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::{fs, io, mem, ptr};
+
+const SOCK_BACKLOG: i32 = 1024;
+
+/// Binds a listener to the filesystem path `path`, with the default [`ListenerBuilder`] options.
+pub fn bind(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    ListenerBuilder::new().bind(path)
+}
+
+/// Binds a listener to `name` in the Linux abstract namespace, with the default
+/// [`ListenerBuilder`] options.
+pub fn bind_abstract(name: &[u8]) -> io::Result<UnixListener> {
+    ListenerBuilder::new().bind_abstract(name)
+}
+
+/// The credentials of the process on the other end of a Unix socket, as of when it connected.
+///
+/// See [unix(7)](https://man.archlinux.org/man/unix.7)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    /// The process Id of the peer.
+    pub pid: libc::pid_t,
+    /// The effective user Id of the peer.
+    pub uid: libc::uid_t,
+    /// The effective group Id of the peer.
+    pub gid: libc::gid_t,
+}
+
+/// Gets the credentials of the peer connected to `stream` with `SO_PEERCRED`.
+pub fn peer_cred(stream: &impl AsRawFd) -> io::Result<PeerCred> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    syscall!(getsockopt(
+        stream.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_PEERCRED,
+        ptr::addr_of_mut!(cred).cast(),
+        ptr::addr_of_mut!(len)
+    ))?;
+
+    Ok(PeerCred {
+        pid: cred.pid,
+        uid: cred.uid,
+        gid: cred.gid,
+    })
+}
+
+/// Configures the options Unix stream listeners are created with.
+///
+/// By default, listeners have a backlog of 1024, and a stale socket file left at the path by a
+/// previous listener is removed before binding.
+///
+/// ```no_run
+/// # use rask_core::sys::unix::uds::ListenerBuilder;
+/// let listener = ListenerBuilder::new()
+///     .mode(0o660)
+///     .owner(None, Some(1000))
+///     .cloexec(true)
+///     .bind("/run/rask/rask.sock")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ListenerBuilder {
+    backlog: i32,
+    remove_stale: bool,
+    mode: Option<u32>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    cloexec: bool,
+    nonblocking: bool,
+}
+
+impl Default for ListenerBuilder {
+    fn default() -> Self {
+        Self {
+            backlog: SOCK_BACKLOG,
+            remove_stale: true,
+            mode: None,
+            uid: None,
+            gid: None,
+            cloexec: false,
+            nonblocking: false,
+        }
+    }
+}
+
+impl ListenerBuilder {
+    /// Creates a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum length of the queue of pending connections.
+    ///
+    /// See [listen(2)](https://man.archlinux.org/man/listen.2)
+    pub fn backlog(&mut self, backlog: i32) -> &mut Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Sets whether a socket file left at the path is removed before binding, so long as no
+    /// listener is accepting on it. Files which are not sockets are never removed.
+    pub fn remove_stale(&mut self, remove: bool) -> &mut Self {
+        self.remove_stale = remove;
+        self
+    }
+
+    /// Sets the permissions of the socket file, i.e. `0o660`. Connecting requires write
+    /// permission. Has no effect in the abstract namespace.
+    pub fn mode(&mut self, mode: u32) -> &mut Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the owner and group of the socket file, leaving either unchanged if `None`. Has no
+    /// effect in the abstract namespace.
+    pub fn owner(&mut self, uid: Option<u32>, gid: Option<u32>) -> &mut Self {
+        self.uid = uid;
+        self.gid = gid;
+        self
+    }
+
+    /// Creates listeners with `SOCK_CLOEXEC`, closing them on exec.
+    pub fn cloexec(&mut self, cloexec: bool) -> &mut Self {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Creates listeners with `SOCK_NONBLOCK`.
+    pub fn nonblocking(&mut self, nonblocking: bool) -> &mut Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Binds a listener to the filesystem path `path`.
+    ///
+    /// The socket file's mode and ownership are set before the listener starts listening, so no
+    /// connection is accepted with the default permissions.
+    pub fn bind(&self, path: impl AsRef<Path>) -> io::Result<UnixListener> {
+        let path = path.as_ref();
+        let bytes = path.as_os_str().as_encoded_bytes();
+        if bytes.contains(&0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "socket path contains a nul byte",
+            ));
+        }
+
+        if self.remove_stale {
+            remove_stale(path)?;
+        }
+
+        let sock = self.create_socket()?;
+        bind_unix_socket(sock.as_raw_fd(), bytes)?;
+
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if self.uid.is_some() || self.gid.is_some() {
+            unix_fs::chown(path, self.uid, self.gid)?;
+        }
+
+        self.listen(sock)
+    }
+
+    /// Binds a listener to `name` in the Linux abstract namespace. Abstract sockets have no
+    /// file, and disappear once the last reference to them is closed.
+    pub fn bind_abstract(&self, name: &[u8]) -> io::Result<UnixListener> {
+        let mut path = Vec::with_capacity(name.len() + 1);
+        path.push(0);
+        path.extend_from_slice(name);
+
+        let sock = self.create_socket()?;
+        bind_unix_socket(sock.as_raw_fd(), &path)?;
+
+        self.listen(sock)
+    }
+
+    fn create_socket(&self) -> io::Result<OwnedFd> {
+        let mut ty = libc::SOCK_STREAM;
+        if self.cloexec {
+            ty |= libc::SOCK_CLOEXEC;
+        }
+        if self.nonblocking {
+            ty |= libc::SOCK_NONBLOCK;
+        }
+
+        let sock_fd = syscall!(socket(libc::AF_UNIX, ty, 0))?;
+        Ok(unsafe { OwnedFd::from_raw_fd(sock_fd) })
+    }
+
+    fn listen(&self, sock: OwnedFd) -> io::Result<UnixListener> {
+        syscall!(listen(sock.as_raw_fd(), self.backlog))?;
+        Ok(unsafe { UnixListener::from_raw_fd(sock.into_raw_fd()) })
+    }
+}
+
+/// Removes the socket file at `path` if no listener is accepting connections on it.
+fn remove_stale(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "path exists and is not a socket",
+        ));
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::from(io::ErrorKind::AddrInUse)),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(e) => Err(e),
+    }
+}
+
+/// Binds `sock_fd` to `path`, which is in the abstract namespace if it begins with a nul byte.
+fn bind_unix_socket(sock_fd: RawFd, path: &[u8]) -> io::Result<()> {
+    let mut socket_address = libc::sockaddr_un {
+        sun_family: libc::AF_UNIX as u16,
+        sun_path: [0; 108],
+    };
+
+    // Filesystem paths must leave room for a nul terminator
+    let abstract_name = path.first() == Some(&0);
+    if path.len() + !abstract_name as usize > socket_address.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "socket path is too long",
+        ));
+    }
+
+    for (dst, src) in socket_address.sun_path.iter_mut().zip(path) {
+        *dst = *src as libc::c_char;
+    }
+
+    let offset = mem::size_of::<libc::sa_family_t>();
+    let len = if abstract_name {
+        offset + path.len()
+    } else {
+        offset + path.len() + 1
+    };
+
+    syscall!(bind(
+        sock_fd,
+        ptr::addr_of!(socket_address).cast(),
+        len as libc::socklen_t
+    ))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs, io,
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::{UnixListener, UnixStream},
+        },
+        path::PathBuf,
+        process,
+    };
+
+    use super::{bind, bind_abstract, peer_cred, ListenerBuilder};
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rask-{}-{name}.sock", process::id()))
+    }
+
+    #[test]
+    fn binds_filesystem_socket_with_mode() {
+        let path = socket_path("mode");
+        let listener = ListenerBuilder::new().mode(0o600).bind(&path).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+
+        let _client = UnixStream::connect(&path).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let cred = peer_cred(&server).unwrap();
+        assert_eq!(cred.pid, process::id() as i32);
+        assert_eq!(cred.uid, unsafe { libc::geteuid() });
+        assert_eq!(cred.gid, unsafe { libc::getegid() });
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_stale_socket_file() {
+        let path = socket_path("stale");
+        drop(bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = bind(&path).unwrap();
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        drop(listener);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_remove_regular_file() {
+        let path = socket_path("file");
+        fs::write(&path, b"").unwrap();
+
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binds_abstract_socket() {
+        let name = format!("rask-{}-abstract", process::id());
+        let listener: UnixListener = bind_abstract(name.as_bytes()).unwrap();
+
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(
+            std::os::linux::net::SocketAddrExt::as_abstract_name(&addr),
+            Some(name.as_bytes())
+        );
+    }
+}
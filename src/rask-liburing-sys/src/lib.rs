@@ -14,12 +14,14 @@
 //! Rust binding for liburing
 
 use std::{
-    ffi::OsString,
+    ffi::CStr,
     ptr::{self, NonNull},
     sync::atomic::{AtomicU16, AtomicU32, Ordering},
 };
 
-use libc::{c_void, mode_t, msghdr, sockaddr, socklen_t, timespec};
+use libc::{
+    c_void, id_t, idtype_t, mode_t, msghdr, siginfo_t, sockaddr, socklen_t, statx, timespec,
+};
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
@@ -31,7 +33,10 @@ pub fn io_uring_opcode_supported(probe: &io_uring_probe, op: i32) -> i32 {
         return 0;
     }
 
-    (probe.ops.1[op as usize].flags & IO_URING_OP_SUPPORTED as u16).into()
+    // SAFETY: the kernel fills in `ops_len` entries following the probe header
+    let ops = unsafe { probe.ops.as_slice(probe.ops_len as usize) };
+    ops.get(op as usize)
+        .map_or(0, |o| (o.flags & IO_URING_OP_SUPPORTED as u16).into())
 }
 
 /// Marks `seen` IO completions belonging to CQ as consumed.
@@ -172,6 +177,21 @@ pub fn io_uring_sqe_set_flags(sqe: &mut io_uring_sqe, flags: u32) {
     sqe.flags = flags as u8
 }
 
+/// Clears the fields of `sqe` which only some requests use, as an SQE slot is reused by whichever
+/// request is prepared in it next. Otherwise, i.e. a `file_index` left by a direct accept turns
+/// the next plain accept in the slot into a direct one.
+#[inline]
+fn io_uring_prep_reset(sqe: &mut io_uring_sqe) {
+    sqe.__bindgen_anon_3.rw_flags = 0;
+    sqe.__bindgen_anon_4.buf_index = 0;
+    sqe.personality = 0;
+    sqe.__bindgen_anon_5.file_index = 0;
+    // SAFETY: the union only overlays the command data of 128-byte SQEs on these fields
+    let tail = unsafe { sqe.__bindgen_anon_6.__bindgen_anon_1.as_mut() };
+    tail.addr3 = 0;
+    tail.__pad2[0] = 0;
+}
+
 #[inline]
 fn io_uring_prep_rw<T>(
     op: u32,
@@ -188,6 +208,7 @@ fn io_uring_prep_rw<T>(
     sqe.__bindgen_anon_1.off = offset;
     sqe.__bindgen_anon_2.addr = addr.map_or(ptr::null(), |a| a) as u64;
     sqe.len = len;
+    io_uring_prep_reset(sqe);
 }
 
 #[inline]
@@ -205,6 +226,7 @@ fn io_uring_prep_rw_buf<T>(
     sqe.__bindgen_anon_1.off = offset;
     sqe.__bindgen_anon_2.addr = addr.map_or(ptr::null(), |a| a.as_ptr()) as u64;
     sqe.len = addr.map_or(0, |a| a.len()) as u32;
+    io_uring_prep_reset(sqe);
 }
 
 #[inline]
@@ -216,6 +238,7 @@ fn io_uring_prep_rw_null(op: u32, sqe: &mut io_uring_sqe, fd: i32, len: u32, off
     sqe.__bindgen_anon_1.off = offset;
     sqe.__bindgen_anon_2.addr = ptr::null::<usize>() as u64;
     sqe.len = len;
+    io_uring_prep_reset(sqe);
 }
 
 /// Prepares a splice request
@@ -679,7 +702,7 @@ pub fn io_uring_prep_accept(
         IORING_OP_ACCEPT,
         sqe,
         fd,
-        addr.as_ref(),
+        addr.as_deref(),
         0,
         addrlen.map_or(ptr::null_mut(), |a| a as *mut socklen_t) as u64,
     );
@@ -960,15 +983,20 @@ pub fn io_uring_prep_fallocate(sqe: &mut io_uring_sqe, fd: i32, mode: i32, offse
     sqe.__bindgen_anon_2.addr = len;
 }
 
+/// Prepares an [`openat`](https://man.archlinux.org/man/openat.2) request
+///
+/// The submission queue entry is setup to open `path`, relative to the directory `dfd`, with
+/// `flags` and `mode`. `path` must live until the request has been submitted.
 #[inline]
 pub fn io_uring_prep_openat(
     sqe: &mut io_uring_sqe,
     dfd: i32,
-    path: OsString,
+    path: &CStr,
     flags: i32,
     mode: mode_t,
 ) {
-    io_uring_prep_rw(IORING_OP_OPENAT, sqe, dfd, Some(&path), mode, 0);
+    io_uring_prep_rw_null(IORING_OP_OPENAT, sqe, dfd, mode, 0);
+    sqe.__bindgen_anon_2.addr = path.as_ptr() as u64;
     sqe.__bindgen_anon_3.open_flags = flags as u32;
 }
 
@@ -976,7 +1004,7 @@ pub fn io_uring_prep_openat(
 pub fn io_uring_prep_openat_direct(
     sqe: &mut io_uring_sqe,
     dfd: i32,
-    path: OsString,
+    path: &CStr,
     flags: i32,
     mode: mode_t,
     file_index: u32,
@@ -1044,7 +1072,33 @@ pub fn io_uring_prep_write(sqe: &mut io_uring_sqe, fd: i32, buf: &[u8], offset:
     io_uring_prep_rw_buf(IORING_OP_WRITE, sqe, fd, Some(buf), offset);
 }
 
-// TODO: statx fadvise madvise
+/// Prepares a [`statx`](https://man.archlinux.org/man/statx.2) request
+///
+/// The submission queue entry is setup to query the fields in `mask` of `path`, relative to the
+/// directory `dfd`, writing them to `statxbuf`. With [`libc::AT_EMPTY_PATH`] in `flags` and an
+/// empty path, `dfd` itself is queried. `path` must live until the request has been submitted,
+/// and `statxbuf` until it completes.
+#[inline]
+pub fn io_uring_prep_statx(
+    sqe: &mut io_uring_sqe,
+    dfd: i32,
+    path: &CStr,
+    flags: i32,
+    mask: u32,
+    statxbuf: &mut statx,
+) {
+    io_uring_prep_rw_null(
+        IORING_OP_STATX,
+        sqe,
+        dfd,
+        mask,
+        statxbuf as *mut statx as u64,
+    );
+    sqe.__bindgen_anon_2.addr = path.as_ptr() as u64;
+    sqe.__bindgen_anon_3.statx_flags = flags as u32;
+}
+
+// TODO: fadvise madvise
 
 /// Prepares a [`send`](https://man.archlinux.org/man/send.2) request
 ///
@@ -1215,8 +1269,17 @@ pub fn io_uring_prep_recv_multishot(
     sqe.ioprio |= IORING_RECV_MULTISHOT as u16;
 }
 
-// TODO: recvmsg helpers, openat2, epollctl, provide_buffers, remove_buffers, shutdown, unlink,
-// rename, sync_file_range, mkdir, symlink, link
+/// Prepares a [`shutdown`](https://man.archlinux.org/man/shutdown.2) request
+///
+/// The submission queue entry is setup to use the file descriptor `fd` to shut down all or part
+/// of a full-duplex connection, as specified by `how`.
+#[inline]
+pub fn io_uring_prep_shutdown(sqe: &mut io_uring_sqe, fd: i32, how: i32) {
+    io_uring_prep_rw_null(IORING_OP_SHUTDOWN, sqe, fd, how as u32, 0);
+}
+
+// TODO: recvmsg helpers, openat2, epollctl, provide_buffers, remove_buffers, unlink, rename,
+// sync_file_range, mkdir, symlink, link
 
 #[inline]
 pub fn io_uring_prep_msg_ring_cqe_flags(
@@ -1326,6 +1389,139 @@ pub fn io_uring_prep_socket_direct_alloc(
     io_uring_set_target_fixed_file(sqe, (IORING_FILE_INDEX_ALLOC - 1) as u32);
 }
 
+// Futex and waitid operations are not present in the vendored liburing 2.4, and mirror their
+// definitions from later liburing releases.
+
+/// Available since Linux 6.7
+pub const IORING_OP_WAITID: u32 = 50;
+/// Available since Linux 6.7
+pub const IORING_OP_FUTEX_WAIT: u32 = 51;
+/// Available since Linux 6.7
+pub const IORING_OP_FUTEX_WAKE: u32 = 52;
+/// Available since Linux 6.7
+pub const IORING_OP_FUTEX_WAITV: u32 = 53;
+
+/// The futex word is 32 bits wide.
+pub const FUTEX2_SIZE_U32: u32 = 0x02;
+/// The futex is private to the process.
+pub const FUTEX2_PRIVATE: u32 = 128;
+/// Match any bit when comparing a futex bitset.
+pub const FUTEX_BITSET_MATCH_ANY: u64 = 0xffffffff;
+
+/// An entry of the vector of futexes waited on by [`io_uring_prep_futex_waitv`].
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct futex_waitv {
+    /// The value the futex is expected to hold.
+    pub val: u64,
+    /// The address of the futex.
+    pub uaddr: u64,
+    /// `FUTEX2_*` flags describing the futex.
+    pub flags: u32,
+    pub __reserved: u32,
+}
+
+/// Prepares a [`waitid`](https://man.archlinux.org/man/waitid.2) request
+///
+/// The submission queue entry is setup to wait for the child process identified by `idtype` and
+/// `id` to change state, according to `options`. Information about the child is written to
+/// `infop` upon completion. `flags` are io_uring specific modifier flags, of which none are
+/// currently defined.
+///
+/// Unlike [`waitid(2)`](https://man.archlinux.org/man/waitid.2), this does not block the thread,
+/// and `infop` must remain valid until the request completes.
+#[inline]
+pub fn io_uring_prep_waitid(
+    sqe: &mut io_uring_sqe,
+    idtype: idtype_t,
+    id: id_t,
+    infop: &mut siginfo_t,
+    options: i32,
+    flags: u32,
+) {
+    io_uring_prep_rw_null(IORING_OP_WAITID, sqe, id as i32, idtype, 0);
+    sqe.__bindgen_anon_3.rw_flags = flags as i32;
+    sqe.__bindgen_anon_5.file_index = options as u32;
+    sqe.__bindgen_anon_1.addr2 = infop as *mut siginfo_t as u64;
+}
+
+/// Prepares a futex wait request
+///
+/// The submission queue entry is setup to wait on the futex at `futex`, so long as it holds the
+/// value `val`. `mask` is the bitset used to match wakeups, `futex_flags` are `FUTEX2_*` flags
+/// describing the futex, and `flags` are io_uring specific modifier flags, of which none are
+/// currently defined.
+///
+/// If the futex does not hold `val` when the request is issued, it completes with `-EAGAIN`. The
+/// futex must remain valid until the request completes.
+///
+/// See [`futex(2)`](https://man.archlinux.org/man/futex.2)
+#[inline]
+pub fn io_uring_prep_futex_wait(
+    sqe: &mut io_uring_sqe,
+    futex: &AtomicU32,
+    val: u64,
+    mask: u64,
+    futex_flags: u32,
+    flags: u32,
+) {
+    io_uring_prep_rw(
+        IORING_OP_FUTEX_WAIT,
+        sqe,
+        futex_flags as i32,
+        Some(futex),
+        0,
+        val,
+    );
+    sqe.__bindgen_anon_3.rw_flags = flags as i32;
+    unsafe { sqe.__bindgen_anon_6.__bindgen_anon_1.as_mut().addr3 = mask };
+}
+
+/// Prepares a futex wake request
+///
+/// The submission queue entry is setup to wake up to `val` waiters on the futex at `futex`, whose
+/// bitset matches `mask`. `futex_flags` are `FUTEX2_*` flags describing the futex, and `flags`
+/// are io_uring specific modifier flags, of which none are currently defined.
+///
+/// The completion result is the number of waiters that were woken.
+///
+/// See [`futex(2)`](https://man.archlinux.org/man/futex.2)
+#[inline]
+pub fn io_uring_prep_futex_wake(
+    sqe: &mut io_uring_sqe,
+    futex: &AtomicU32,
+    val: u64,
+    mask: u64,
+    futex_flags: u32,
+    flags: u32,
+) {
+    io_uring_prep_rw(
+        IORING_OP_FUTEX_WAKE,
+        sqe,
+        futex_flags as i32,
+        Some(futex),
+        0,
+        val,
+    );
+    sqe.__bindgen_anon_3.rw_flags = flags as i32;
+    unsafe { sqe.__bindgen_anon_6.__bindgen_anon_1.as_mut().addr3 = mask };
+}
+
+/// Prepares a vectored futex wait request
+///
+/// The submission queue entry is setup to wait on any of the futexes described by `futexes`. The
+/// request completes once one of them is woken, with the result being the index of the woken
+/// futex. `flags` are io_uring specific modifier flags, of which none are currently defined.
+///
+/// `futexes` must remain valid until the request completes.
+///
+/// See [`futex_waitv(2)`](https://man.archlinux.org/man/futex_waitv.2)
+#[inline]
+pub fn io_uring_prep_futex_waitv(sqe: &mut io_uring_sqe, futexes: &[futex_waitv], flags: u32) {
+    io_uring_prep_rw_buf(IORING_OP_FUTEX_WAITV, sqe, 0, Some(futexes), 0);
+    sqe.__bindgen_anon_3.rw_flags = flags as i32;
+}
+
 /// # Safety
 /// `ring` must point to a valid and initialized `io_uring`
 #[inline]
@@ -1520,12 +1716,14 @@ pub unsafe fn io_uring_buf_ring_add(
     mask: i32,
     buf_offset: i32,
 ) {
-    let buf: *mut io_uring_buf = ptr::addr_of_mut!(
-        br.__bindgen_anon_1.bufs.as_mut()[((br.__bindgen_anon_1.__bindgen_anon_1.as_ref().tail
-            as i32
-            + buf_offset)
-            & mask) as usize]
-    );
+    // `bufs` is a flexible array member, so is indexed past its declared length of zero
+    let index = (br.__bindgen_anon_1.__bindgen_anon_1.as_ref().tail as i32 + buf_offset) & mask;
+    let buf: *mut io_uring_buf = br
+        .__bindgen_anon_1
+        .bufs
+        .as_mut()
+        .as_mut_ptr()
+        .add(index as usize);
 
     (*buf).addr = addr as u64;
     (*buf).len = len;
@@ -1567,6 +1765,58 @@ pub unsafe fn io_uring_buf_ring_cq_advance(
     io_uring_buf_ring_cq_advance_internal(cq, br, count, count)
 }
 
+// The following are not present in the vendored liburing 2.4, and are implemented in terms of
+// the raw `io_uring_register` syscall, mirroring later liburing releases.
+
+/// Register NAPI busy polling settings for the ring. Available since Linux 6.9.
+pub const IORING_REGISTER_NAPI: u32 = 27;
+/// Unregister NAPI busy polling settings for the ring. Available since Linux 6.9.
+pub const IORING_UNREGISTER_NAPI: u32 = 28;
+
+/// Argument to [`io_uring_register_napi`] and [`io_uring_unregister_napi`].
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct io_uring_napi {
+    /// Busy poll timeout, in microseconds.
+    pub busy_poll_to: u32,
+    /// Non-zero if busy polling should be preferred over interrupts.
+    pub prefer_busy_poll: u8,
+    pub pad: [u8; 3],
+    pub resv: u64,
+}
+
+/// Enables NAPI busy polling on the network devices backing sockets used with `ring`.
+///
+/// On success, `napi` is overwritten with the settings that were in effect before this call.
+///
+/// # Safety
+/// `ring` must point to a valid and initialized `io_uring`
+#[inline]
+pub unsafe fn io_uring_register_napi(ring: &mut io_uring, napi: &mut io_uring_napi) -> i32 {
+    io_uring_register(
+        ring.ring_fd as u32,
+        IORING_REGISTER_NAPI,
+        ptr::addr_of_mut!(*napi).cast(),
+        1,
+    )
+}
+
+/// Disables NAPI busy polling for `ring`.
+///
+/// On success, `napi` is overwritten with the settings that were in effect before this call.
+///
+/// # Safety
+/// `ring` must point to a valid and initialized `io_uring`
+#[inline]
+pub unsafe fn io_uring_unregister_napi(ring: &mut io_uring, napi: &mut io_uring_napi) -> i32 {
+    io_uring_register(
+        ring.ring_fd as u32,
+        IORING_UNREGISTER_NAPI,
+        ptr::addr_of_mut!(*napi).cast(),
+        1,
+    )
+}
+
 // #[inline]
 // unsafe fn io_uring_peek_cqe_internal(
 //     ring: &mut io_uring,
@@ -0,0 +1,176 @@
+use std::{fmt, io, mem, ptr, ptr::NonNull, slice};
+
+use rask_liburing_sys::{
+    io_uring_buf, io_uring_buf_ring, io_uring_buf_ring_add, io_uring_buf_ring_advance,
+    io_uring_buf_ring_init, io_uring_buf_ring_mask,
+};
+
+/// A ring of equally sized buffers provided to the kernel.
+///
+/// Requests selecting a buffer from the ring's group, such as
+/// [`SubmissionEntry::prep_recvmsg_multishot`](crate::SubmissionEntry::prep_recvmsg_multishot),
+/// pick one once data arrives, rather than reserving a buffer while they wait. The Id of the
+/// chosen buffer is reported by [`CqeFlags::buffer_id`](crate::CqeFlags::buffer_id). Once its
+/// data has been consumed, the buffer is handed back to the kernel with [`BufRing::recycle`].
+///
+/// If the ring runs out of buffers, requests complete with `-ENOBUFS`.
+///
+/// See [io_uring_register_buf_ring(3)](https://man.archlinux.org/man/io_uring_register_buf_ring.3)
+pub struct BufRing {
+    ring: NonNull<io_uring_buf_ring>,
+    buffers: NonNull<u8>,
+    entries: u16,
+    buf_len: u32,
+    group: u16,
+}
+
+impl BufRing {
+    /// Allocates a ring of `entries` buffers of `buf_len` bytes for the buffer group `group`, with
+    /// every buffer provided to the kernel. `entries` must be a power of two, up to 32768.
+    ///
+    /// The ring is used once registered with [`IoUring::register_buf_ring`](crate::IoUring::register_buf_ring).
+    pub fn new(entries: u16, buf_len: u32, group: u16) -> io::Result<Self> {
+        if !entries.is_power_of_two() || entries > 1 << 15 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entries must be a power of two, up to 32768",
+            ));
+        }
+
+        let ring = map(Self::ring_len(entries))?.cast::<io_uring_buf_ring>();
+        let buffers = match map(entries as usize * buf_len as usize) {
+            Ok(buffers) => buffers,
+            Err(e) => {
+                unsafe { libc::munmap(ring.as_ptr().cast(), Self::ring_len(entries)) };
+                return Err(e);
+            }
+        };
+
+        let buf_ring = Self {
+            ring,
+            buffers,
+            entries,
+            buf_len,
+            group,
+        };
+
+        // SAFETY: the ring was just mapped, and is only accessed through `buf_ring`
+        unsafe {
+            io_uring_buf_ring_init(ring.as_ptr());
+            let mask = io_uring_buf_ring_mask(entries as u32);
+            for id in 0..entries {
+                io_uring_buf_ring_add(
+                    &mut *ring.as_ptr(),
+                    buf_ring.buffer_ptr(id).cast(),
+                    buf_len,
+                    id,
+                    mask,
+                    id as i32,
+                );
+            }
+            io_uring_buf_ring_advance(&mut *ring.as_ptr(), entries as i32);
+        }
+
+        Ok(buf_ring)
+    }
+
+    fn ring_len(entries: u16) -> usize {
+        entries as usize * mem::size_of::<io_uring_buf>()
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut io_uring_buf_ring {
+        self.ring.as_ptr()
+    }
+
+    /// The number of buffers in the ring.
+    pub fn entries(&self) -> u16 {
+        self.entries
+    }
+
+    /// The buffer group Id requests select buffers from the ring with.
+    pub fn group(&self) -> u16 {
+        self.group
+    }
+
+    /// The length of each buffer.
+    pub fn buf_len(&self) -> u32 {
+        self.buf_len
+    }
+
+    fn buffer_ptr(&self, id: u16) -> *mut u8 {
+        assert!(id < self.entries, "buffer Id out of range");
+        // SAFETY: the buffers are contiguous, and `id` is in range
+        unsafe {
+            self.buffers
+                .as_ptr()
+                .add(id as usize * self.buf_len as usize)
+        }
+    }
+
+    /// Gets the first `len` bytes of the buffer `id`, as filled by the request it was selected
+    /// for.
+    ///
+    /// # Safety
+    /// The buffer must have been selected by a completed request, and not yet recycled, as the
+    /// kernel may otherwise be writing to it.
+    pub unsafe fn buffer(&self, id: u16, len: usize) -> &[u8] {
+        slice::from_raw_parts(self.buffer_ptr(id), len.min(self.buf_len as usize))
+    }
+
+    /// Hands the buffer `id` back to the kernel, to be selected by a later request.
+    ///
+    /// The buffer must not be accessed again until it is selected by another request, nor recycled
+    /// twice.
+    pub fn recycle(&self, id: u16) {
+        let addr = self.buffer_ptr(id);
+        // SAFETY: the ring is only accessed through `self`, and buffers are only added to it here
+        unsafe {
+            let ring = &mut *self.ring.as_ptr();
+            let mask = io_uring_buf_ring_mask(self.entries as u32);
+            io_uring_buf_ring_add(ring, addr.cast(), self.buf_len, id, mask, 0);
+            io_uring_buf_ring_advance(ring, 1);
+        }
+    }
+}
+
+impl fmt::Debug for BufRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufRing")
+            .field("entries", &self.entries)
+            .field("buf_len", &self.buf_len)
+            .field("group", &self.group)
+            .finish()
+    }
+}
+
+impl Drop for BufRing {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ring.as_ptr().cast(), Self::ring_len(self.entries));
+            libc::munmap(
+                self.buffers.as_ptr().cast(),
+                self.entries as usize * self.buf_len as usize,
+            );
+        }
+    }
+}
+
+/// Maps `len` bytes of zeroed, page aligned memory.
+fn map(len: usize) -> io::Result<NonNull<u8>> {
+    let addr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+
+    if addr == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(NonNull::new(addr.cast()).unwrap())
+}
@@ -0,0 +1,105 @@
+use std::{io, mem::MaybeUninit, time::Duration};
+
+use rask_liburing_sys::{
+    io_uring, io_uring_params, io_uring_queue_init_params, IORING_SETUP_CQSIZE,
+    IORING_SETUP_R_DISABLED, IORING_SETUP_SQPOLL, IORING_SETUP_SQ_AFF,
+};
+
+use crate::{IoUring, Restrictions};
+
+/// Configures the parameters an [`IoUring`] is created with.
+///
+/// See [io_uring_setup(2)](https://man.archlinux.org/man/io_uring_setup.2)
+#[derive(Debug, Clone, Default)]
+pub struct IoUringBuilder {
+    flags: u32,
+    cq_entries: u32,
+    sq_thread_cpu: Option<u32>,
+    sq_thread_idle: u32,
+    restrictions: Option<Restrictions>,
+}
+
+impl IoUringBuilder {
+    /// Creates a builder for a ring with default parameters.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a kernel thread to poll the SQ ring, so that submitting requests does not require a
+    /// syscall. After `idle` has passed without any new submissions, the thread goes to sleep and
+    /// must be woken, which the [`Submitter`](crate::Submitter) does as needed.
+    ///
+    /// Before Linux 5.11, this requires the `CAP_SYS_ADMIN` capability.
+    pub fn sqpoll(&mut self, idle: Duration) -> &mut Self {
+        self.flags |= IORING_SETUP_SQPOLL;
+        self.sq_thread_idle = u32::try_from(idle.as_millis()).unwrap_or(u32::MAX);
+        self
+    }
+
+    /// Pin the SQ poll thread to the given CPU. Has no effect unless [`IoUringBuilder::sqpoll`] is
+    /// also set.
+    pub fn sqpoll_cpu(&mut self, cpu: u32) -> &mut Self {
+        self.sq_thread_cpu = Some(cpu);
+        self
+    }
+
+    /// Size the CQ ring with `entries` entries, rather than the default of twice the SQ size.
+    pub fn cq_entries(&mut self, entries: u32) -> &mut Self {
+        self.flags |= IORING_SETUP_CQSIZE;
+        self.cq_entries = entries;
+        self
+    }
+
+    /// Create the ring in a disabled state. No SQEs are processed until the ring is enabled with
+    /// [`IoUring::enable`], giving the opportunity to register [`Restrictions`] first.
+    pub fn disabled(&mut self) -> &mut Self {
+        self.flags |= IORING_SETUP_R_DISABLED;
+        self
+    }
+
+    /// Restrict the ring to the given allow-list of operations. The ring is created disabled,
+    /// the restrictions registered, and then the ring is enabled.
+    pub fn restrictions(&mut self, restrictions: Restrictions) -> &mut Self {
+        self.restrictions = Some(restrictions);
+        self
+    }
+
+    /// Initializes a new io_uring instance with `entries` SQ entries, using the configured
+    /// parameters.
+    pub fn build(&self, entries: u32) -> io::Result<IoUring> {
+        let mut params: io_uring_params = unsafe { MaybeUninit::zeroed().assume_init() };
+        params.flags = self.flags;
+        if self.restrictions.is_some() {
+            params.flags |= IORING_SETUP_R_DISABLED;
+        }
+        params.cq_entries = self.cq_entries;
+        // The kernel rejects an affinity without a poll thread to apply it to
+        if let Some(cpu) = self
+            .sq_thread_cpu
+            .filter(|_| self.flags & IORING_SETUP_SQPOLL > 0)
+        {
+            params.flags |= IORING_SETUP_SQ_AFF;
+            params.sq_thread_cpu = cpu;
+        }
+        params.sq_thread_idle = self.sq_thread_idle;
+
+        let mut ring: MaybeUninit<io_uring> = MaybeUninit::zeroed();
+        let res = unsafe { io_uring_queue_init_params(entries, ring.as_mut_ptr(), &mut params) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        let mut ring = IoUring::from_raw(unsafe { ring.assume_init() });
+
+        if let Some(restrictions) = &self.restrictions {
+            ring.register_restrictions(restrictions)?;
+
+            if self.flags & IORING_SETUP_R_DISABLED == 0 {
+                ring.enable()?;
+            }
+        }
+
+        Ok(ring)
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    io,
+    os::fd::RawFd,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use rask_liburing_sys::{
+    io_uring_cq, io_uring_enter, IORING_ENTER_GETEVENTS, IORING_SQ_CQ_OVERFLOW, IORING_SQ_TASKRUN,
+};
+
+use crate::CompletionQueue;
+
+/// The completion half of an [`IoUring`](crate::IoUring).
+///
+/// A `Completer` owns the CQ ring: it waits for and reaps completions posted by the kernel. It may
+/// be moved to another thread, independently of the [`Submitter`](crate::Submitter) it was split
+/// from.
+///
+/// The CQ tail is only ever written by the kernel and is read with `Acquire` ordering, while the CQ
+/// head is only ever written by the `Completer` and is published with `Release` ordering once the
+/// entries before it have been copied out.
+#[derive(Debug)]
+pub struct Completer<'a> {
+    cq: &'a mut io_uring_cq,
+    sq_kflags: *const AtomicU32,
+    ring_fd: RawFd,
+    flags: u32,
+}
+
+// SAFETY: The CQ head is only ever written by a single `Completer`, which requires `&mut self` for
+// every operation. `sq_kflags` is only read, atomically.
+unsafe impl Send for Completer<'_> {}
+
+impl<'a> Completer<'a> {
+    pub(crate) fn new(
+        cq: &'a mut io_uring_cq,
+        sq_kflags: *const AtomicU32,
+        ring_fd: RawFd,
+        flags: u32,
+    ) -> Self {
+        Self {
+            cq,
+            sq_kflags,
+            ring_fd,
+            flags,
+        }
+    }
+
+    fn sq_kflags(&self) -> u32 {
+        // SAFETY: kflags points into the SQ ring mapped by the kernel, which outlives `self`
+        unsafe { (*self.sq_kflags).load(Ordering::Relaxed) }
+    }
+
+    /// Gets the number of unconsumed, ready entries
+    pub fn ready(&self) -> u32 {
+        // SAFETY: khead and ktail point into the CQ ring mapped by the kernel, which outlives `self`
+        let (head, tail) = unsafe {
+            let khead: &AtomicU32 = &*self.cq.khead.cast();
+            let ktail: &AtomicU32 = &*self.cq.ktail.cast();
+            (khead.load(Ordering::Relaxed), ktail.load(Ordering::Acquire))
+        };
+
+        tail.wrapping_sub(head)
+    }
+
+    /// Determines if completions have been held back by the kernel due to a full CQ. Entering the
+    /// kernel via [`Completer::wait`] will flush them once there is room.
+    pub fn has_overflown(&self) -> bool {
+        self.sq_kflags() & IORING_SQ_CQ_OVERFLOW > 0
+    }
+
+    fn needs_flush(&self) -> bool {
+        self.sq_kflags() & (IORING_SQ_CQ_OVERFLOW | IORING_SQ_TASKRUN) > 0
+    }
+
+    /// Get an iterator over CQEs
+    pub fn completions(&mut self) -> CompletionQueue<'_> {
+        CompletionQueue::new(self.cq, self.flags)
+    }
+
+    /// Waits for at least `wait_for` CQEs to be ready, entering the kernel only if they are not
+    /// already available.
+    ///
+    /// See [io_uring_enter(2)](https://man.archlinux.org/man/io_uring_enter.2)
+    pub fn wait(&mut self, wait_for: u32) -> io::Result<()> {
+        if self.ready() >= wait_for && !self.needs_flush() {
+            return Ok(());
+        }
+
+        let res = unsafe {
+            io_uring_enter(
+                self.ring_fd as u32,
+                0,
+                wait_for,
+                IORING_ENTER_GETEVENTS,
+                ptr::null_mut(),
+            )
+        };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(())
+    }
+}
@@ -1,11 +1,12 @@
 use bitflags::bitflags;
 use rask_liburing_sys::{
-    io_uring_cqe, io_uring_cqe_get_data64, IORING_CQE_F_BUFFER, IORING_CQE_F_MORE,
-    IORING_CQE_F_NOTIF, IORING_CQE_F_SOCK_NONEMPTY,
+    io_uring_cqe, io_uring_cqe_get_data64, IORING_CQE_BUFFER_SHIFT, IORING_CQE_F_BUFFER,
+    IORING_CQE_F_MORE, IORING_CQE_F_NOTIF, IORING_CQE_F_SOCK_NONEMPTY,
 };
 
 bitflags! {
     /// Carries request-specific information
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct CqeFlags: u32 {
         /// If set, the upper 16 bits of the flags field carries the buffer Id that was chosen for
         /// this request.
@@ -18,32 +19,55 @@ bitflags! {
         /// Set for notification CQEs, as seen with the zero-copy networking send and receive
         /// support.
         const Notification = IORING_CQE_F_NOTIF;
+
+        const _ = !0;
+    }
+}
+
+impl CqeFlags {
+    /// Get the Id of the provided buffer chosen for this request, if one was selected.
+    pub fn buffer_id(&self) -> Option<u16> {
+        if self.contains(CqeFlags::Buffer) {
+            Some((self.bits() >> IORING_CQE_BUFFER_SHIFT) as u16)
+        } else {
+            None
+        }
     }
 }
 
-/// TODO
-pub struct CompletionEntry<'a> {
-    inner: &'a io_uring_cqe,
+/// A completion queue entry, copied out of the CQ ring.
+///
+/// Holding onto a `CompletionEntry` does not prevent the kernel from reusing the slot it was read
+/// from.
+#[derive(Clone, Copy, Debug)]
+pub struct CompletionEntry {
+    user_data: u64,
+    res: i32,
+    flags: u32,
 }
 
-impl<'a> CompletionEntry<'a> {
-    pub(crate) fn new(cqe: &'a io_uring_cqe) -> Self {
-        Self { inner: cqe }
+impl CompletionEntry {
+    pub(crate) fn new(cqe: &io_uring_cqe) -> Self {
+        Self {
+            user_data: unsafe { io_uring_cqe_get_data64(cqe) },
+            res: cqe.res,
+            flags: cqe.flags,
+        }
     }
 
     /// Retrieve the user_data set on the corresponding SQE request.
     pub fn get_user_data(&self) -> u64 {
-        unsafe { io_uring_cqe_get_data64(self.inner) }
+        self.user_data
     }
 
     /// Get flags set on the CQE, which carry request-specific information.
     pub fn flags(&self) -> CqeFlags {
-        CqeFlags::from_bits(self.inner.flags).expect("Supported flags are defined")
+        CqeFlags::from_bits_retain(self.flags)
     }
 
     /// Get the result of the operation. This is equivalent to the return value of the syscall
     /// represented by the SQE request.
     pub fn result(&self) -> i32 {
-        self.inner.res
+        self.res
     }
 }
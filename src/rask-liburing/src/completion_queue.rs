@@ -1,61 +1,72 @@
-use std::{
-    ptr,
-    sync::atomic::{AtomicPtr, Ordering},
-};
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use rask_liburing_sys::io_uring_cq;
+use rask_liburing_sys::{io_uring_cq, IORING_SETUP_CQE32};
 
 use crate::CompletionEntry;
 
 /// An iterator over CQEs, automatically advancing the ring buffer when iteration completes
+///
+/// The CQ tail is loaded with `Acquire` ordering when the iterator is created, so every CQE up to
+/// that point is fully written by the kernel. Entries are copied out of the ring as they are
+/// yielded, and the CQ head is published with `Release` ordering on drop, handing the consumed
+/// slots back to the kernel.
 pub struct CompletionQueue<'a> {
-    inner: &'a io_uring_cq,
-    count: usize,
+    inner: &'a mut io_uring_cq,
     head: u32,
+    tail: u32,
+    shift: u32,
 }
 
 impl<'a> CompletionQueue<'a> {
-    pub(crate) fn new(cq: &'a io_uring_cq) -> Self {
-        let head = AtomicPtr::from(cq.khead);
+    pub(crate) fn new(cq: &'a mut io_uring_cq, flags: u32) -> Self {
+        // SAFETY: khead and ktail point into the CQ ring mapped by the kernel, which outlives `cq`
+        let (head, tail) = unsafe {
+            let khead: &AtomicU32 = &*cq.khead.cast();
+            let ktail: &AtomicU32 = &*cq.ktail.cast();
+            (khead.load(Ordering::Relaxed), ktail.load(Ordering::Acquire))
+        };
+
         Self {
             inner: cq,
-            count: 0,
-            head: unsafe { head.load(Ordering::Acquire).read() },
+            head,
+            tail,
+            shift: if flags & IORING_SETUP_CQE32 > 0 { 1 } else { 0 },
         }
     }
 }
 
-impl<'a> Iterator for CompletionQueue<'a> {
-    type Item = CompletionEntry<'a>;
+impl Iterator for CompletionQueue<'_> {
+    type Item = CompletionEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let tail = AtomicPtr::from(self.inner.ktail);
-        if self.head == unsafe { tail.load(Ordering::Acquire).read() } {
-            None
-        } else {
-            let index = (self.head & self.inner.ring_mask) << 1;
-            Some(CompletionEntry::new(unsafe {
-                self.inner.cqes.offset(index as isize).as_ref().unwrap()
-            }))
+        if self.head == self.tail {
+            return None;
         }
 
-        // let item = ptr::null_mut();
-        // match unsafe { io_uring_peek_cqe(self.inner, item) } {
-        //     0 => None,
-        //     _ => {
-        //         self.count += 1;
-        //         unsafe { item.read().as_ref().map(CompletionEntry::new) }
-        //     }
-        // }
+        let index = (self.head & self.inner.ring_mask) << self.shift;
+        self.head = self.head.wrapping_add(1);
+
+        // SAFETY: index is masked to the size of the CQE array, and the entry was published by the
+        // kernel before the tail observed in `new`.
+        Some(CompletionEntry::new(unsafe {
+            &*self.inner.cqes.add(index as usize)
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.tail.wrapping_sub(self.head) as usize;
+        (remaining, Some(remaining))
     }
 }
 
+impl ExactSizeIterator for CompletionQueue<'_> {}
+
 impl Drop for CompletionQueue<'_> {
     fn drop(&mut self) {
-        if self.count > 0 {
-            let khead = AtomicPtr::from(self.inner.khead);
-            let mut value = unsafe { *self.inner.khead + self.count as u32 };
-            khead.store(ptr::addr_of_mut!(value), Ordering::Release);
+        // SAFETY: khead points into the CQ ring mapped by the kernel, which outlives `self`
+        let khead: &AtomicU32 = unsafe { &*self.inner.khead.cast() };
+        if khead.load(Ordering::Relaxed) != self.head {
+            khead.store(self.head, Ordering::Release);
         }
     }
 }
@@ -1,7 +1,8 @@
-use libc::{iovec, mode_t, msghdr, sockaddr, socklen_t, timespec};
+use libc::{id_t, idtype_t, iovec, mode_t, msghdr, siginfo_t, sockaddr, socklen_t, timespec};
 use std::{
     ffi::OsString,
     ptr::{self, NonNull},
+    sync::atomic::AtomicU32,
 };
 
 use super::{
@@ -66,7 +67,12 @@ const IORING_OP_SOCKET: u8 = 45;
 const IORING_OP_URING_CMD: u8 = 46;
 const IORING_OP_SEND_ZC: u8 = 47;
 const IORING_OP_SENDMSG_ZC: u8 = 48;
-const IORING_OP_LAST: u8 = 49;
+const IORING_OP_READ_MULTISHOT: u8 = 49;
+const IORING_OP_WAITID: u8 = 50;
+const IORING_OP_FUTEX_WAIT: u8 = 51;
+const IORING_OP_FUTEX_WAKE: u8 = 52;
+const IORING_OP_FUTEX_WAITV: u8 = 53;
+const IORING_OP_LAST: u8 = 54;
 const IORING_MSG_DATA: u8 = 0;
 const IORING_MSG_SEND_FD: u8 = 1;
 
@@ -111,6 +117,8 @@ union Flags {
     xattr_flags: u32,
     msg_ring_flags: u32,
     uring_cmd_flags: u32,
+    waitid_flags: u32,
+    futex_flags: u32,
 }
 
 #[repr(C, packed)]
@@ -145,6 +153,15 @@ union Union6 {
     cmd: [u8; 0],
 }
 
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FutexWaitV {
+    val: u64,
+    uaddr: u64,
+    flags: u32,
+    reserved: u32,
+}
+
 #[repr(C)]
 pub struct IoUringSqe {
     opcode: u8,
@@ -1253,6 +1270,100 @@ impl IoUringSqe {
         self.prep_msg_ring_fd(fd, source_fd, IORING_FILE_INDEX_ALLOC, data, flags);
     }
 
+    /// Prepares a [`waitid`](https://man.archlinux.org/man/waitid.2) request
+    ///
+    /// The submission queue entry is setup to wait for the child process identified by `idtype` and
+    /// `id` to change state, according to `options`. Information about the child is written to
+    /// `infop` upon completion. `flags` are io_uring specific modifier flags, of which none are
+    /// currently defined.
+    ///
+    /// Unlike [`waitid(2)`](https://man.archlinux.org/man/waitid.2), this does not block the thread,
+    /// and `infop` must remain valid until the request completes. Available since kernel 6.7.
+    #[inline]
+    pub fn prep_waitid(
+        &mut self,
+        idtype: idtype_t,
+        id: id_t,
+        infop: &mut siginfo_t,
+        options: i32,
+        flags: u32,
+    ) {
+        self.prep_rw_null(IORING_OP_WAITID, id as i32, idtype, 0);
+        self.op_flags.waitid_flags = flags;
+        self.union5.file_index = options as u32;
+        self.union1.addr2 = infop as *mut siginfo_t as u64;
+    }
+
+    /// Prepares a futex wait request
+    ///
+    /// The submission queue entry is setup to wait on the futex at `futex`, so long as it holds the
+    /// value `val`. `mask` is the bitset used to match wakeups, `futex_flags` are `FUTEX2_*` flags
+    /// describing the futex, and `flags` are io_uring specific modifier flags, of which none are
+    /// currently defined.
+    ///
+    /// If the futex does not hold `val` when the request is issued, it completes with `-EAGAIN`.
+    /// The futex must remain valid until the request completes. Available since kernel 6.7.
+    #[inline]
+    pub fn prep_futex_wait(
+        &mut self,
+        futex: &AtomicU32,
+        val: u64,
+        mask: u64,
+        futex_flags: u32,
+        flags: u32,
+    ) {
+        self.prep_rw(
+            IORING_OP_FUTEX_WAIT,
+            futex_flags as i32,
+            Some(futex),
+            0,
+            val,
+        );
+        self.op_flags.futex_flags = flags;
+        self.union6.addr.addr3 = mask;
+    }
+
+    /// Prepares a futex wake request
+    ///
+    /// The submission queue entry is setup to wake up to `val` waiters on the futex at `futex`,
+    /// whose bitset matches `mask`. `futex_flags` are `FUTEX2_*` flags describing the futex, and
+    /// `flags` are io_uring specific modifier flags, of which none are currently defined.
+    ///
+    /// The completion result is the number of waiters that were woken. Available since kernel 6.7.
+    #[inline]
+    pub fn prep_futex_wake(
+        &mut self,
+        futex: &AtomicU32,
+        val: u64,
+        mask: u64,
+        futex_flags: u32,
+        flags: u32,
+    ) {
+        self.prep_rw(
+            IORING_OP_FUTEX_WAKE,
+            futex_flags as i32,
+            Some(futex),
+            0,
+            val,
+        );
+        self.op_flags.futex_flags = flags;
+        self.union6.addr.addr3 = mask;
+    }
+
+    /// Prepares a vectored futex wait request
+    ///
+    /// The submission queue entry is setup to wait on any of the futexes described by `futexes`.
+    /// The request completes once one of them is woken, with the result being the index of the
+    /// woken futex. `flags` are io_uring specific modifier flags, of which none are currently
+    /// defined.
+    ///
+    /// `futexes` must remain valid until the request completes. Available since kernel 6.7.
+    #[inline]
+    pub fn prep_futex_waitv(&mut self, futexes: &[FutexWaitV], flags: u32) {
+        self.prep_rw_buf(IORING_OP_FUTEX_WAITV, 0, Some(futexes), 0);
+        self.op_flags.futex_flags = flags;
+    }
+
     // TODO: xattr
 
     #[inline]
@@ -14,9 +14,6 @@
 
 mod core;
 
-mod squeue;
-pub use squeue::*;
-
 mod completion_entry;
 pub use completion_entry::*;
 
@@ -26,13 +23,45 @@ pub use submission_entry::*;
 mod completion_queue;
 pub use completion_queue::*;
 
-use std::{io, mem::MaybeUninit, ptr};
+mod submitter;
+pub use submitter::*;
+
+mod completer;
+pub use completer::*;
+
+mod builder;
+pub use builder::*;
+
+mod restrictions;
+pub use restrictions::*;
+
+mod napi;
+pub use napi::*;
+
+mod probe;
+pub use probe::*;
+
+mod buf_ring;
+pub use buf_ring::*;
+
+mod recvmsg;
+pub use recvmsg::*;
+
+use std::{
+    io, mem,
+    ptr::{self, NonNull},
+};
 
 use rask_liburing_sys::{
-    io_uring, io_uring_enter, io_uring_get_sqe, io_uring_queue_exit, io_uring_queue_init,
-    io_uring_sq,
+    io_uring, io_uring_buf_reg, io_uring_enable_rings, io_uring_get_sqe, io_uring_probe,
+    io_uring_probe_op, io_uring_queue_exit, io_uring_register_buf_ring,
+    io_uring_register_files_sparse, io_uring_register_napi, io_uring_register_probe,
+    io_uring_register_restrictions, io_uring_unregister_buf_ring, io_uring_unregister_napi,
 };
 
+/// Opcodes are a `u8`, so a probe describes at most 256 operations.
+const PROBE_OPS: usize = 256;
+
 /// io_uring is a Linux-specific API for asynchronous I/O. It allows the user to submit one or more I/O requests,
 /// which are processed asynchronously without blocking the calling process. io_uring gets its name from ring
 /// buffers which are shared between user space and kernel space. This arrangement allows for efficient I/O, while
@@ -42,39 +71,190 @@ use rask_liburing_sys::{
 #[derive(Debug)]
 pub struct IoUring {
     inner: io_uring,
-    unsubmitted: u32,
-    in_flight: u32,
+    stats: SubmitStats,
+    napi: Option<NapiSettings>,
 }
 
+// SAFETY: The ring is only accessed through `&mut self`, or through the `Submitter` and `Completer`
+// halves which borrow it. Moving the owner between threads does not affect the kernel's view of it.
+unsafe impl Send for IoUring {}
+
 impl IoUring {
     /// Initializes a new io_uring instance, including the SQ and CQ ring buffers.
     pub fn new(entries: u32) -> io::Result<Self> {
-        let mut ring: MaybeUninit<io_uring> = MaybeUninit::zeroed();
-        let res = unsafe { io_uring_queue_init(entries, ring.as_mut_ptr(), 0) };
-        let ring = unsafe { ring.assume_init() };
+        IoUringBuilder::new().build(entries)
+    }
+
+    /// Configure the parameters of a new io_uring instance.
+    pub fn builder() -> IoUringBuilder {
+        IoUringBuilder::new()
+    }
+
+    pub(crate) fn from_raw(ring: io_uring) -> Self {
+        Self {
+            inner: ring,
+            stats: SubmitStats::default(),
+            napi: None,
+        }
+    }
+
+    /// Get the counters describing how submissions have reached the kernel.
+    pub fn submit_stats(&self) -> SubmitStats {
+        self.stats
+    }
+
+    /// Register an allow-list of operations on a ring created with [`IoUringBuilder::disabled`].
+    /// Restrictions can only be registered once, before the ring is enabled.
+    ///
+    /// See [io_uring_register(2)](https://man.archlinux.org/man/io_uring_register.2)
+    pub fn register_restrictions(&mut self, restrictions: &Restrictions) -> io::Result<()> {
+        let mut raw = restrictions.to_raw();
+        let res = unsafe {
+            io_uring_register_restrictions(&mut self.inner, raw.as_mut_ptr(), raw.len() as u32)
+        };
 
         if res < 0 {
             return Err(io::Error::from_raw_os_error(-res));
         }
 
-        Ok(Self {
-            inner: ring,
-            unsubmitted: 0,
-            in_flight: 0,
-        })
+        Ok(())
+    }
+
+    /// Enable a ring created with [`IoUringBuilder::disabled`], after which SQEs are processed.
+    ///
+    /// See [io_uring_register(2)](https://man.archlinux.org/man/io_uring_register.2)
+    pub fn enable(&mut self) -> io::Result<()> {
+        let res = unsafe { io_uring_enable_rings(&mut self.inner) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(())
+    }
+
+    /// Enable NAPI busy polling for sockets used with this ring, replacing any settings previously
+    /// registered.
+    ///
+    /// Returns the settings which were in effect before this call, as reported by the kernel.
+    pub fn register_napi(&mut self, settings: NapiSettings) -> io::Result<NapiSettings> {
+        let mut raw = settings.to_raw();
+        let res = unsafe { io_uring_register_napi(&mut self.inner, &mut raw) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        self.napi = Some(settings);
+        Ok(NapiSettings::from_raw(raw))
+    }
+
+    /// Disable NAPI busy polling for this ring.
+    ///
+    /// Returns the settings which were in effect before this call, as reported by the kernel.
+    pub fn unregister_napi(&mut self) -> io::Result<NapiSettings> {
+        let mut raw = Default::default();
+        let res = unsafe { io_uring_unregister_napi(&mut self.inner, &mut raw) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        self.napi = None;
+        Ok(NapiSettings::from_raw(raw))
+    }
+
+    /// Get the NAPI busy polling settings last registered through this handle, if any.
+    ///
+    /// The kernel has no way to read them back without replacing them, so they are remembered
+    /// here, and do not reflect settings registered through another handle to the same ring.
+    pub fn registered_napi(&self) -> Option<NapiSettings> {
+        self.napi
+    }
+
+    /// Register a sparse table of `nr` files with the ring, whose slots are filled by direct
+    /// descriptors, i.e. by [`SubmissionEntry::prep_accept_direct`].
+    ///
+    /// See [io_uring_register_files(3)](https://man.archlinux.org/man/io_uring_register_files.3)
+    pub fn register_files_sparse(&mut self, nr: u32) -> io::Result<()> {
+        let res = unsafe { io_uring_register_files_sparse(&mut self.inner, nr) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(())
+    }
+
+    /// Register `buf_ring` with the ring, providing its buffers to requests selecting from its
+    /// group.
+    ///
+    /// Requires Linux 5.19 or later.
+    ///
+    /// # Safety
+    /// `buf_ring` must outlive its registration, which lasts until it is unregistered with
+    /// [`IoUring::unregister_buf_ring`], or the ring is dropped.
+    ///
+    /// See [io_uring_register_buf_ring(3)](https://man.archlinux.org/man/io_uring_register_buf_ring.3)
+    pub unsafe fn register_buf_ring(&mut self, buf_ring: &BufRing) -> io::Result<()> {
+        let mut reg = io_uring_buf_reg {
+            ring_addr: buf_ring.as_ptr() as u64,
+            ring_entries: buf_ring.entries() as u32,
+            bgid: buf_ring.group(),
+            flags: 0,
+            resv: [0; 3],
+        };
+        let res = io_uring_register_buf_ring(&mut self.inner, &mut reg, 0);
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(())
+    }
+
+    /// Unregister the buffer ring of the buffer group `group`.
+    pub fn unregister_buf_ring(&mut self, group: u16) -> io::Result<()> {
+        let res = unsafe { io_uring_unregister_buf_ring(&mut self.inner, group as i32) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(())
+    }
+
+    /// Probe the kernel for the operations this ring supports.
+    ///
+    /// See [io_uring_register_probe(3)](https://man.archlinux.org/man/io_uring_register_probe.3)
+    pub fn probe(&mut self) -> io::Result<Probe> {
+        let len =
+            mem::size_of::<io_uring_probe>() + PROBE_OPS * mem::size_of::<io_uring_probe_op>();
+        // SAFETY: the probe is zeroed as required by the kernel, and freed by `Probe` with
+        // `io_uring_free_probe`, which calls `free`.
+        let probe = unsafe { libc::calloc(1, len) }.cast::<io_uring_probe>();
+        let probe = Probe::from_raw(NonNull::new(probe).ok_or_else(io::Error::last_os_error)?);
+
+        let res =
+            unsafe { io_uring_register_probe(&mut self.inner, probe.as_ptr(), PROBE_OPS as u32) };
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(probe)
     }
 
     /// Get the next available submission queue entry from the submission queue belonging to this ring.
     pub fn get_sqe(&mut self) -> Result<SubmissionEntry<'_>, SQFullError> {
         let entry = io_uring_get_sqe(&mut self.inner).ok_or(SQFullError)?;
-        self.unsubmitted += 1;
 
         Ok(SubmissionEntry::new(entry))
     }
 
     /// Get an iterator over CQEs
     pub fn get_cqes(&mut self) -> CompletionQueue<'_> {
-        CompletionQueue::new(&self.inner.cq)
+        CompletionQueue::new(&mut self.inner.cq, self.inner.flags)
     }
 
     /// Informs the kernel of new SQEs, but waits for no CQEs before continuing.
@@ -92,25 +272,50 @@ impl IoUring {
     ///
     /// See [io_uring_enter(2)](https://man.archlinux.org/man/io_uring_enter.2)
     pub fn enter_and_wait(&mut self, wait_for: u32) -> io::Result<u32> {
-        let accepted = unsafe {
-            io_uring_enter(
-                self.inner.ring_fd as u32,
-                self.unsubmitted,
-                wait_for,
-                0,
-                ptr::null_mut(),
-            )
-        };
+        self.submitter().submit_and_wait(wait_for)
+    }
 
-        if accepted < 0 {
-            return Err(io::Error::last_os_error());
-        }
+    /// Borrow the submission half of the ring.
+    pub fn submitter(&mut self) -> Submitter<'_> {
+        self.split().0
+    }
 
-        let accepted = accepted as u32;
-        self.unsubmitted -= accepted;
-        self.in_flight += accepted;
+    /// Borrow the completion half of the ring.
+    pub fn completer(&mut self) -> Completer<'_> {
+        self.split().1
+    }
 
-        Ok(accepted)
+    /// Split the ring into its submission and completion halves, allowing one thread to submit
+    /// requests while another reaps their completions.
+    ///
+    /// ```no_run
+    /// # use rask_liburing::IoUring;
+    /// let mut ring = IoUring::new(64).unwrap();
+    /// let (mut submitter, mut completer) = ring.split();
+    ///
+    /// std::thread::scope(|s| {
+    ///     s.spawn(move || {
+    ///         submitter.get_sqe().unwrap().prep_nop().set_user_data(1);
+    ///         submitter.submit().unwrap();
+    ///     });
+    ///
+    ///     s.spawn(move || {
+    ///         completer.wait(1).unwrap();
+    ///         for cqe in completer.completions() {
+    ///             assert_eq!(cqe.get_user_data(), 1);
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    pub fn split(&mut self) -> (Submitter<'_>, Completer<'_>) {
+        let ring_fd = self.inner.ring_fd;
+        let flags = self.inner.flags;
+        let sq_kflags = self.inner.sq.kflags.cast_const().cast();
+
+        (
+            Submitter::new(&mut self.inner.sq, &mut self.stats, ring_fd, flags),
+            Completer::new(&mut self.inner.cq, sq_kflags, ring_fd, flags),
+        )
     }
 }
 
@@ -120,14 +325,233 @@ impl Drop for IoUring {
     }
 }
 
-/// TODO
-#[derive(Debug)]
-pub struct SubmissionQueue<'a> {
-    inner: &'a io_uring_sq,
-}
+#[cfg(test)]
+mod test {
+    use std::{
+        mem::MaybeUninit,
+        net::{TcpListener, TcpStream},
+        os::fd::{AsRawFd, FromRawFd},
+        process::Command,
+        sync::atomic::AtomicU32,
+        thread,
+        time::Duration,
+    };
+
+    use rask_liburing_sys::{IORING_OP_FUTEX_WAIT, IORING_OP_NOP, IORING_OP_WAITID};
+
+    use super::{FutexWait, IoUring, NapiSettings, Restrictions, SqeFlags};
+
+    #[test]
+    fn split_submits_and_completes_across_threads() {
+        const COUNT: u64 = 128;
+        let mut ring = IoUring::new(32).unwrap();
+        let (mut submitter, mut completer) = ring.split();
+
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..COUNT {
+                    loop {
+                        match submitter.get_sqe() {
+                            Ok(mut sqe) => {
+                                sqe.prep_nop().set_user_data(i);
+                                break;
+                            }
+                            Err(_) => {
+                                submitter.submit().unwrap();
+                            }
+                        }
+                    }
+                }
+                submitter.submit().unwrap();
+            });
+
+            let reaper = s.spawn(move || {
+                let mut seen = Vec::new();
+                while (seen.len() as u64) < COUNT {
+                    completer.wait(1).unwrap();
+                    seen.extend(completer.completions().map(|cqe| {
+                        assert_eq!(cqe.result(), 0);
+                        cqe.get_user_data()
+                    }));
+                }
+                seen
+            });
+
+            let seen = reaper.join().unwrap();
+            assert_eq!(seen, (0..COUNT).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn sqpoll_wakes_sleeping_poller() {
+        let mut ring = IoUring::builder()
+            .sqpoll(Duration::from_millis(1))
+            .build(8)
+            .unwrap();
+
+        // Give the poller time to idle out and go to sleep.
+        thread::sleep(Duration::from_millis(50));
+
+        let mut submitter = ring.submitter();
+        assert!(submitter.needs_wakeup());
+
+        submitter.get_sqe().unwrap().prep_nop().set_user_data(7);
+        submitter.submit().unwrap();
+
+        let stats = submitter.stats();
+        assert_eq!(stats.submits, 1);
+        assert_eq!(stats.wakeups, 1);
+        assert_eq!(stats.enters, 1);
+
+        let mut completer = ring.completer();
+        completer.wait(1).unwrap();
+        let cqes: Vec<_> = completer.completions().collect();
+        assert_eq!(cqes.len(), 1);
+        assert_eq!(cqes[0].get_user_data(), 7);
+    }
+
+    #[test]
+    fn sqpoll_cpu_is_ignored_without_sqpoll() {
+        let mut ring = IoUring::builder().sqpoll_cpu(0).build(8).unwrap();
+
+        ring.get_sqe().unwrap().prep_nop().set_user_data(1);
+        ring.enter_and_wait(1).unwrap();
+        assert_eq!(ring.get_cqes().count(), 1);
+    }
+
+    #[test]
+    fn restrictions_reject_disallowed_requests() {
+        let mut restrictions = Restrictions::new();
+        restrictions.allow_sqe_op(IORING_OP_NOP);
+
+        let mut ring = IoUring::builder()
+            .restrictions(restrictions)
+            .build(8)
+            .unwrap();
+
+        ring.get_sqe().unwrap().prep_nop().set_user_data(1);
+        ring.get_sqe()
+            .unwrap()
+            .prep_nop()
+            .set_flags(SqeFlags::IoDrain)
+            .set_user_data(2);
+        ring.enter_and_wait(2).unwrap();
+
+        let results: Vec<_> = ring
+            .get_cqes()
+            .map(|cqe| (cqe.get_user_data(), cqe.result()))
+            .collect();
+        assert_eq!(results, vec![(1, 0), (2, -libc::EACCES)]);
+    }
+
+    #[test]
+    fn napi_settings_read_back() {
+        let mut ring = IoUring::new(8).unwrap();
+        let settings = NapiSettings::new(Duration::from_micros(50)).prefer_busy_poll(true);
+
+        match ring.register_napi(settings) {
+            // NAPI registration is only available since Linux 6.9
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => return,
+            res => assert_eq!(res.unwrap(), NapiSettings::default()),
+        }
+        assert_eq!(ring.registered_napi(), Some(settings));
+
+        let updated = NapiSettings::new(Duration::from_micros(100));
+        assert_eq!(ring.register_napi(updated).unwrap(), settings);
+        assert_eq!(ring.unregister_napi().unwrap(), updated);
+        assert_eq!(ring.registered_napi(), None);
+    }
+
+    #[test]
+    fn futex_wait_is_woken() {
+        let mut ring = IoUring::new(8).unwrap();
+        // Futex operations are only available since Linux 6.7
+        if !ring.probe().unwrap().is_supported(IORING_OP_FUTEX_WAIT) {
+            return;
+        }
+
+        let futex = AtomicU32::new(0);
+        ring.get_sqe()
+            .unwrap()
+            .prep_futex_wait(&futex, 1, None)
+            .set_user_data(1);
+        ring.enter_and_wait(1).unwrap();
+        let cqes: Vec<_> = ring.get_cqes().collect();
+        assert_eq!(cqes[0].result(), -libc::EAGAIN);
+
+        let futexes = [FutexWait::new(&futex, 0)];
+        ring.get_sqe()
+            .unwrap()
+            .prep_futex_waitv(&futexes)
+            .set_user_data(2);
+        ring.get_sqe()
+            .unwrap()
+            .prep_futex_wake(&futex, 1, None)
+            .set_user_data(3);
+        ring.enter_and_wait(2).unwrap();
+
+        let mut results: Vec<_> = ring
+            .get_cqes()
+            .map(|cqe| (cqe.get_user_data(), cqe.result()))
+            .collect();
+        results.sort();
+        assert_eq!(results, vec![(2, 0), (3, 1)]);
+    }
+
+    #[test]
+    fn waitid_reaps_child() {
+        let mut ring = IoUring::new(8).unwrap();
+        // waitid is only available since Linux 6.7
+        if !ring.probe().unwrap().is_supported(IORING_OP_WAITID) {
+            return;
+        }
+
+        // The child is reaped by the ring, rather than `Child::wait`
+        #[allow(clippy::zombie_processes)]
+        let child = Command::new("true").spawn().unwrap();
+        let mut info: libc::siginfo_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        ring.get_sqe()
+            .unwrap()
+            .prep_waitid(libc::P_PID, child.id(), &mut info, libc::WEXITED)
+            .set_user_data(1);
+        ring.enter_and_wait(1).unwrap();
+
+        let cqes: Vec<_> = ring.get_cqes().collect();
+        assert_eq!(cqes[0].result(), 0);
+        assert_eq!(unsafe { info.si_pid() }, child.id() as i32);
+        assert_eq!(unsafe { info.si_status() }, 0);
+    }
+
+    #[test]
+    fn accept_after_waitid_in_the_same_slot() {
+        // With a single SQE, every request is prepared in the same slot
+        let mut ring = IoUring::new(1).unwrap();
+        if !ring.probe().unwrap().is_supported(IORING_OP_WAITID) {
+            return;
+        }
+
+        #[allow(clippy::zombie_processes)]
+        let child = Command::new("true").spawn().unwrap();
+        let mut info: libc::siginfo_t = unsafe { MaybeUninit::zeroed().assume_init() };
+        ring.get_sqe()
+            .unwrap()
+            .prep_waitid(libc::P_PID, child.id(), &mut info, libc::WEXITED)
+            .set_user_data(1);
+        ring.enter_and_wait(1).unwrap();
+        assert_eq!(ring.get_cqes().next().unwrap().result(), 0);
+
+        // The waitid's options share the SQE field a direct accept takes its file index from
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        ring.get_sqe()
+            .unwrap()
+            .prep_accept(listener.as_raw_fd())
+            .set_user_data(2);
+        ring.enter_and_wait(1).unwrap();
 
-impl<'a> SubmissionQueue<'a> {
-    fn new(sq: &'a io_uring_sq) -> Self {
-        Self { inner: sq }
+        let fd = ring.get_cqes().next().unwrap().result();
+        assert!(fd >= 0);
+        let accepted = unsafe { TcpStream::from_raw_fd(fd) };
+        assert_eq!(accepted.peer_addr().unwrap(), client.local_addr().unwrap());
     }
 }
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use rask_liburing_sys::io_uring_napi;
+
+/// NAPI busy polling settings for a ring.
+///
+/// When registered, requests on sockets handled by the ring busy poll the network device's NAPI
+/// context for up to `busy_poll_timeout` before sleeping, trading CPU time for lower receive
+/// latency. Requires Linux 6.9 or later.
+///
+/// See [io_uring_register_napi(3)](https://man.archlinux.org/man/io_uring_register_napi.3)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NapiSettings {
+    /// How long to busy poll for before sleeping. Has microsecond granularity.
+    pub busy_poll_timeout: Duration,
+    /// Prefer busy polling over interrupts, i.e. `SO_PREFER_BUSY_POLL`.
+    pub prefer_busy_poll: bool,
+}
+
+impl NapiSettings {
+    /// Creates settings busy polling for `busy_poll_timeout`.
+    pub fn new(busy_poll_timeout: Duration) -> Self {
+        Self {
+            busy_poll_timeout,
+            prefer_busy_poll: false,
+        }
+    }
+
+    /// Sets whether busy polling is preferred over interrupts.
+    pub fn prefer_busy_poll(mut self, prefer: bool) -> Self {
+        self.prefer_busy_poll = prefer;
+        self
+    }
+
+    pub(crate) fn to_raw(self) -> io_uring_napi {
+        io_uring_napi {
+            busy_poll_to: u32::try_from(self.busy_poll_timeout.as_micros()).unwrap_or(u32::MAX),
+            prefer_busy_poll: self.prefer_busy_poll as u8,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn from_raw(napi: io_uring_napi) -> Self {
+        Self {
+            busy_poll_timeout: Duration::from_micros(napi.busy_poll_to as u64),
+            prefer_busy_poll: napi.prefer_busy_poll != 0,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{fmt, ptr::NonNull};
+
+use rask_liburing_sys::{io_uring_free_probe, io_uring_opcode_supported, io_uring_probe};
+
+/// The set of operations supported by the running kernel, as reported for a ring.
+///
+/// Operations added in newer kernels, such as the futex and waitid requests, should be checked for
+/// before being submitted. Unsupported requests complete with `-EINVAL`.
+///
+/// See [io_uring_get_probe_ring(3)](https://man.archlinux.org/man/io_uring_get_probe_ring.3)
+pub struct Probe {
+    inner: NonNull<io_uring_probe>,
+}
+
+// SAFETY: The probe is allocated for, and only ever accessed through, this `Probe`.
+unsafe impl Send for Probe {}
+
+impl Probe {
+    pub(crate) fn from_raw(probe: NonNull<io_uring_probe>) -> Self {
+        Self { inner: probe }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut io_uring_probe {
+        self.inner.as_ptr()
+    }
+
+    /// The highest opcode known to the kernel.
+    pub fn last_op(&self) -> u32 {
+        // SAFETY: `inner` is valid until dropped
+        unsafe { self.inner.as_ref() }.last_op as u32
+    }
+
+    /// Determines if the kernel supports the given opcode, i.e. `IORING_OP_FUTEX_WAIT`.
+    pub fn is_supported(&self, op: u32) -> bool {
+        // SAFETY: `inner` is valid until dropped
+        io_uring_opcode_supported(unsafe { self.inner.as_ref() }, op as i32) != 0
+    }
+}
+
+impl fmt::Debug for Probe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Probe")
+            .field("last_op", &self.last_op())
+            .finish()
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        unsafe { io_uring_free_probe(self.inner.as_ptr()) }
+    }
+}
@@ -0,0 +1,88 @@
+use std::{mem, ptr};
+
+use libc::msghdr;
+use rask_liburing_sys::io_uring_recvmsg_out;
+
+/// A message received by a multishot recvmsg request, parsed from the buffer it was written to.
+///
+/// The kernel lays out the buffer as an `io_uring_recvmsg_out` header, followed by space for the
+/// source address and control messages sized by the `msghdr` the request was submitted with, and
+/// then the payload.
+///
+/// See [io_uring_recvmsg_out(3)](https://man.archlinux.org/man/io_uring_recvmsg_out.3)
+#[derive(Debug, Clone, Copy)]
+pub struct RecvMsgOut<'a> {
+    header: io_uring_recvmsg_out,
+    name: &'a [u8],
+    control: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> RecvMsgOut<'a> {
+    /// Parses the message in `buf`, which holds the bytes written by the request, as reported by
+    /// its CQE's result. `msg` is the `msghdr` the request was submitted with.
+    ///
+    /// Returns `None` if `buf` is too short to hold the header.
+    pub fn parse(buf: &'a [u8], msg: &msghdr) -> Option<Self> {
+        let header_len = mem::size_of::<io_uring_recvmsg_out>();
+        let name_len = msg.msg_namelen as usize;
+        let control_len = msg.msg_controllen;
+        let payload_start = header_len + name_len + control_len;
+        if buf.len() < payload_start {
+            return None;
+        }
+
+        // SAFETY: `buf` holds at least the header, which may be unaligned
+        let header: io_uring_recvmsg_out = unsafe { ptr::read_unaligned(buf.as_ptr().cast()) };
+
+        let name = &buf[header_len..header_len + name_len.min(header.namelen as usize)];
+        let control_start = header_len + name_len;
+        let control =
+            &buf[control_start..control_start + control_len.min(header.controllen as usize)];
+        let payload = &buf[payload_start..];
+
+        Some(Self {
+            header,
+            name,
+            control,
+            payload,
+        })
+    }
+
+    /// The source address of the message, i.e. a `sockaddr_in6`. Truncated to the `msg_namelen`
+    /// the request was submitted with.
+    pub fn name(&self) -> &'a [u8] {
+        self.name
+    }
+
+    /// Whether the source address was longer than the space given to it.
+    pub fn is_name_truncated(&self) -> bool {
+        self.header.namelen as usize > self.name.len()
+    }
+
+    /// The control messages received with the message, to be walked as a sequence of `cmsghdr`s.
+    pub fn control(&self) -> &'a [u8] {
+        self.control
+    }
+
+    /// Whether control messages were dropped, as there was not enough space for them.
+    pub fn is_control_truncated(&self) -> bool {
+        self.header.flags & libc::MSG_CTRUNC as u32 != 0
+    }
+
+    /// The payload of the message.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Whether the payload was longer than the buffer could hold, and was truncated.
+    pub fn is_payload_truncated(&self) -> bool {
+        self.header.flags & libc::MSG_TRUNC as u32 != 0
+    }
+
+    /// The flags of the received message, as returned in `msg_flags` by
+    /// [recvmsg(2)](https://man.archlinux.org/man/recvmsg.2).
+    pub fn flags(&self) -> u32 {
+        self.header.flags
+    }
+}
@@ -0,0 +1,104 @@
+use std::mem::MaybeUninit;
+
+use rask_liburing_sys::{
+    io_uring_restriction, IORING_RESTRICTION_REGISTER_OP, IORING_RESTRICTION_SQE_FLAGS_ALLOWED,
+    IORING_RESTRICTION_SQE_FLAGS_REQUIRED, IORING_RESTRICTION_SQE_OP,
+};
+
+use crate::SqeFlags;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Restriction {
+    RegisterOp(u8),
+    SqeOp(u8),
+    SqeFlagsAllowed(SqeFlags),
+    SqeFlagsRequired(SqeFlags),
+}
+
+impl Restriction {
+    fn to_raw(self) -> io_uring_restriction {
+        let mut res: io_uring_restriction = unsafe { MaybeUninit::zeroed().assume_init() };
+
+        match self {
+            Restriction::RegisterOp(op) => {
+                res.opcode = IORING_RESTRICTION_REGISTER_OP as u16;
+                res.__bindgen_anon_1.register_op = op;
+            }
+            Restriction::SqeOp(op) => {
+                res.opcode = IORING_RESTRICTION_SQE_OP as u16;
+                res.__bindgen_anon_1.sqe_op = op;
+            }
+            Restriction::SqeFlagsAllowed(flags) => {
+                res.opcode = IORING_RESTRICTION_SQE_FLAGS_ALLOWED as u16;
+                res.__bindgen_anon_1.sqe_flags = flags.bits();
+            }
+            Restriction::SqeFlagsRequired(flags) => {
+                res.opcode = IORING_RESTRICTION_SQE_FLAGS_REQUIRED as u16;
+                res.__bindgen_anon_1.sqe_flags = flags.bits();
+            }
+        }
+
+        res
+    }
+}
+
+/// An allow-list of operations a ring may perform, registered while the ring is disabled.
+///
+/// Once registered, any SQE using an opcode or flags outside of the allow-list completes with
+/// `-EACCES`, and any disallowed `io_uring_register` operation fails with `EACCES`. Restrictions
+/// can only be registered once, and cannot be lifted for the lifetime of the ring.
+///
+/// ```no_run
+/// # use rask_liburing::{IoUring, Restrictions, SqeFlags};
+/// # use rask_liburing_sys::{IORING_OP_ACCEPT, IORING_OP_RECV, IORING_OP_SEND};
+/// let mut restrictions = Restrictions::new();
+/// restrictions
+///     .allow_sqe_op(IORING_OP_ACCEPT)
+///     .allow_sqe_op(IORING_OP_RECV)
+///     .allow_sqe_op(IORING_OP_SEND)
+///     .allow_sqe_flags(SqeFlags::IoLink);
+///
+/// let ring = IoUring::builder().restrictions(restrictions).build(64).unwrap();
+/// ```
+///
+/// See [io_uring_register(2)](https://man.archlinux.org/man/io_uring_register.2)
+#[derive(Debug, Clone, Default)]
+pub struct Restrictions {
+    entries: Vec<Restriction>,
+}
+
+impl Restrictions {
+    /// Creates an empty allow-list, which denies every operation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow SQEs with the given opcode, i.e. `IORING_OP_RECV`.
+    pub fn allow_sqe_op(&mut self, op: u32) -> &mut Self {
+        self.entries.push(Restriction::SqeOp(op as u8));
+        self
+    }
+
+    /// Allow the given `io_uring_register` operation, i.e. `IORING_REGISTER_BUFFERS`.
+    pub fn allow_register_op(&mut self, op: u32) -> &mut Self {
+        self.entries.push(Restriction::RegisterOp(op as u8));
+        self
+    }
+
+    /// Allow SQEs to set the given flags. SQEs setting any other flag are rejected.
+    pub fn allow_sqe_flags(&mut self, flags: SqeFlags) -> &mut Self {
+        self.entries.push(Restriction::SqeFlagsAllowed(flags));
+        self
+    }
+
+    /// Require every SQE to set the given flags, i.e. [`SqeFlags::FixedFile`] to confine requests
+    /// to registered files.
+    pub fn require_sqe_flags(&mut self, flags: SqeFlags) -> &mut Self {
+        self.entries.push(Restriction::SqeFlagsRequired(flags));
+        self
+    }
+
+    pub(crate) fn to_raw(&self) -> Vec<io_uring_restriction> {
+        self.entries.iter().map(|r| r.to_raw()).collect()
+    }
+}
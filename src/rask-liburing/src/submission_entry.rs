@@ -1,10 +1,79 @@
-use std::{error::Error, fmt, os::fd::AsRawFd};
+use std::{
+    error::Error, ffi::CStr, fmt, io::IoSlice, net::Shutdown, os::fd::AsRawFd,
+    sync::atomic::AtomicU32,
+};
 
+use bitflags::bitflags;
+use libc::{
+    id_t, idtype_t, mode_t, msghdr, siginfo_t, sockaddr, sockaddr_storage, socklen_t, statx,
+    timespec,
+};
 use rask_liburing_sys::{
-    io_uring_prep_accept, io_uring_prep_close, io_uring_prep_multishot_accept, io_uring_prep_recv,
-    io_uring_prep_send, io_uring_sqe, io_uring_sqe_set_data64,
+    futex_waitv, io_uring_prep_accept, io_uring_prep_accept_direct, io_uring_prep_cancel64,
+    io_uring_prep_close, io_uring_prep_close_direct, io_uring_prep_connect,
+    io_uring_prep_fallocate, io_uring_prep_fsync, io_uring_prep_futex_wait,
+    io_uring_prep_futex_waitv, io_uring_prep_futex_wake, io_uring_prep_link_timeout,
+    io_uring_prep_multishot_accept, io_uring_prep_multishot_accept_direct, io_uring_prep_nop,
+    io_uring_prep_openat, io_uring_prep_poll_add, io_uring_prep_read, io_uring_prep_recv,
+    io_uring_prep_recvmsg_multishot, io_uring_prep_send, io_uring_prep_sendmsg,
+    io_uring_prep_shutdown, io_uring_prep_socket, io_uring_prep_splice, io_uring_prep_statx,
+    io_uring_prep_tee, io_uring_prep_timeout, io_uring_prep_timeout_remove,
+    io_uring_prep_timeout_update, io_uring_prep_waitid, io_uring_prep_write, io_uring_prep_writev,
+    io_uring_sqe, io_uring_sqe_set_data64, io_uring_sqe_set_flags, iovec, FUTEX2_PRIVATE,
+    FUTEX2_SIZE_U32, FUTEX_BITSET_MATCH_ANY, IORING_ASYNC_CANCEL_ANY, IORING_FILE_INDEX_ALLOC,
+    IORING_FSYNC_DATASYNC, IOSQE_ASYNC_BIT, IOSQE_BUFFER_SELECT_BIT, IOSQE_CQE_SKIP_SUCCESS_BIT,
+    IOSQE_FIXED_FILE_BIT, IOSQE_IO_DRAIN_BIT, IOSQE_IO_HARDLINK_BIT, IOSQE_IO_LINK_BIT,
 };
 
+/// Set in the flags of [`SubmissionEntry::prep_splice`] when its input is a direct descriptor.
+pub use rask_liburing_sys::SPLICE_F_FD_IN_FIXED;
+
+bitflags! {
+    /// Modifies the behavior of a submission queue entry
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct SqeFlags: u8 {
+        /// The file descriptor in the SQE refers to the index of a previously registered file or
+        /// direct file descriptor, not a normal file descriptor.
+        const FixedFile = 1 << IOSQE_FIXED_FILE_BIT;
+        /// The SQE will not be started before previously submitted SQEs have completed, and new
+        /// SQEs will not be started before this one completes.
+        const IoDrain = 1 << IOSQE_IO_DRAIN_BIT;
+        /// The SQE forms a link with the next SQE in the submission ring. That next SQE will not
+        /// be started before this one completes successfully.
+        const IoLink = 1 << IOSQE_IO_LINK_BIT;
+        /// Like `IoLink`, except the link isn't severed if an error or unexpected result occurs.
+        const IoHardlink = 1 << IOSQE_IO_HARDLINK_BIT;
+        /// Issue the SQE asynchronously from the start, rather than attempting it non-blocking
+        /// first.
+        const Async = 1 << IOSQE_ASYNC_BIT;
+        /// Select an IO buffer from the buffer group indicated in the SQE.
+        const BufferSelect = 1 << IOSQE_BUFFER_SELECT_BIT;
+        /// Don't post a CQE if the request completes successfully.
+        const CqeSkipSuccess = 1 << IOSQE_CQE_SKIP_SUCCESS_BIT;
+    }
+}
+
+/// A futex waited on by [`SubmissionEntry::prep_futex_waitv`].
+///
+/// Futexes are 32-bit and private to the process, matching [`SubmissionEntry::prep_futex_wait`].
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct FutexWait(futex_waitv);
+
+impl FutexWait {
+    /// Wait on `futex`, so long as it holds `expected`.
+    ///
+    /// The caller must guarantee `futex` lives until the request waiting on it completes.
+    pub fn new(futex: &AtomicU32, expected: u32) -> Self {
+        Self(futex_waitv {
+            val: expected as u64,
+            uaddr: futex.as_ptr() as u64,
+            flags: FUTEX2_SIZE_U32 | FUTEX2_PRIVATE,
+            __reserved: 0,
+        })
+    }
+}
+
 /// Indicates the SubmissionQueue is full. Either the kernel needs to be notified of new SQEs, or
 /// should be given time to process requests.
 ///
@@ -36,6 +105,27 @@ impl<'a> SubmissionEntry<'a> {
         self
     }
 
+    /// Set flags modifying the behavior of the request. As preparing a request resets the flags,
+    /// this must be called after one of the `prep_*` methods.
+    pub fn set_flags(&mut self, flags: SqeFlags) -> &mut Self {
+        io_uring_sqe_set_flags(self.inner, flags.bits() as u32);
+        self
+    }
+
+    /// Add to the flags modifying the behavior of the request, keeping those already set. Like
+    /// [`SubmissionEntry::set_flags`], this must be called after one of the `prep_*` methods.
+    pub fn add_flags(&mut self, flags: SqeFlags) -> &mut Self {
+        self.inner.flags |= flags.bits();
+        self
+    }
+
+    /// Prepare the entry for a no-op request, which completes immediately without performing any IO.
+    pub fn prep_nop(&mut self) -> &mut Self {
+        io_uring_prep_nop(self.inner);
+
+        self
+    }
+
     /// Prepare the entry for an accept request.
     ///
     /// `fd` should be a file descriptor to a connection-based socket, i.e. [`TcpListener`]
@@ -62,6 +152,107 @@ impl<'a> SubmissionEntry<'a> {
         self
     }
 
+    /// Prepare the entry for a multishot accept request, like
+    /// [`SubmissionEntry::prep_accept_multi`], passing `flags` to
+    /// [accept4(2)](https://man.archlinux.org/man/accept4.2) for each connection, i.e.
+    /// `libc::SOCK_CLOEXEC`.
+    pub fn prep_accept_multi_flags(&mut self, fd: &impl AsRawFd, flags: i32) -> &mut Self {
+        io_uring_prep_multishot_accept(self.inner, fd.as_raw_fd(), None, None, flags as u32);
+
+        self
+    }
+
+    /// Prepare the entry for an accept request, writing the peer's address to `addr`.
+    ///
+    /// `flags` are passed to [accept4(2)](https://man.archlinux.org/man/accept4.2), i.e.
+    /// `libc::SOCK_CLOEXEC`. `addrlen` must be initialized to the size of `addr`, and is updated
+    /// to the size of the peer's address. The caller must guarantee `addr` and `addrlen` live until
+    /// the request completes.
+    pub fn prep_accept_addr(
+        &mut self,
+        fd: impl AsRawFd,
+        addr: &mut sockaddr_storage,
+        addrlen: &mut socklen_t,
+        flags: i32,
+    ) -> &mut Self {
+        let addr = unsafe { &mut *(addr as *mut sockaddr_storage).cast::<sockaddr>() };
+        io_uring_prep_accept(
+            self.inner,
+            fd.as_raw_fd(),
+            Some(addr),
+            Some(addrlen),
+            flags as u32,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for an accept request, installing the connection as a direct descriptor
+    /// in a free slot of the ring's registered file table. The result of the request is the index
+    /// of the slot, which is used with [`SqeFlags::FixedFile`].
+    ///
+    /// Requires a file table registered with [`crate::IoUring::register_files_sparse`]. See
+    /// [`SubmissionEntry::prep_accept_addr`] for `addr` and `addrlen`.
+    ///
+    /// See [io_uring_prep_accept_direct(3)](https://man.archlinux.org/man/io_uring_prep_accept_direct.3)
+    pub fn prep_accept_direct(
+        &mut self,
+        fd: impl AsRawFd,
+        addr: &mut sockaddr_storage,
+        addrlen: &mut socklen_t,
+    ) -> &mut Self {
+        let addr = unsafe { &mut *(addr as *mut sockaddr_storage).cast::<sockaddr>() };
+        io_uring_prep_accept_direct(
+            self.inner,
+            fd.as_raw_fd(),
+            Some(addr),
+            Some(addrlen),
+            0,
+            IORING_FILE_INDEX_ALLOC as u32,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for a multishot accept request, installing each connection as a direct
+    /// descriptor. Combines [`SubmissionEntry::prep_accept_multi`] and
+    /// [`SubmissionEntry::prep_accept_direct`].
+    pub fn prep_accept_multi_direct(&mut self, fd: &impl AsRawFd) -> &mut Self {
+        io_uring_prep_multishot_accept_direct(self.inner, fd.as_raw_fd(), None, None, 0);
+
+        self
+    }
+
+    /// Prepare the entry for a socket request. The result of the request is the new socket's file
+    /// descriptor.
+    ///
+    /// Requires Linux 5.19 or later.
+    ///
+    /// See [socket(2)](https://man.archlinux.org/man/socket.2)
+    pub fn prep_socket(&mut self, domain: i32, ty: i32, protocol: i32) -> &mut Self {
+        io_uring_prep_socket(self.inner, domain, ty, protocol, 0);
+
+        self
+    }
+
+    /// Prepare the entry for a connect request, connecting `fd` to the address in `addr` of size
+    /// `addrlen`.
+    ///
+    /// The caller must guarantee `addr` lives until the request completes.
+    ///
+    /// See [connect(2)](https://man.archlinux.org/man/connect.2)
+    pub fn prep_connect(
+        &mut self,
+        fd: impl AsRawFd,
+        addr: &sockaddr_storage,
+        addrlen: socklen_t,
+    ) -> &mut Self {
+        let addr = unsafe { &*(addr as *const sockaddr_storage).cast::<sockaddr>() };
+        io_uring_prep_connect(self.inner, fd.as_raw_fd(), addr, addrlen);
+
+        self
+    }
+
     /// Prepare the entry for a receive request.
     ///
     /// The caller must guarantee `buffer` lives long enough to be used by the kernel, and when
@@ -74,6 +265,127 @@ impl<'a> SubmissionEntry<'a> {
         self
     }
 
+    /// Prepare the entry for a read request, reading into `buffer` from `fd` at `offset`. An
+    /// offset of `u64::MAX` reads from the file's current position, as for non-seekable files.
+    ///
+    /// The caller must guarantee `buffer` lives long enough to be used by the kernel, and when
+    /// handling the corresponding CQE.
+    ///
+    /// See [read(2)](https://man.archlinux.org/man/read.2)
+    pub fn prep_read(&mut self, fd: impl AsRawFd, buffer: &mut [u8], offset: u64) -> &mut Self {
+        io_uring_prep_read(self.inner, fd.as_raw_fd(), buffer, offset);
+
+        self
+    }
+
+    /// Prepare the entry for a write request, writing `buffer` to `fd` at `offset`. An offset of
+    /// `u64::MAX` writes at the file's current position, as for non-seekable files.
+    ///
+    /// The caller must guarantee `buffer` lives long enough to be used by the kernel.
+    ///
+    /// See [write(2)](https://man.archlinux.org/man/write.2)
+    pub fn prep_write(&mut self, fd: impl AsRawFd, buffer: &[u8], offset: u64) -> &mut Self {
+        io_uring_prep_write(self.inner, fd.as_raw_fd(), buffer, offset);
+
+        self
+    }
+
+    /// Prepare the entry for an openat request, opening `path` relative to the directory `dfd`,
+    /// or the working directory if it is [`libc::AT_FDCWD`]. The opened file descriptor is the
+    /// result of the completion.
+    ///
+    /// The caller must guarantee `path` lives until the request is submitted.
+    ///
+    /// See [openat(2)](https://man.archlinux.org/man/openat.2)
+    pub fn prep_openat(&mut self, dfd: i32, path: &CStr, flags: i32, mode: mode_t) -> &mut Self {
+        io_uring_prep_openat(self.inner, dfd, path, flags, mode);
+
+        self
+    }
+
+    /// Prepare the entry for a statx request, querying the fields in `mask` of `path` relative to
+    /// the directory `dfd`, or of `dfd` itself with an empty path and [`libc::AT_EMPTY_PATH`].
+    ///
+    /// The caller must guarantee `path` lives until the request is submitted, and `buf` until it
+    /// completes.
+    ///
+    /// See [statx(2)](https://man.archlinux.org/man/statx.2)
+    pub fn prep_statx(
+        &mut self,
+        dfd: i32,
+        path: &CStr,
+        flags: i32,
+        mask: u32,
+        buf: &mut statx,
+    ) -> &mut Self {
+        io_uring_prep_statx(self.inner, dfd, path, flags, mask, buf);
+
+        self
+    }
+
+    /// Prepare the entry for an fsync request, flushing `fd`'s data and, unless `data_only`,
+    /// its metadata to the device.
+    ///
+    /// See [fsync(2)](https://man.archlinux.org/man/fsync.2)
+    pub fn prep_fsync(&mut self, fd: impl AsRawFd, data_only: bool) -> &mut Self {
+        let flags = if data_only { IORING_FSYNC_DATASYNC } else { 0 };
+        io_uring_prep_fsync(self.inner, fd.as_raw_fd(), flags);
+
+        self
+    }
+
+    /// Prepare the entry for an fallocate request, manipulating the space allocated to `fd` in
+    /// the range of `len` bytes from `offset` according to `mode`.
+    ///
+    /// See [fallocate(2)](https://man.archlinux.org/man/fallocate.2)
+    pub fn prep_fallocate(
+        &mut self,
+        fd: impl AsRawFd,
+        mode: i32,
+        offset: u64,
+        len: u64,
+    ) -> &mut Self {
+        io_uring_prep_fallocate(self.inner, fd.as_raw_fd(), mode, offset, len);
+
+        self
+    }
+
+    /// Prepare the entry for a multishot recvmsg request, which completes for each message
+    /// received on `fd`, writing it to a buffer selected from the buffer group `group`.
+    ///
+    /// Only the lengths of `msg`'s name and control buffers are used, reserving that much space
+    /// in the selected buffer. Each completion's buffer is parsed with [`crate::RecvMsgOut`]. The
+    /// caller must guarantee `msg` lives until the request is submitted.
+    ///
+    /// Requires a ring of buffers registered with [`crate::IoUring::register_buf_ring`], and
+    /// Linux 6.0 or later.
+    ///
+    /// See [io_uring_prep_recvmsg_multishot(3)](https://man.archlinux.org/man/io_uring_prep_recvmsg_multishot.3)
+    pub fn prep_recvmsg_multishot(
+        &mut self,
+        fd: impl AsRawFd,
+        msg: &mut msghdr,
+        group: u16,
+    ) -> &mut Self {
+        io_uring_prep_recvmsg_multishot(self.inner, fd.as_raw_fd(), msg, 0);
+        self.inner.__bindgen_anon_4.buf_group = group;
+        self.inner.flags |= SqeFlags::BufferSelect.bits();
+
+        self
+    }
+
+    /// Prepare the entry for a sendmsg request, sending the message described by `msg`.
+    ///
+    /// The caller must guarantee `msg`, and the buffers it refers to, live until the request
+    /// completes.
+    ///
+    /// See [sendmsg(2)](https://man.archlinux.org/man/sendmsg.2)
+    pub fn prep_sendmsg(&mut self, fd: impl AsRawFd, msg: &msghdr) -> &mut Self {
+        io_uring_prep_sendmsg(self.inner, fd.as_raw_fd(), msg, 0);
+
+        self
+    }
+
     /// Prepare the entry for a send request.
     ///
     /// The caller must guarantee `buffer` lives long enough to be used by the kernel.
@@ -85,6 +397,97 @@ impl<'a> SubmissionEntry<'a> {
         self
     }
 
+    /// Prepare the entry for a vectored send request, writing `bufs` in order.
+    ///
+    /// The caller must guarantee `bufs`, and the buffers they refer to, live until the request
+    /// completes.
+    ///
+    /// See [writev(2)](https://man.archlinux.org/man/writev.2)
+    pub fn prep_writev(&mut self, fd: impl AsRawFd, bufs: &[IoSlice<'_>]) -> &mut Self {
+        // SAFETY: `IoSlice` is ABI compatible with `iovec`
+        let iovecs =
+            unsafe { std::slice::from_raw_parts(bufs.as_ptr().cast::<iovec>(), bufs.len()) };
+        io_uring_prep_writev(self.inner, fd.as_raw_fd(), iovecs, 0);
+
+        self
+    }
+
+    /// Prepare the entry for a splice request, moving up to `len` bytes from `fd_in` to `fd_out`,
+    /// one of which must be a pipe. The offsets are those of the other file, and `None` for a pipe
+    /// or to use the file's current position. `flags` are those of splice, and
+    /// [`SPLICE_F_FD_IN_FIXED`] if `fd_in` is a direct descriptor. [`SqeFlags::FixedFile`] only
+    /// applies to `fd_out`.
+    ///
+    /// See [splice(2)](https://man.archlinux.org/man/splice.2)
+    pub fn prep_splice(
+        &mut self,
+        fd_in: impl AsRawFd,
+        off_in: Option<u64>,
+        fd_out: impl AsRawFd,
+        off_out: Option<u64>,
+        len: u32,
+        flags: u32,
+    ) -> &mut Self {
+        io_uring_prep_splice(
+            self.inner,
+            fd_in.as_raw_fd(),
+            off_in.map_or(-1, |off| off as i64),
+            fd_out.as_raw_fd(),
+            off_out.map_or(-1, |off| off as i64),
+            len,
+            flags,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for a tee request, duplicating up to `len` bytes from the pipe `fd_in` to
+    /// the pipe `fd_out`, without consuming them from `fd_in`.
+    ///
+    /// See [tee(2)](https://man.archlinux.org/man/tee.2)
+    pub fn prep_tee(
+        &mut self,
+        fd_in: impl AsRawFd,
+        fd_out: impl AsRawFd,
+        len: u32,
+        flags: u32,
+    ) -> &mut Self {
+        io_uring_prep_tee(
+            self.inner,
+            fd_in.as_raw_fd(),
+            fd_out.as_raw_fd(),
+            len,
+            flags,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for a single poll of `fd`, completing with the events of `mask`, i.e.
+    /// [`libc::POLLIN`], once one of them is ready.
+    ///
+    /// See [poll(2)](https://man.archlinux.org/man/poll.2)
+    pub fn prep_poll_add(&mut self, fd: impl AsRawFd, mask: u32) -> &mut Self {
+        io_uring_prep_poll_add(self.inner, fd.as_raw_fd(), mask);
+
+        self
+    }
+
+    /// Prepare the entry for a shutdown request, shutting down the read, write or both halves of
+    /// a connection.
+    ///
+    /// See [shutdown(2)](https://man.archlinux.org/man/shutdown.2)
+    pub fn prep_shutdown(&mut self, fd: impl AsRawFd, how: Shutdown) -> &mut Self {
+        let how = match how {
+            Shutdown::Read => libc::SHUT_RD,
+            Shutdown::Write => libc::SHUT_WR,
+            Shutdown::Both => libc::SHUT_RDWR,
+        };
+        io_uring_prep_shutdown(self.inner, fd.as_raw_fd(), how);
+
+        self
+    }
+
     /// Prepare the entry for a close request.
     ///
     /// See [close(2)](https://man.archlinux.org/man/close.2)
@@ -92,4 +495,184 @@ impl<'a> SubmissionEntry<'a> {
         io_uring_prep_close(self.inner, fd.as_raw_fd());
         self
     }
+
+    /// Prepare the entry for a close request on the direct descriptor at `file_index` of the
+    /// ring's registered file table.
+    ///
+    /// See [io_uring_prep_close_direct(3)](https://man.archlinux.org/man/io_uring_prep_close_direct.3)
+    pub fn prep_close_direct(&mut self, file_index: u32) -> &mut Self {
+        io_uring_prep_close_direct(self.inner, file_index);
+        self
+    }
+
+    /// Prepare the entry for a timeout request, completing with `-ETIME` once `ts` has elapsed.
+    ///
+    /// The caller must guarantee `ts` lives until the request completes.
+    ///
+    /// See [io_uring_prep_timeout(3)](https://man.archlinux.org/man/io_uring_prep_timeout.3)
+    pub fn prep_timeout(&mut self, ts: &mut timespec) -> &mut Self {
+        io_uring_prep_timeout(self.inner, ts, 0, 0);
+
+        self
+    }
+
+    /// Prepare the entry for a timeout on the previous entry, which must set [`SqeFlags::IoLink`].
+    /// If the previous request has not completed once `ts` has elapsed, it is cancelled, and
+    /// completes with `-ECANCELED`.
+    ///
+    /// The caller must guarantee `ts` lives until the request is submitted.
+    ///
+    /// See [io_uring_prep_link_timeout(3)](https://man.archlinux.org/man/io_uring_prep_link_timeout.3)
+    pub fn prep_link_timeout(&mut self, ts: &mut timespec) -> &mut Self {
+        io_uring_prep_link_timeout(self.inner, ts, 0);
+
+        self
+    }
+
+    /// Prepare the entry to update the timeout request whose user_data is `user_data`, re-arming
+    /// it to complete once `ts` has elapsed from now.
+    ///
+    /// The result of the request is 0 if the timeout was updated, or `-ENOENT` if it could not be
+    /// found, i.e. as it has already completed.
+    ///
+    /// The caller must guarantee `ts` lives until the request is submitted.
+    ///
+    /// See [io_uring_prep_timeout_update(3)](https://man.archlinux.org/man/io_uring_prep_timeout_update.3)
+    pub fn prep_timeout_update(&mut self, user_data: u64, ts: &mut timespec) -> &mut Self {
+        io_uring_prep_timeout_update(self.inner, ts, user_data, 0);
+
+        self
+    }
+
+    /// Prepare the entry to remove the timeout request whose user_data is `user_data`, which
+    /// completes with `-ECANCELED`.
+    ///
+    /// See [io_uring_prep_timeout_remove(3)](https://man.archlinux.org/man/io_uring_prep_timeout_remove.3)
+    pub fn prep_timeout_remove(&mut self, user_data: u64) -> &mut Self {
+        io_uring_prep_timeout_remove(self.inner, user_data, 0);
+
+        self
+    }
+
+    /// Prepare the entry to cancel the in-flight request whose user_data is `user_data`.
+    ///
+    /// The result of the request is 0 if the request was found and cancelled, `-ENOENT` if it
+    /// could not be found, or `-EALREADY` if it was already running and may still complete. The
+    /// cancelled request completes with `-ECANCELED`.
+    ///
+    /// See [io_uring_prep_cancel(3)](https://man.archlinux.org/man/io_uring_prep_cancel.3)
+    pub fn prep_cancel(&mut self, user_data: u64) -> &mut Self {
+        io_uring_prep_cancel64(self.inner, user_data, 0);
+
+        self
+    }
+
+    /// Prepare the entry to cancel every in-flight request on the ring, i.e. before it is torn
+    /// down. The result of the request is the number of requests cancelled, or `-ENOENT` if there
+    /// were none.
+    ///
+    /// Requires Linux 5.19 or later.
+    ///
+    /// See [io_uring_prep_cancel(3)](https://man.archlinux.org/man/io_uring_prep_cancel.3)
+    pub fn prep_cancel_any(&mut self) -> &mut Self {
+        io_uring_prep_cancel64(self.inner, 0, IORING_ASYNC_CANCEL_ANY as i32);
+
+        self
+    }
+
+    /// Prepare the entry for a futex wait request, completing once `futex` is woken.
+    ///
+    /// If `futex` does not hold `expected` when the request is issued, it completes with
+    /// `-EAGAIN`. Only wakeups whose bitset intersects `mask` are matched, pass `None` to match
+    /// any. The caller must guarantee `futex` lives until the request completes.
+    ///
+    /// Requires Linux 6.7 or later; check for `IORING_OP_FUTEX_WAIT` with [`crate::Probe`].
+    ///
+    /// See [io_uring_prep_futex_wait(3)](https://man.archlinux.org/man/io_uring_prep_futex_wait.3)
+    pub fn prep_futex_wait(
+        &mut self,
+        futex: &AtomicU32,
+        expected: u32,
+        mask: Option<u32>,
+    ) -> &mut Self {
+        let mask = mask.map_or(FUTEX_BITSET_MATCH_ANY, u64::from);
+        io_uring_prep_futex_wait(
+            self.inner,
+            futex,
+            expected as u64,
+            mask,
+            FUTEX2_SIZE_U32 | FUTEX2_PRIVATE,
+            0,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for a futex wake request, waking at most `count` waiters on `futex`.
+    ///
+    /// Only waiters whose bitset intersects `mask` are woken, pass `None` to wake any. The result
+    /// of the request is the number of waiters woken. Wakes both waiters on the ring and threads
+    /// blocked in [futex(2)](https://man.archlinux.org/man/futex.2).
+    ///
+    /// Requires Linux 6.7 or later; check for `IORING_OP_FUTEX_WAKE` with [`crate::Probe`].
+    ///
+    /// See [io_uring_prep_futex_wake(3)](https://man.archlinux.org/man/io_uring_prep_futex_wake.3)
+    pub fn prep_futex_wake(
+        &mut self,
+        futex: &AtomicU32,
+        count: u32,
+        mask: Option<u32>,
+    ) -> &mut Self {
+        let mask = mask.map_or(FUTEX_BITSET_MATCH_ANY, u64::from);
+        io_uring_prep_futex_wake(
+            self.inner,
+            futex,
+            count as u64,
+            mask,
+            FUTEX2_SIZE_U32 | FUTEX2_PRIVATE,
+            0,
+        );
+
+        self
+    }
+
+    /// Prepare the entry for a vectored futex wait request, completing once any of `futexes` is
+    /// woken. The result of the request is the index of the woken futex.
+    ///
+    /// The caller must guarantee `futexes`, and the futexes they refer to, live until the request
+    /// completes.
+    ///
+    /// Requires Linux 6.7 or later; check for `IORING_OP_FUTEX_WAITV` with [`crate::Probe`].
+    ///
+    /// See [io_uring_prep_futex_waitv(3)](https://man.archlinux.org/man/io_uring_prep_futex_waitv.3)
+    pub fn prep_futex_waitv(&mut self, futexes: &[FutexWait]) -> &mut Self {
+        // SAFETY: `FutexWait` is a transparent wrapper around `futex_waitv`
+        let futexes = unsafe {
+            std::slice::from_raw_parts(futexes.as_ptr().cast::<futex_waitv>(), futexes.len())
+        };
+        io_uring_prep_futex_waitv(self.inner, futexes, 0);
+
+        self
+    }
+
+    /// Prepare the entry for a waitid request, completing once the child identified by `idtype` and
+    /// `id` changes state according to `options`, i.e. `libc::WEXITED`.
+    ///
+    /// Information about the child is written to `infop`. The caller must guarantee `infop` lives
+    /// until the request completes.
+    ///
+    /// Requires Linux 6.7 or later; check for `IORING_OP_WAITID` with [`crate::Probe`].
+    ///
+    /// See [waitid(2)](https://man.archlinux.org/man/waitid.2)
+    pub fn prep_waitid(
+        &mut self,
+        idtype: idtype_t,
+        id: id_t,
+        infop: &mut siginfo_t,
+        options: i32,
+    ) -> &mut Self {
+        io_uring_prep_waitid(self.inner, idtype, id, infop, options, 0);
+
+        self
+    }
 }
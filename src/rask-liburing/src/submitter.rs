@@ -0,0 +1,277 @@
+use std::{
+    io,
+    os::fd::RawFd,
+    ptr,
+    sync::atomic::{self, AtomicU32, Ordering},
+};
+
+use rask_liburing_sys::{
+    io_uring_enter, io_uring_sq, IORING_ENTER_GETEVENTS, IORING_ENTER_SQ_WAIT,
+    IORING_ENTER_SQ_WAKEUP, IORING_SETUP_SQE128, IORING_SETUP_SQPOLL, IORING_SQ_NEED_WAKEUP,
+};
+
+use crate::{SQFullError, SubmissionEntry};
+
+/// Counters describing how submissions reached the kernel, used to gauge whether SQPOLL is paying
+/// off for a workload.
+///
+/// With SQPOLL, `enters` should stay well below `submits`. If `wakeups` tracks `submits` closely,
+/// the poller is idling out between submissions and the ring would be better off without it, or
+/// with a longer idle time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubmitStats {
+    /// Number of submissions which published at least one new SQE.
+    pub submits: u64,
+    /// Number of `io_uring_enter` calls made to submit or wait.
+    pub enters: u64,
+    /// Number of times the SQ poll thread had gone to sleep and was woken with
+    /// `IORING_ENTER_SQ_WAKEUP`.
+    pub wakeups: u64,
+    /// Number of times the SQ was full and the submitter waited on the SQ poll thread with
+    /// `IORING_ENTER_SQ_WAIT`.
+    pub sq_waits: u64,
+}
+
+/// The submission half of an [`IoUring`](crate::IoUring).
+///
+/// A `Submitter` owns the SQ ring: it hands out [`SubmissionEntry`]s and publishes them to the
+/// kernel. It may be moved to another thread, independently of the [`Completer`](crate::Completer)
+/// it was split from, so that one thread prepares requests while another reaps their completions.
+///
+/// Entries handed out by [`Submitter::get_sqe`] are not visible to the kernel until
+/// [`Submitter::submit`] (or [`Submitter::submit_and_wait`]) is called, at which point the SQ tail
+/// is published with `Release` ordering so the kernel observes fully written SQEs.
+///
+/// # SQPOLL
+/// When the ring was created with `IORING_SETUP_SQPOLL`, a kernel thread polls the SQ tail and
+/// picks up new entries on its own. In this mode [`Submitter::submit`] only publishes the tail and
+/// never enters the kernel, unless the poller has gone to sleep and set `IORING_SQ_NEED_WAKEUP`,
+/// in which case `io_uring_enter` is called with `IORING_ENTER_SQ_WAKEUP` to wake it.
+///
+/// As the poller consumes entries asynchronously, the SQ may still be full after submitting. Use
+/// [`Submitter::sq_wait`] to sleep until the poller frees up an entry, rather than spinning on
+/// [`Submitter::get_sqe`].
+///
+/// See [io_uring_enter(2)](https://man.archlinux.org/man/io_uring_enter.2)
+#[derive(Debug)]
+pub struct Submitter<'a> {
+    sq: &'a mut io_uring_sq,
+    stats: &'a mut SubmitStats,
+    ring_fd: RawFd,
+    flags: u32,
+}
+
+/// The submission half of an [`IoUring`](crate::IoUring), under the name it had before
+/// [`IoUring::split`](crate::IoUring::split) was added.
+#[deprecated(note = "Use Submitter instead")]
+pub type SubmissionQueue<'a> = Submitter<'a>;
+
+// SAFETY: The SQ ring is only ever written by a single `Submitter`, which requires `&mut self` for
+// every operation. The kernel-shared head, tail and flags are accessed atomically.
+unsafe impl Send for Submitter<'_> {}
+
+impl<'a> Submitter<'a> {
+    pub(crate) fn new(
+        sq: &'a mut io_uring_sq,
+        stats: &'a mut SubmitStats,
+        ring_fd: RawFd,
+        flags: u32,
+    ) -> Self {
+        Self {
+            sq,
+            stats,
+            ring_fd,
+            flags,
+        }
+    }
+
+    fn khead(&self) -> &AtomicU32 {
+        // SAFETY: khead points into the SQ ring mapped by the kernel, which outlives `self`
+        unsafe { &*self.sq.khead.cast() }
+    }
+
+    fn ktail(&self) -> &AtomicU32 {
+        // SAFETY: ktail points into the SQ ring mapped by the kernel, which outlives `self`
+        unsafe { &*self.sq.ktail.cast() }
+    }
+
+    fn kflags(&self) -> &AtomicU32 {
+        // SAFETY: kflags points into the SQ ring mapped by the kernel, which outlives `self`
+        unsafe { &*self.sq.kflags.cast() }
+    }
+
+    /// Determines if the ring was set up with a kernel submission polling thread.
+    pub fn is_sqpoll(&self) -> bool {
+        self.flags & IORING_SETUP_SQPOLL > 0
+    }
+
+    /// Get the next available submission queue entry.
+    ///
+    /// If the SQ is full, either the kernel needs to be notified of new SQEs, or should be given
+    /// time to process requests.
+    pub fn get_sqe(&mut self) -> Result<SubmissionEntry<'_>, SQFullError> {
+        // The poller thread advances head concurrently, so it must be observed with Acquire to be
+        // sure it is done reading the entry about to be reused.
+        let head = if self.is_sqpoll() {
+            self.khead().load(Ordering::Acquire)
+        } else {
+            self.khead().load(Ordering::Relaxed)
+        };
+
+        let next = self.sq.sqe_tail.wrapping_add(1);
+        if next.wrapping_sub(head) > self.sq.ring_entries {
+            return Err(SQFullError);
+        }
+
+        let shift = if self.flags & IORING_SETUP_SQE128 > 0 {
+            1
+        } else {
+            0
+        };
+        let index = (self.sq.sqe_tail & self.sq.ring_mask) << shift;
+        self.sq.sqe_tail = next;
+
+        // SAFETY: index is masked to the size of the SQE array, and the slot was released by the
+        // kernel as observed through head above.
+        let sqe = unsafe { &mut *self.sq.sqes.add(index as usize) };
+        Ok(SubmissionEntry::new(sqe))
+    }
+
+    /// Gets the number of entries which have been prepared, but not yet submitted to the kernel.
+    pub fn pending(&self) -> u32 {
+        self.sq.sqe_tail.wrapping_sub(self.sq.sqe_head)
+    }
+
+    /// Gets the number of entries published to the SQ ring which the kernel has yet to consume.
+    pub fn in_ring(&self) -> u32 {
+        self.sq
+            .sqe_head
+            .wrapping_sub(self.khead().load(Ordering::Acquire))
+    }
+
+    /// Gets the number of free entries in the SQ ring.
+    pub fn space_left(&self) -> u32 {
+        self.sq.ring_entries - (self.pending() + self.in_ring())
+    }
+
+    /// Get the counters describing how submissions have reached the kernel.
+    pub fn stats(&self) -> SubmitStats {
+        *self.stats
+    }
+
+    /// Determines if the SQ poll thread has gone to sleep, and must be woken to process new
+    /// entries. Always `false` when the ring does not use SQPOLL.
+    pub fn needs_wakeup(&self) -> bool {
+        self.is_sqpoll() && self.kflags().load(Ordering::Relaxed) & IORING_SQ_NEED_WAKEUP > 0
+    }
+
+    /// Sleeps until the SQ poll thread has consumed entries and there is space in the SQ ring.
+    ///
+    /// Returns immediately when there is already space, or when the ring does not use SQPOLL, as
+    /// entries are then only consumed by submitting them.
+    pub fn sq_wait(&mut self) -> io::Result<()> {
+        if !self.is_sqpoll() || self.space_left() > 0 {
+            return Ok(());
+        }
+
+        self.flush();
+        self.enter(0, 0, IORING_ENTER_SQ_WAIT)?;
+        self.stats.sq_waits += 1;
+
+        Ok(())
+    }
+
+    /// Publishes all prepared entries to the kernel by advancing the SQ tail.
+    ///
+    /// Returns the number of entries the kernel has yet to consume.
+    pub(crate) fn flush(&mut self) -> u32 {
+        let tail = self.sq.sqe_tail;
+
+        if self.sq.sqe_head != tail {
+            self.sq.sqe_head = tail;
+            self.stats.submits += 1;
+            // Ensure the kernel sees the SQE contents before it sees the tail update.
+            self.ktail().store(tail, Ordering::Release);
+        }
+
+        // The kernel never writes SQEs, it only advances head once it has consumed them.
+        tail.wrapping_sub(self.khead().load(Ordering::Relaxed))
+    }
+
+    /// Determines if `io_uring_enter` must be called to get `submitted` entries processed, and
+    /// adds the flags required to do so.
+    fn needs_enter(&self, submitted: u32, flags: &mut u32) -> bool {
+        if submitted == 0 {
+            return false;
+        }
+
+        if !self.is_sqpoll() {
+            return true;
+        }
+
+        // Pairs with the barrier the poller issues before setting IORING_SQ_NEED_WAKEUP, so that
+        // either it sees our tail update or we see its flag.
+        atomic::fence(Ordering::SeqCst);
+        if self.kflags().load(Ordering::Relaxed) & IORING_SQ_NEED_WAKEUP > 0 {
+            *flags |= IORING_ENTER_SQ_WAKEUP;
+            return true;
+        }
+
+        false
+    }
+
+    /// Informs the kernel of new SQEs, but waits for no CQEs before continuing.
+    ///
+    /// Returns the number of SQEs that were successfully submitted. For SQPOLL rings this is the
+    /// number of entries handed to the poller thread.
+    ///
+    /// See [io_uring_enter(2)](https://man.archlinux.org/man/io_uring_enter.2)
+    pub fn submit(&mut self) -> io::Result<u32> {
+        self.submit_and_wait(0)
+    }
+
+    /// Informs the kernel of new SQEs, and waits for the given number of CQEs.
+    ///
+    /// Returns the number of SQEs that were successfully submitted. Completions must be retrieved
+    /// through the [`Completer`](crate::Completer) belonging to the same ring.
+    ///
+    /// See [io_uring_enter(2)](https://man.archlinux.org/man/io_uring_enter.2)
+    pub fn submit_and_wait(&mut self, wait_for: u32) -> io::Result<u32> {
+        let submitted = self.flush();
+        let mut flags = 0;
+
+        if !self.needs_enter(submitted, &mut flags) && wait_for == 0 {
+            return Ok(submitted);
+        }
+
+        if wait_for > 0 {
+            flags |= IORING_ENTER_GETEVENTS;
+        }
+
+        let accepted = self.enter(submitted, wait_for, flags)?;
+        if flags & IORING_ENTER_SQ_WAKEUP > 0 {
+            self.stats.wakeups += 1;
+        }
+
+        Ok(accepted)
+    }
+
+    fn enter(&mut self, to_submit: u32, wait_for: u32, flags: u32) -> io::Result<u32> {
+        let res = unsafe {
+            io_uring_enter(
+                self.ring_fd as u32,
+                to_submit,
+                wait_for,
+                flags,
+                ptr::null_mut(),
+            )
+        };
+        self.stats.enters += 1;
+
+        if res < 0 {
+            return Err(io::Error::from_raw_os_error(-res));
+        }
+
+        Ok(res as u32)
+    }
+}